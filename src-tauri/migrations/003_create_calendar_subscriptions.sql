@@ -0,0 +1,14 @@
+-- Calendar subscriptions play a sound ahead of events read from local .ics files
+CREATE TABLE IF NOT EXISTS calendar_subscriptions (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    source_path TEXT NOT NULL,
+    audio_file_path TEXT NOT NULL,
+    lead_minutes INTEGER NOT NULL DEFAULT 5 CHECK (lead_minutes >= 0),
+    volume INTEGER NOT NULL DEFAULT 100 CHECK (volume BETWEEN 0 AND 100),
+    enabled INTEGER NOT NULL DEFAULT 1 CHECK (enabled IN (0, 1)),
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_calendar_subscriptions_enabled ON calendar_subscriptions(enabled);
@@ -0,0 +1,3 @@
+-- Lead warnings played or shown ahead of each schedule, stored as a JSON array
+ALTER TABLE schedules
+    ADD COLUMN pre_alerts TEXT NOT NULL DEFAULT '[]';
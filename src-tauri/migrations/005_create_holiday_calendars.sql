@@ -0,0 +1,14 @@
+-- Named sets of non-business dates that schedules can skip or shift around
+CREATE TABLE IF NOT EXISTS holiday_calendars (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    dates TEXT NOT NULL DEFAULT '[]',
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+ALTER TABLE schedules
+    ADD COLUMN holiday_calendar_id TEXT REFERENCES holiday_calendars(id) ON DELETE SET NULL;
+
+ALTER TABLE schedules
+    ADD COLUMN holiday_rule TEXT NOT NULL DEFAULT 'skip';
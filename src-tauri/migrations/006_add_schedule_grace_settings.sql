@@ -0,0 +1,7 @@
+-- Per-schedule overrides for how late a run may still fire and the minimum
+-- time between runs; NULL falls back to the global settings
+ALTER TABLE schedules
+    ADD COLUMN late_tolerance_seconds INTEGER CHECK (late_tolerance_seconds >= 0);
+
+ALTER TABLE schedules
+    ADD COLUMN min_spacing_seconds INTEGER CHECK (min_spacing_seconds >= 0);
@@ -0,0 +1,4 @@
+-- How a schedule treats wall-clock times repeated or skipped by DST changes,
+-- stored as JSON
+ALTER TABLE schedules
+    ADD COLUMN dst_policy TEXT NOT NULL DEFAULT '{}';
@@ -0,0 +1,9 @@
+-- One row per occurrence that an engine has claimed, so two engines sharing
+-- the database never play the same occurrence twice
+CREATE TABLE IF NOT EXISTS execution_leases (
+    lease_key TEXT PRIMARY KEY,
+    owner TEXT NOT NULL,
+    acquired_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_execution_leases_acquired_at ON execution_leases(acquired_at);
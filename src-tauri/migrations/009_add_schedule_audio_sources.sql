@@ -0,0 +1,12 @@
+-- Where a schedule's audio comes from (its single file or a playlist), stored
+-- as JSON, plus the rotation position for playlists that rotate
+ALTER TABLE schedules
+    ADD COLUMN audio_source TEXT NOT NULL DEFAULT '{"type":"file"}';
+
+ALTER TABLE schedules
+    ADD COLUMN audio_source_position INTEGER NOT NULL DEFAULT 0 CHECK (audio_source_position >= 0);
+
+-- The file that actually played, which differs from the schedule's own file
+-- for playlists
+ALTER TABLE audio_playback_history
+    ADD COLUMN audio_file_path TEXT;
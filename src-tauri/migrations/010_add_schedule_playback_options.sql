@@ -0,0 +1,3 @@
+-- Per-schedule playback settings such as a crescendo ramp, stored as JSON
+ALTER TABLE schedules
+    ADD COLUMN playback TEXT NOT NULL DEFAULT '{}';
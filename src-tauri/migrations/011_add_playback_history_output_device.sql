@@ -0,0 +1,6 @@
+-- Which output device each playback went to, and a note such as why the
+-- schedule's own device was not used
+ALTER TABLE audio_playback_history
+    ADD COLUMN output_device TEXT;
+ALTER TABLE audio_playback_history
+    ADD COLUMN note TEXT;
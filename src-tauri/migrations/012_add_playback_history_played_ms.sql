@@ -0,0 +1,3 @@
+-- How long each playback actually lasted, filled in when it ends
+ALTER TABLE audio_playback_history
+    ADD COLUMN played_ms INTEGER;
@@ -0,0 +1,3 @@
+-- Recurring holidays kept as rules, expanded whenever dates are looked up
+ALTER TABLE holiday_calendars
+    ADD COLUMN recurring TEXT NOT NULL DEFAULT '[]';
@@ -26,14 +26,37 @@ pub enum AudioError {
     NoOutputDevice,
     #[error("failed to initialise audio output stream: {0}")]
     Stream(#[source] rodio::StreamError),
+    #[error("audio output error: {0}")]
+    Output(String),
+    #[error("output device '{0}' was not found")]
+    DeviceNotFound(String),
     #[error("failed to create audio sink: {0}")]
     Sink(#[source] rodio::PlayError),
     #[error("failed to decode audio stream: {0}")]
     Decoder(String),
+    #[error("failed to seek audio stream: {0}")]
+    Seek(String),
     #[error("failed to read audio file: {0}")]
     Io(#[from] std::io::Error),
+    #[error("invalid playback options: {0}")]
+    InvalidOptions(String),
+    #[error("nothing is playing")]
+    NothingPlaying,
+    #[error("no sound with id '{0}' is playing")]
+    VoiceNotFound(String),
     #[error("audio engine is not running")]
     EngineUnavailable,
     #[error(transparent)]
     Validation(#[from] AudioValidationError),
 }
+
+impl AudioError {
+    /// Whether the error means there is no working output to play through,
+    /// as opposed to a problem with the file being played.
+    pub fn is_output_unavailable(&self) -> bool {
+        matches!(
+            self,
+            AudioError::NoOutputDevice | AudioError::Stream(_) | AudioError::Output(_)
+        )
+    }
+}
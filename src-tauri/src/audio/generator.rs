@@ -0,0 +1,638 @@
+use std::f32::consts::TAU;
+use std::fmt;
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::{ChannelCount, Sample, SampleRate, Source};
+use serde::{Deserialize, Serialize};
+
+use crate::audio::error::AudioError;
+use crate::audio::validator::AudioFileMetadata;
+
+/// Rate sounds are generated at; the output resamples as needed.
+pub const GENERATED_SAMPLE_RATE: SampleRate = 44_100;
+/// Peak level of a generated sound, leaving headroom at full volume.
+const PEAK: f32 = 0.5;
+/// Rise and fall at each edge of a tone, so it starts and stops without a
+/// click.
+const EDGE_SECONDS: f64 = 0.005;
+
+const MIN_FREQUENCY_HZ: f32 = 20.0;
+const MAX_FREQUENCY_HZ: f32 = 20_000.0;
+/// Most beeps or bell strikes in one sound; repeat the sound for more.
+const MAX_COUNT: u32 = 100;
+const MAX_CHIME_NOTES: usize = 16;
+const MAX_SPACING_MS: u32 = 60_000;
+/// Longest a generated sound may last in total.
+const MAX_LENGTH_MS: u64 = 10 * 60 * 1000;
+
+/// Partials of a struck bell as (multiple of the fundamental, amplitude).
+/// The inharmonic ratios are what make it sound like a bell rather than a
+/// plain tone.
+const BELL_PARTIALS: [(f32, f32); 5] = [
+    (1.0, 1.0),
+    (2.0, 0.6),
+    (2.76, 0.4),
+    (5.4, 0.25),
+    (8.93, 0.15),
+];
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Waveform {
+    #[default]
+    Sine,
+    /// Harsher and easier to hear over background noise.
+    Square,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseColor {
+    /// Equal energy at every frequency; a hiss.
+    #[default]
+    White,
+    /// Falling 3 dB per octave; like steady rain.
+    Pink,
+    /// Falling 6 dB per octave; a low rumble like surf.
+    Brown,
+}
+
+/// A sound synthesized when it plays instead of read from a file, so it is
+/// always available.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GeneratedSound {
+    /// `count` short tones with `spacing_ms` of silence between them.
+    Beeps {
+        frequency_hz: f32,
+        count: u32,
+        duration_ms: u32,
+        spacing_ms: u32,
+        #[serde(default)]
+        waveform: Waveform,
+    },
+    /// One steady tone.
+    Tone {
+        frequency_hz: f32,
+        duration_ms: u32,
+        #[serde(default)]
+        waveform: Waveform,
+    },
+    /// Notes struck in turn, `spacing_ms` apart, each ringing out over
+    /// `duration_ms`, like a doorbell.
+    Chime {
+        frequencies_hz: Vec<f32>,
+        duration_ms: u32,
+        spacing_ms: u32,
+    },
+    /// `count` strikes of a bell, `spacing_ms` apart, each ringing out over
+    /// `duration_ms`.
+    Bell {
+        frequency_hz: f32,
+        count: u32,
+        duration_ms: u32,
+        spacing_ms: u32,
+    },
+    Noise {
+        #[serde(default)]
+        color: NoiseColor,
+        duration_ms: u32,
+    },
+}
+
+impl GeneratedSound {
+    pub fn validate(&self) -> Result<(), AudioError> {
+        let frequencies = match self {
+            Self::Beeps { frequency_hz, .. }
+            | Self::Tone { frequency_hz, .. }
+            | Self::Bell { frequency_hz, .. } => vec![*frequency_hz],
+            Self::Chime { frequencies_hz, .. } => frequencies_hz.clone(),
+            Self::Noise { .. } => Vec::new(),
+        };
+        if frequencies
+            .iter()
+            .any(|frequency| !(MIN_FREQUENCY_HZ..=MAX_FREQUENCY_HZ).contains(frequency))
+        {
+            return Err(AudioError::InvalidOptions(format!(
+                "frequencies must be between {MIN_FREQUENCY_HZ} and {MAX_FREQUENCY_HZ} Hz"
+            )));
+        }
+
+        match self {
+            Self::Beeps { count, .. } | Self::Bell { count, .. }
+                if *count == 0 || *count > MAX_COUNT =>
+            {
+                return Err(AudioError::InvalidOptions(format!(
+                    "count must be between 1 and {MAX_COUNT}"
+                )));
+            }
+            Self::Chime { frequencies_hz, .. }
+                if frequencies_hz.is_empty() || frequencies_hz.len() > MAX_CHIME_NOTES =>
+            {
+                return Err(AudioError::InvalidOptions(format!(
+                    "a chime must have between 1 and {MAX_CHIME_NOTES} notes"
+                )));
+            }
+            _ => {}
+        }
+
+        if self.duration_ms() == 0 {
+            return Err(AudioError::InvalidOptions(
+                "duration must be at least 1 ms".into(),
+            ));
+        }
+        if self.spacing_ms() > MAX_SPACING_MS {
+            return Err(AudioError::InvalidOptions(format!(
+                "spacing must be at most {} seconds",
+                MAX_SPACING_MS / 1000
+            )));
+        }
+        if self.length() > Duration::from_millis(MAX_LENGTH_MS) {
+            return Err(AudioError::InvalidOptions(format!(
+                "a generated sound must last at most {} minutes",
+                MAX_LENGTH_MS / 60_000
+            )));
+        }
+        Ok(())
+    }
+
+    /// How long the sound lasts from start to finish.
+    pub fn length(&self) -> Duration {
+        let (duration, spacing) = (self.duration_ms() as u64, self.spacing_ms() as u64);
+        let ms = match self {
+            Self::Beeps { count, .. } => {
+                let count = *count as u64;
+                count * duration + count.saturating_sub(1) * spacing
+            }
+            Self::Bell { count, .. } => (*count as u64).saturating_sub(1) * spacing + duration,
+            Self::Chime { frequencies_hz, .. } => {
+                (frequencies_hz.len() as u64).saturating_sub(1) * spacing + duration
+            }
+            Self::Tone { .. } | Self::Noise { .. } => duration,
+        };
+        Duration::from_millis(ms)
+    }
+
+    /// Stands in for a file's metadata while the sound plays.
+    pub fn metadata(&self) -> AudioFileMetadata {
+        let name = self.to_string();
+        AudioFileMetadata {
+            path: name.clone(),
+            file_name: name,
+            extension: String::new(),
+            duration_ms: Some(self.length().as_millis() as u64),
+            sample_rate: GENERATED_SAMPLE_RATE,
+            channels: 1,
+            size_bytes: 0,
+            loudness: None,
+        }
+    }
+
+    pub fn source(&self) -> GeneratedSource {
+        GeneratedSource::new(self.clone())
+    }
+
+    fn duration_ms(&self) -> u32 {
+        match self {
+            Self::Beeps { duration_ms, .. }
+            | Self::Tone { duration_ms, .. }
+            | Self::Chime { duration_ms, .. }
+            | Self::Bell { duration_ms, .. }
+            | Self::Noise { duration_ms, .. } => *duration_ms,
+        }
+    }
+
+    fn spacing_ms(&self) -> u32 {
+        match self {
+            Self::Beeps { spacing_ms, .. }
+            | Self::Chime { spacing_ms, .. }
+            | Self::Bell { spacing_ms, .. } => *spacing_ms,
+            Self::Tone { .. } | Self::Noise { .. } => 0,
+        }
+    }
+}
+
+/// A short description, recorded in the playback history in place of a
+/// file path.
+impl fmt::Display for GeneratedSound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Beeps {
+                frequency_hz,
+                count,
+                ..
+            } => write!(f, "{count} beeps at {frequency_hz} Hz"),
+            Self::Tone {
+                frequency_hz,
+                waveform,
+                ..
+            } => {
+                let waveform = match waveform {
+                    Waveform::Sine => "sine",
+                    Waveform::Square => "square",
+                };
+                write!(f, "{frequency_hz} Hz {waveform} tone")
+            }
+            Self::Chime { frequencies_hz, .. } => {
+                write!(f, "{}-note chime", frequencies_hz.len())
+            }
+            Self::Bell {
+                frequency_hz,
+                count,
+                ..
+            } => write!(f, "{count} bell strikes at {frequency_hz} Hz"),
+            Self::Noise { color, .. } => {
+                let color = match color {
+                    NoiseColor::White => "white",
+                    NoiseColor::Pink => "pink",
+                    NoiseColor::Brown => "brown",
+                };
+                write!(f, "{color} noise")
+            }
+        }
+    }
+}
+
+/// Filter state for coloured noise.
+struct Noise {
+    rng: fastrand::Rng,
+    state: [f32; 3],
+}
+
+impl Noise {
+    fn next(&mut self, color: NoiseColor) -> f32 {
+        let white = self.rng.f32() * 2.0 - 1.0;
+        let sample = match color {
+            NoiseColor::White => white,
+            NoiseColor::Pink => {
+                // Paul Kellet's economy pink filter.
+                let [b0, b1, b2] = &mut self.state;
+                *b0 = 0.99765 * *b0 + white * 0.0990460;
+                *b1 = 0.96300 * *b1 + white * 0.2965164;
+                *b2 = 0.57000 * *b2 + white * 1.0526913;
+                (*b0 + *b1 + *b2 + white * 0.1848) * 0.25
+            }
+            NoiseColor::Brown => {
+                // Leaky integration of white noise.
+                let level = &mut self.state[0];
+                *level = (*level + 0.02 * white) / 1.02;
+                *level * 3.5
+            }
+        };
+        sample.clamp(-1.0, 1.0)
+    }
+}
+
+/// Plays a [`GeneratedSound`], working out each sample as it is needed.
+pub struct GeneratedSource {
+    sound: GeneratedSound,
+    frame: u64,
+    frames: u64,
+    /// The partials a bell strike rings with, below the Nyquist frequency.
+    partials: Vec<(f32, f32)>,
+    /// Gain applied to the summed strikes so they peak at [`PEAK`].
+    scale: f32,
+    noise: Noise,
+}
+
+impl GeneratedSource {
+    fn new(sound: GeneratedSound) -> Self {
+        let frames = seconds_to_frames(sound.length().as_secs_f64());
+        let (partials, scale) = match &sound {
+            GeneratedSound::Chime {
+                frequencies_hz,
+                duration_ms,
+                spacing_ms,
+            } => {
+                let overlapping = overlapping(frequencies_hz.len(), *duration_ms, *spacing_ms);
+                (Vec::new(), PEAK / overlapping)
+            }
+            GeneratedSound::Bell {
+                frequency_hz,
+                count,
+                duration_ms,
+                spacing_ms,
+            } => {
+                let overlapping = overlapping(*count as usize, *duration_ms, *spacing_ms);
+                let nyquist = GENERATED_SAMPLE_RATE as f32 / 2.0;
+                let partials = BELL_PARTIALS
+                    .iter()
+                    .map(|(ratio, amplitude)| (frequency_hz * ratio, *amplitude))
+                    .filter(|(frequency, _)| *frequency < nyquist)
+                    .collect();
+                let total: f32 = BELL_PARTIALS.iter().map(|(_, amplitude)| amplitude).sum();
+                (partials, PEAK / (total * overlapping))
+            }
+            _ => (Vec::new(), PEAK),
+        };
+        Self {
+            sound,
+            frame: 0,
+            frames,
+            partials,
+            scale,
+            noise: Noise {
+                rng: fastrand::Rng::with_seed(0),
+                state: [0.0; 3],
+            },
+        }
+    }
+
+    fn sample(&mut self) -> f32 {
+        let frame = self.frame;
+        match &self.sound {
+            GeneratedSound::Beeps {
+                frequency_hz,
+                duration_ms,
+                spacing_ms,
+                waveform,
+                ..
+            } => {
+                let length = ms_to_frames(*duration_ms);
+                let position = frame % (length + ms_to_frames(*spacing_ms)).max(1);
+                if position < length {
+                    PEAK * edges(position, length) * wave(*waveform, *frequency_hz, frame)
+                } else {
+                    0.0
+                }
+            }
+            GeneratedSound::Tone {
+                frequency_hz,
+                waveform,
+                ..
+            } => PEAK * edges(frame, self.frames) * wave(*waveform, *frequency_hz, frame),
+            GeneratedSound::Chime {
+                frequencies_hz,
+                duration_ms,
+                spacing_ms,
+            } => {
+                let sum: f32 = frequencies_hz
+                    .iter()
+                    .enumerate()
+                    .map(|(note, frequency)| {
+                        let onset = note as u64 * ms_to_frames(*spacing_ms);
+                        struck(
+                            frame,
+                            onset,
+                            ms_to_frames(*duration_ms),
+                            &[(*frequency, 1.0)],
+                        )
+                    })
+                    .sum();
+                self.scale * sum
+            }
+            GeneratedSound::Bell {
+                count,
+                duration_ms,
+                spacing_ms,
+                ..
+            } => {
+                let sum: f32 = (0..*count as u64)
+                    .map(|strike| {
+                        let onset = strike * ms_to_frames(*spacing_ms);
+                        struck(frame, onset, ms_to_frames(*duration_ms), &self.partials)
+                    })
+                    .sum();
+                self.scale * sum
+            }
+            GeneratedSound::Noise { color, .. } => {
+                let color = *color;
+                PEAK * edges(frame, self.frames) * self.noise.next(color)
+            }
+        }
+    }
+}
+
+impl Iterator for GeneratedSource {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.frame >= self.frames {
+            return None;
+        }
+        let sample = self.sample();
+        self.frame += 1;
+        Some(sample)
+    }
+}
+
+impl Source for GeneratedSource {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        1
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        GENERATED_SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.sound.length())
+    }
+
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        self.frame = seconds_to_frames(position.as_secs_f64()).min(self.frames);
+        Ok(())
+    }
+}
+
+fn seconds_to_frames(seconds: f64) -> u64 {
+    (seconds * GENERATED_SAMPLE_RATE as f64) as u64
+}
+
+fn ms_to_frames(ms: u32) -> u64 {
+    ms as u64 * GENERATED_SAMPLE_RATE as u64 / 1000
+}
+
+/// How far through its cycle a wave at `frequency` is at `frame`, as a
+/// fraction. Worked out in `f64`, as an `f32` time has too little precision
+/// left minutes into a sound.
+fn phase(frequency: f32, frame: u64) -> f32 {
+    (frame as f64 * frequency as f64 / GENERATED_SAMPLE_RATE as f64).fract() as f32
+}
+
+fn wave(waveform: Waveform, frequency: f32, frame: u64) -> f32 {
+    let phase = phase(frequency, frame);
+    match waveform {
+        Waveform::Sine => (TAU * phase).sin(),
+        Waveform::Square if phase < 0.5 => 1.0,
+        Waveform::Square => -1.0,
+    }
+}
+
+/// Gain at `position` of a tone `length` frames long, rising and falling
+/// briefly at either end.
+fn edges(position: u64, length: u64) -> f32 {
+    let edge = seconds_to_frames(EDGE_SECONDS).min(length / 2).max(1) as f32;
+    let from_end = length.saturating_sub(position + 1) as f32;
+    (position as f32 / edge).min(from_end / edge).min(1.0)
+}
+
+/// Partials struck at `onset`, ringing out over `length` frames.
+fn struck(frame: u64, onset: u64, length: u64, partials: &[(f32, f32)]) -> f32 {
+    if frame < onset || frame >= onset + length {
+        return 0.0;
+    }
+    let position = frame - onset;
+    let t = position as f32 / GENERATED_SAMPLE_RATE as f32;
+    let ring = length as f32 / GENERATED_SAMPLE_RATE as f32;
+    let fundamental = partials.first().map_or(1.0, |(frequency, _)| *frequency);
+    let sum: f32 = partials
+        .iter()
+        .map(|(frequency, amplitude)| {
+            // The fundamental dies away to about -40 dB by the end of the
+            // ring; higher partials fade sooner.
+            let decay = (-4.6 * t / ring * (frequency / fundamental).sqrt()).exp();
+            amplitude * decay * (TAU * phase(*frequency, position)).sin()
+        })
+        .sum();
+    sum * edges(position, length)
+}
+
+/// Most strikes ringing at once, to scale them so they never clip.
+fn overlapping(strikes: usize, duration_ms: u32, spacing_ms: u32) -> f32 {
+    if spacing_ms == 0 {
+        return strikes.max(1) as f32;
+    }
+    (duration_ms.div_ceil(spacing_ms) as usize).clamp(1, strikes.max(1)) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(ms: u64) -> usize {
+        (ms * GENERATED_SAMPLE_RATE as u64 / 1000) as usize
+    }
+
+    #[test]
+    fn beeps_are_separated_by_silence() {
+        let beeps: GeneratedSound = serde_json::from_str(
+            r#"{"kind":"beeps","frequency_hz":880,"count":3,"duration_ms":100,"spacing_ms":50}"#,
+        )
+        .unwrap();
+        assert_eq!(beeps.length(), Duration::from_millis(400));
+        assert_eq!(beeps.to_string(), "3 beeps at 880 Hz");
+
+        let samples: Vec<f32> = beeps.source().collect();
+        assert_eq!(samples.len(), frames(400));
+        assert!(samples[frames(100) + 10..frames(150)]
+            .iter()
+            .all(|sample| *sample == 0.0));
+        assert!(samples[frames(150)..frames(250)]
+            .iter()
+            .any(|sample| sample.abs() > 0.4));
+        assert!(samples.iter().all(|sample| sample.abs() <= PEAK));
+    }
+
+    #[test]
+    fn overlapping_strikes_never_clip() {
+        let bell = GeneratedSound::Bell {
+            frequency_hz: 440.0,
+            count: 4,
+            duration_ms: 2000,
+            spacing_ms: 300,
+        };
+        let chime = GeneratedSound::Chime {
+            frequencies_hz: vec![659.25, 523.25, 587.33, 392.0],
+            duration_ms: 1500,
+            spacing_ms: 400,
+        };
+        for sound in [bell, chime] {
+            let samples: Vec<f32> = sound.source().collect();
+            assert_eq!(samples.len(), frames(sound.length().as_millis() as u64));
+            assert!(
+                samples.iter().all(|sample| sample.abs() <= PEAK),
+                "{sound} clips"
+            );
+            assert!(
+                samples.iter().any(|sample| sample.abs() > 0.1),
+                "{sound} is silent"
+            );
+        }
+    }
+
+    #[test]
+    fn long_tones_stay_in_tune() {
+        let frequency = 1000.3;
+        let tone = GeneratedSound::Tone {
+            frequency_hz: frequency,
+            duration_ms: 10 * 60 * 1000,
+            waveform: Waveform::Sine,
+        };
+        let mut source = tone.source();
+        source.try_seek(Duration::from_secs(599)).unwrap();
+
+        let start = 599 * GENERATED_SAMPLE_RATE as u64;
+        for (offset, sample) in source.take(1000).enumerate() {
+            let t = (start + offset as u64) as f64 / GENERATED_SAMPLE_RATE as f64;
+            let ideal = PEAK as f64 * (std::f64::consts::TAU * frequency as f64 * t).sin();
+            assert!(
+                (sample as f64 - ideal).abs() < 1e-3,
+                "{sample} instead of {ideal} at {t}s"
+            );
+        }
+    }
+
+    #[test]
+    fn darker_noise_changes_more_slowly() {
+        let roughness = |color| {
+            let samples: Vec<f32> = GeneratedSound::Noise {
+                color,
+                duration_ms: 500,
+            }
+            .source()
+            .collect();
+            assert!(samples.iter().all(|sample| sample.abs() <= PEAK));
+            samples
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0]).abs())
+                .sum::<f32>()
+        };
+
+        let (white, pink, brown) = (
+            roughness(NoiseColor::White),
+            roughness(NoiseColor::Pink),
+            roughness(NoiseColor::Brown),
+        );
+        assert!(white > pink && pink > brown, "{white} {pink} {brown}");
+    }
+
+    #[test]
+    fn rejects_unplayable_parameters() {
+        let tone = |frequency_hz| GeneratedSound::Tone {
+            frequency_hz,
+            duration_ms: 500,
+            waveform: Waveform::Square,
+        };
+        assert!(tone(440.0).validate().is_ok());
+        assert!(tone(5.0).validate().is_err());
+        assert!(tone(f32::NAN).validate().is_err());
+
+        let no_beeps = GeneratedSound::Beeps {
+            frequency_hz: 440.0,
+            count: 0,
+            duration_ms: 100,
+            spacing_ms: 100,
+            waveform: Waveform::Sine,
+        };
+        assert!(no_beeps.validate().is_err());
+
+        let silent_chime = GeneratedSound::Chime {
+            frequencies_hz: Vec::new(),
+            duration_ms: 500,
+            spacing_ms: 100,
+        };
+        assert!(silent_chime.validate().is_err());
+
+        let endless = GeneratedSound::Noise {
+            color: NoiseColor::Pink,
+            duration_ms: 11 * 60 * 1000,
+        };
+        assert!(endless.validate().is_err());
+    }
+}
@@ -0,0 +1,522 @@
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use rodio::Source;
+use serde::Serialize;
+
+use crate::audio::error::AudioError;
+use crate::audio::generator::GeneratedSound;
+use crate::audio::player::AudioPlayer;
+
+/// Loudness normalized files are brought to.
+pub const NORMALIZATION_TARGET_LUFS: f64 = -18.0;
+/// Normalization never lifts a file's true peak above this.
+const PEAK_CEILING_DBTP: f64 = -1.0;
+/// Most a quiet file is boosted, so near-silent recordings do not turn into
+/// amplified hiss.
+const MAX_BOOST_DB: f64 = 12.0;
+
+/// Blocks quieter than this are left out of the integrated loudness.
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+/// Blocks more than this far below the ungated loudness are left out too.
+const RELATIVE_GATE_LU: f64 = 10.0;
+/// Measurement blocks are 400 ms long and overlap by 75%, so they are built
+/// from four consecutive 100 ms steps.
+const STEPS_PER_BLOCK: usize = 4;
+const STEPS_PER_SECOND: u32 = 10;
+
+/// Taps per phase of the true-peak interpolation filter.
+const PEAK_FILTER_TAPS: usize = 12;
+
+/// How loud a file is, measured per EBU R128.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Loudness {
+    /// Integrated loudness in LUFS; `None` if the file is silent throughout.
+    pub integrated_lufs: Option<f64>,
+    /// Highest true (inter-sample) peak in dBTP.
+    pub true_peak_dbtp: f64,
+}
+
+impl Loudness {
+    /// Gain that brings the file to [`NORMALIZATION_TARGET_LUFS`], held back
+    /// so its peaks stay below the ceiling. Silent files are left alone.
+    pub fn normalization_gain(&self) -> f32 {
+        let Some(integrated) = self.integrated_lufs else {
+            return 1.0;
+        };
+        let gain_db = (NORMALIZATION_TARGET_LUFS - integrated)
+            .min(PEAK_CEILING_DBTP - self.true_peak_dbtp)
+            .min(MAX_BOOST_DB);
+        10f64.powf(gain_db / 20.0) as f32
+    }
+}
+
+/// Decodes the whole file and measures it.
+pub fn analyze_file(path: &Path) -> Result<Loudness, AudioError> {
+    Ok(measure(AudioPlayer::create_source(path, None)?))
+}
+
+/// Measures `source` to its end.
+pub fn measure<S: Source>(source: S) -> Loudness {
+    let channels = source.channels().max(1) as usize;
+    let sample_rate = source.sample_rate().max(1);
+    let mut meter = LoudnessMeter::new(channels, sample_rate);
+    let mut peak = TruePeak::new(channels, sample_rate);
+
+    let mut channel = 0;
+    for sample in source {
+        meter.push(channel, sample);
+        peak.push(channel, sample);
+        channel = (channel + 1) % channels;
+    }
+
+    Loudness {
+        integrated_lufs: meter.integrated(),
+        true_peak_dbtp: 20.0 * (peak.max as f64).max(f64::MIN_POSITIVE).log10(),
+    }
+}
+
+/// A second-order IIR filter section.
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b,
+            a,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z1;
+        self.z1 = self.b[1] * x - self.a[1] * y + self.z2;
+        self.z2 = self.b[2] * x - self.a[2] * y;
+        y
+    }
+}
+
+/// The BS.1770 K-weighting filter: a high-shelf modelling the head followed
+/// by a high-pass, worked out for any sample rate.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    [shelf, high_pass]
+}
+
+/// Weight of each channel in the loudness sum. Only 5.1 layouts are told
+/// apart: the LFE is ignored and the surrounds count for more.
+fn channel_weights(channels: usize) -> Vec<f64> {
+    if channels == 6 {
+        vec![1.0, 1.0, 1.0, 0.0, 1.41, 1.41]
+    } else {
+        vec![1.0; channels]
+    }
+}
+
+/// Gated integrated loudness, built from the K-weighted energy of each
+/// 100 ms step.
+struct LoudnessMeter {
+    filters: Vec<[Biquad; 2]>,
+    weights: Vec<f64>,
+    step_frames: u64,
+    /// Weighted energy of the step in progress and how far it has got.
+    energy: f64,
+    frames: u64,
+    /// Mean weighted energy of each completed step.
+    steps: Vec<f64>,
+}
+
+impl LoudnessMeter {
+    fn new(channels: usize, sample_rate: u32) -> Self {
+        Self {
+            filters: vec![k_weighting(sample_rate); channels],
+            weights: channel_weights(channels),
+            step_frames: (sample_rate / STEPS_PER_SECOND).max(1) as u64,
+            energy: 0.0,
+            frames: 0,
+            steps: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, channel: usize, sample: f32) {
+        let [shelf, high_pass] = &mut self.filters[channel];
+        let weighted = high_pass.process(shelf.process(sample as f64));
+        self.energy += self.weights[channel] * weighted * weighted;
+
+        if channel == self.filters.len() - 1 {
+            self.frames += 1;
+            if self.frames == self.step_frames {
+                self.steps.push(self.energy / self.frames as f64);
+                self.energy = 0.0;
+                self.frames = 0;
+            }
+        }
+    }
+
+    /// Energy of each 400 ms block. A file shorter than one block is
+    /// measured as a single block of whatever it has.
+    fn blocks(&self) -> Vec<f64> {
+        if self.steps.len() < STEPS_PER_BLOCK {
+            let frames = self.steps.len() as u64 * self.step_frames + self.frames;
+            if frames == 0 {
+                return Vec::new();
+            }
+            let energy = self.steps.iter().sum::<f64>() * self.step_frames as f64 + self.energy;
+            return vec![energy / frames as f64];
+        }
+        self.steps
+            .windows(STEPS_PER_BLOCK)
+            .map(|window| window.iter().sum::<f64>() / STEPS_PER_BLOCK as f64)
+            .collect()
+    }
+
+    fn integrated(&self) -> Option<f64> {
+        let absolute: Vec<f64> = self
+            .blocks()
+            .into_iter()
+            .filter(|energy| energy_to_lufs(*energy) > ABSOLUTE_GATE_LUFS)
+            .collect();
+        if absolute.is_empty() {
+            return None;
+        }
+
+        let relative_gate = energy_to_lufs(mean(&absolute)) - RELATIVE_GATE_LU;
+        let gated: Vec<f64> = absolute
+            .into_iter()
+            .filter(|energy| energy_to_lufs(*energy) > relative_gate)
+            .collect();
+        Some(energy_to_lufs(mean(&gated)))
+    }
+}
+
+fn energy_to_lufs(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.max(f64::MIN_POSITIVE).log10()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Highest peak between samples as well as on them, found by oversampling
+/// with a windowed-sinc interpolator.
+struct TruePeak {
+    /// Interpolation taps for each oversampled phase.
+    phases: Vec<Vec<f32>>,
+    /// Most recent samples of each channel, newest first.
+    history: Vec<[f32; PEAK_FILTER_TAPS]>,
+    max: f32,
+}
+
+impl TruePeak {
+    fn new(channels: usize, sample_rate: u32) -> Self {
+        let factor = match sample_rate {
+            0..96_000 => 4,
+            96_000..192_000 => 2,
+            _ => 1,
+        };
+        let phases = if factor == 1 {
+            Vec::new()
+        } else {
+            Self::interpolation_phases(factor)
+        };
+        Self {
+            phases,
+            history: vec![[0.0; PEAK_FILTER_TAPS]; channels],
+            max: 0.0,
+        }
+    }
+
+    fn interpolation_phases(factor: usize) -> Vec<Vec<f32>> {
+        let length = PEAK_FILTER_TAPS * factor;
+        let centre = (length - 1) as f64 / 2.0;
+        let taps: Vec<f64> = (0..length)
+            .map(|index| {
+                let t = (index as f64 - centre) / factor as f64;
+                let sinc = if t == 0.0 {
+                    1.0
+                } else {
+                    (PI * t).sin() / (PI * t)
+                };
+                let window =
+                    0.5 - 0.5 * (2.0 * PI * (index + 1) as f64 / (length + 1) as f64).cos();
+                sinc * window
+            })
+            .collect();
+
+        (0..factor)
+            .map(|phase| {
+                let phase_taps: Vec<f64> =
+                    taps.iter().skip(phase).step_by(factor).copied().collect();
+                let sum: f64 = phase_taps.iter().sum();
+                phase_taps.iter().map(|tap| (tap / sum) as f32).collect()
+            })
+            .collect()
+    }
+
+    fn push(&mut self, channel: usize, sample: f32) {
+        self.max = self.max.max(sample.abs());
+        if self.phases.is_empty() {
+            return;
+        }
+
+        let history = &mut self.history[channel];
+        history.copy_within(..PEAK_FILTER_TAPS - 1, 1);
+        history[0] = sample;
+        for taps in &self.phases {
+            let interpolated: f32 = taps
+                .iter()
+                .zip(history.iter())
+                .map(|(tap, x)| tap * x)
+                .sum();
+            self.max = self.max.max(interpolated.abs());
+        }
+    }
+}
+
+/// When a file was last measured, to tell if it has changed since.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FileStamp {
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Result<Self, AudioError> {
+        let metadata = fs::metadata(path)?;
+        Ok(Self {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// Measurements by file, kept until the file changes so each is decoded
+/// for analysis only once, and by generated sound, keyed by its settings.
+#[derive(Default)]
+pub(crate) struct LoudnessCache {
+    entries: Mutex<HashMap<PathBuf, (FileStamp, Loudness)>>,
+    generated: Mutex<HashMap<String, Loudness>>,
+    /// Files being measured in the background.
+    pending: Mutex<HashSet<PathBuf>>,
+}
+
+impl LoudnessCache {
+    /// The file's loudness if it has been measured since it last changed.
+    pub(crate) fn measured(&self, path: &Path) -> Option<Loudness> {
+        self.cached(path, FileStamp::of(path).ok()?)
+    }
+
+    /// Measures the file on a blocking thread, unless that is under way
+    /// already, so callers need not wait for it to be decoded.
+    pub(crate) fn analyze_in_background(self: &Arc<Self>, path: PathBuf) {
+        let is_new = self
+            .pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(path.clone());
+        if !is_new {
+            return;
+        }
+
+        let cache = Arc::clone(self);
+        tokio::task::spawn_blocking(move || {
+            if let Err(err) = cache.analyze(&path) {
+                eprintln!("Failed to measure loudness of {}: {}", path.display(), err);
+            }
+            cache
+                .pending
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .remove(&path);
+        });
+    }
+
+    /// The file's loudness, measuring it if it is new or has changed.
+    pub(crate) fn analyze(&self, path: &Path) -> Result<Loudness, AudioError> {
+        let stamp = FileStamp::of(path)?;
+        if let Some(loudness) = self.cached(path, stamp) {
+            return Ok(loudness);
+        }
+
+        let loudness = analyze_file(path)?;
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(path.to_path_buf(), (stamp, loudness));
+        Ok(loudness)
+    }
+
+    /// The generated sound's loudness, synthesizing and measuring it the
+    /// first time those settings are asked for.
+    pub(crate) fn analyze_generated(&self, sound: &GeneratedSound) -> Loudness {
+        let key = serde_json::to_string(sound).unwrap_or_else(|_| sound.to_string());
+        let cached = self
+            .generated
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(&key)
+            .copied();
+        if let Some(loudness) = cached {
+            return loudness;
+        }
+
+        let loudness = measure(sound.source());
+        self.generated
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(key, loudness);
+        loudness
+    }
+
+    fn cached(&self, path: &Path, stamp: FileStamp) -> Option<Loudness> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(path)
+            .filter(|(cached, _)| *cached == stamp)
+            .map(|(_, loudness)| *loudness)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    const RATE: u32 = 48_000;
+    const MINUS_23_DBFS: f32 = 0.070_794_58;
+
+    /// `seconds` of a stereo sine at `frequency` and `amplitude`, starting at
+    /// `phase` radians.
+    fn sine(frequency: f64, amplitude: f32, phase: f64, seconds: f64) -> Vec<f32> {
+        let frames = (seconds * RATE as f64) as usize;
+        (0..frames)
+            .flat_map(|frame| {
+                let t = frame as f64 / RATE as f64;
+                let sample = amplitude * (2.0 * PI * frequency * t + phase).sin() as f32;
+                [sample, sample]
+            })
+            .collect()
+    }
+
+    fn measure_stereo(samples: Vec<f32>) -> Loudness {
+        measure(SamplesBuffer::new(2, RATE, samples))
+    }
+
+    #[test]
+    fn measures_a_reference_tone() {
+        // A 997 Hz sine at -23 dBFS on both channels reads -23 LUFS.
+        let loudness = measure_stereo(sine(997.0, MINUS_23_DBFS, 0.0, 5.0));
+
+        let integrated = loudness.integrated_lufs.unwrap();
+        assert!((integrated + 23.0).abs() < 0.1, "{integrated}");
+        assert!((loudness.true_peak_dbtp + 23.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn gates_out_silence() {
+        let mut samples = sine(997.0, MINUS_23_DBFS, 0.0, 3.0);
+        samples.extend(vec![0.0; 2 * RATE as usize * 3]);
+
+        // Ungated, the silent half would take it down to -26 LUFS; only the
+        // blocks straddling the end of the tone count towards it.
+        let integrated = measure_stereo(samples).integrated_lufs.unwrap();
+        assert!((integrated + 23.0).abs() < 0.5, "{integrated}");
+        assert_eq!(
+            measure_stereo(vec![0.0; 2 * RATE as usize]).integrated_lufs,
+            None
+        );
+    }
+
+    #[test]
+    fn finds_peaks_between_samples() {
+        // A quarter-rate sine sampled 45° off its crests peaks 3 dB above
+        // its samples.
+        let loudness = measure_stereo(sine(RATE as f64 / 4.0, 0.5, PI / 4.0, 1.0));
+
+        let sample_peak = 20.0 * (0.5 * 0.5f64.sqrt()).log10();
+        assert!(
+            loudness.true_peak_dbtp > sample_peak + 2.5,
+            "{}",
+            loudness.true_peak_dbtp
+        );
+        assert!(loudness.true_peak_dbtp < -5.5);
+    }
+
+    #[test]
+    fn normalization_is_held_back_by_the_peak_ceiling() {
+        let quiet = Loudness {
+            integrated_lufs: Some(-28.0),
+            true_peak_dbtp: -12.0,
+        };
+        assert!((quiet.normalization_gain() - 10f32.powf(0.5)).abs() < 1e-4);
+
+        let peaky = Loudness {
+            integrated_lufs: Some(-28.0),
+            true_peak_dbtp: -3.0,
+        };
+        assert!((peaky.normalization_gain() - 10f32.powf(0.1)).abs() < 1e-4);
+
+        let loud = Loudness {
+            integrated_lufs: Some(-8.0),
+            true_peak_dbtp: 0.0,
+        };
+        assert!((loud.normalization_gain() - 10f32.powf(-0.5)).abs() < 1e-4);
+
+        let silent = Loudness {
+            integrated_lufs: None,
+            true_peak_dbtp: f64::NEG_INFINITY,
+        };
+        assert_eq!(silent.normalization_gain(), 1.0);
+    }
+
+    #[test]
+    fn generated_sounds_are_measured_once_per_setting() {
+        let cache = LoudnessCache::default();
+        let tone = |duration_ms| GeneratedSound::Tone {
+            frequency_hz: 440.0,
+            duration_ms,
+            waveform: Default::default(),
+        };
+
+        let first = cache.analyze_generated(&tone(500));
+        assert_eq!(cache.analyze_generated(&tone(500)), first);
+        cache.analyze_generated(&tone(800));
+        assert_eq!(cache.generated.lock().unwrap().len(), 2);
+    }
+}
@@ -1,7 +1,17 @@
 pub mod error;
+pub mod generator;
+pub mod loudness;
+pub mod options;
+pub mod output;
 pub mod player;
 pub mod service;
 pub mod validator;
+pub mod voice;
 
-pub use service::{AudioService, PlaybackState};
+pub use generator::{GeneratedSound, NoiseColor, Waveform};
+pub use loudness::Loudness;
+pub use options::{PlaybackOptions, PreRoll, RampCurve, Repeat, Trim, VolumeRamp};
+pub use output::{list_output_devices, OutputBackend, OutputDevice};
+pub use player::{FinishReason, VoiceInput, VoiceProgress};
+pub use service::{AudioService, OutputStatus, PlaybackCompletion, PlaybackState};
 pub use validator::AudioFileMetadata;
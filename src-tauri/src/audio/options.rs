@@ -0,0 +1,408 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::error::AudioError;
+
+/// Longest crescendo a schedule may ask for.
+const MAX_RAMP_SECONDS: u32 = 60 * 60;
+/// Most times a file may be repeated; use `Repeat::Forever` beyond that.
+const MAX_PLAY_COUNT: u32 = 1000;
+/// Fade-out applied when the maximum play duration is reached, unless the
+/// schedule sets its own.
+pub(crate) const MAX_DURATION_FADE_OUT: Duration = Duration::from_secs(3);
+/// Longest fade-in or fade-out a schedule may ask for.
+const MAX_FADE_MS: u32 = 60_000;
+
+/// Per-schedule settings for how a file is played, stored with the schedule.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct PlaybackOptions {
+    /// Rises gradually to the schedule's volume instead of the short default
+    /// fade-in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ramp: Option<VolumeRamp>,
+    #[serde(default)]
+    pub repeat: Repeat,
+    /// Fades out and stops once playback has run this long.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_duration_seconds: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim: Option<Trim>,
+    /// Name of the output device to play through instead of the default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_device: Option<String>,
+    /// Plays alongside whatever is already playing instead of stopping it,
+    /// e.g. a short chime over background ambience.
+    #[serde(default)]
+    pub mix: bool,
+    /// Fade-in instead of the short default; a crescendo takes precedence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fade_in_ms: Option<u32>,
+    /// Fade-out when playback is stopped or reaches its maximum duration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fade_out_ms: Option<u32>,
+    /// Shape of the fade-in and fade-out.
+    #[serde(default)]
+    pub fade_curve: RampCurve,
+    /// Adjusts the gain by the file's measured loudness, so different files
+    /// sound equally loud at the same volume.
+    #[serde(default)]
+    pub normalize: bool,
+}
+
+impl PlaybackOptions {
+    pub fn validate(&self) -> Result<(), AudioError> {
+        if let Some(ramp) = &self.ramp {
+            ramp.validate()?;
+        }
+        if let Repeat::Times { count } = self.repeat {
+            if count == 0 || count > MAX_PLAY_COUNT {
+                return Err(AudioError::InvalidOptions(format!(
+                    "repeat count must be between 1 and {MAX_PLAY_COUNT}"
+                )));
+            }
+        }
+        if self.max_duration_seconds == Some(0) {
+            return Err(AudioError::InvalidOptions(
+                "maximum play duration must be at least 1 second".into(),
+            ));
+        }
+        if let Some(trim) = &self.trim {
+            trim.validate(None)?;
+        }
+        for (name, fade) in [("fade-in", self.fade_in_ms), ("fade-out", self.fade_out_ms)] {
+            if fade.is_some_and(|ms| ms > MAX_FADE_MS) {
+                return Err(AudioError::InvalidOptions(format!(
+                    "{name} must last at most {} seconds",
+                    MAX_FADE_MS / 1000
+                )));
+            }
+        }
+        if self
+            .output_device
+            .as_ref()
+            .is_some_and(|name| name.trim().is_empty())
+        {
+            return Err(AudioError::InvalidOptions(
+                "output device name must not be empty".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn max_duration(&self) -> Option<Duration> {
+        self.max_duration_seconds
+            .map(|seconds| Duration::from_secs(seconds as u64))
+    }
+
+    pub fn fade_in(&self) -> Option<Duration> {
+        self.fade_in_ms.map(|ms| Duration::from_millis(ms as u64))
+    }
+
+    pub fn fade_out(&self) -> Option<Duration> {
+        self.fade_out_ms.map(|ms| Duration::from_millis(ms as u64))
+    }
+
+    /// Fade-out applied when the maximum play duration is reached.
+    pub fn limit_fade_out(&self) -> Duration {
+        self.fade_out().unwrap_or(MAX_DURATION_FADE_OUT)
+    }
+
+    /// How long playing a file of length `file` lasts with these options, or
+    /// `None` if it repeats until stopped.
+    pub fn played_length(&self, file: Duration) -> Option<Duration> {
+        let file = self.trim.map_or(file, |trim| trim.segment(file));
+        let repeated =
+            self.repeat
+                .plays()
+                .map(|plays| file * plays)
+                .map(|length| match self.ramp {
+                    Some(ramp) if ramp.loop_until_complete => length.max(ramp.duration()),
+                    _ => length,
+                });
+
+        match (repeated, self.max_duration()) {
+            (Some(length), Some(limit)) => Some(length.min(limit + self.limit_fade_out())),
+            (length, None) => length,
+            (None, limit) => limit.map(|limit| limit + self.limit_fade_out()),
+        }
+    }
+}
+
+/// A sound played just before the main file on the same sink, e.g. an
+/// announcement chime, so the two follow each other without a gap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreRoll {
+    pub path: PathBuf,
+    pub volume_percent: u8,
+    /// Silence between the pre-roll and the main file.
+    pub gap: Duration,
+}
+
+/// Plays only part of a file, e.g. to skip a long intro.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Trim {
+    #[serde(default)]
+    pub start_ms: u64,
+    /// Where playback stops; `None` plays to the end of the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_ms: Option<u64>,
+}
+
+impl Trim {
+    pub fn start(&self) -> Duration {
+        Duration::from_millis(self.start_ms)
+    }
+
+    /// Length of the segment, or `None` if it runs to the end of the file.
+    pub fn length(&self) -> Option<Duration> {
+        self.end_ms
+            .map(|end| Duration::from_millis(end.saturating_sub(self.start_ms)))
+    }
+
+    /// Length of the segment within a file of length `file`.
+    pub fn segment(&self, file: Duration) -> Duration {
+        let end = self
+            .end_ms
+            .map_or(file, |end| Duration::from_millis(end).min(file));
+        end.saturating_sub(self.start())
+    }
+
+    /// Checks the segment is non-empty and, when the file's length is known,
+    /// that it lies within the file.
+    pub fn validate(&self, duration_ms: Option<u64>) -> Result<(), AudioError> {
+        if self.end_ms.is_some_and(|end| end <= self.start_ms) {
+            return Err(AudioError::InvalidOptions(
+                "trim end must come after its start".into(),
+            ));
+        }
+        if let Some(duration_ms) = duration_ms {
+            if self.start_ms >= duration_ms {
+                return Err(AudioError::InvalidOptions(format!(
+                    "trim start {} is past the end of the file ({})",
+                    format_ms(self.start_ms),
+                    format_ms(duration_ms)
+                )));
+            }
+            if let Some(end) = self.end_ms.filter(|end| *end > duration_ms) {
+                return Err(AudioError::InvalidOptions(format!(
+                    "trim end {} is past the end of the file ({})",
+                    format_ms(end),
+                    format_ms(duration_ms)
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// "1:30.250" style position for error messages.
+pub(crate) fn format_ms(ms: u64) -> String {
+    let seconds = ms / 1000;
+    match ms % 1000 {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        millis => format!("{}:{:02}.{millis:03}", seconds / 60, seconds % 60),
+    }
+}
+
+/// How many times a file plays back to back.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Repeat {
+    #[default]
+    Once,
+    Times {
+        count: u32,
+    },
+    /// Until stopped, or until the maximum play duration is reached.
+    Forever,
+}
+
+impl Repeat {
+    /// Total number of plays, or `None` for `Forever`.
+    pub fn plays(self) -> Option<u32> {
+        match self {
+            Repeat::Once => Some(1),
+            Repeat::Times { count } => Some(count),
+            Repeat::Forever => None,
+        }
+    }
+}
+
+/// Shape of a volume ramp between its start and end volume.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RampCurve {
+    #[default]
+    Linear,
+    /// Slow at first, rising faster towards the end.
+    Exponential,
+    /// Quick at first, levelling off towards the end.
+    Logarithmic,
+}
+
+impl RampCurve {
+    /// Steepness of the exponential and logarithmic curves.
+    const SHAPE: f32 = 5.0;
+
+    /// Maps ramp progress `t` (0–1) to the fraction of the volume change
+    /// applied so far.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        let scale = Self::SHAPE.exp() - 1.0;
+        match self {
+            RampCurve::Linear => t,
+            RampCurve::Exponential => ((Self::SHAPE * t).exp() - 1.0) / scale,
+            RampCurve::Logarithmic => (1.0 + scale * t).ln() / Self::SHAPE,
+        }
+    }
+}
+
+/// A crescendo from `start_volume` up to the schedule's volume.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct VolumeRamp {
+    pub duration_seconds: u32,
+    /// Volume (0-100) the ramp starts from; never above the schedule's volume.
+    #[serde(default = "default_ramp_start")]
+    pub start_volume: u8,
+    #[serde(default)]
+    pub curve: RampCurve,
+    /// Plays the file again if it ends before the ramp does.
+    #[serde(default)]
+    pub loop_until_complete: bool,
+}
+
+fn default_ramp_start() -> u8 {
+    10
+}
+
+impl VolumeRamp {
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.duration_seconds as u64)
+    }
+
+    pub fn validate(&self) -> Result<(), AudioError> {
+        if self.duration_seconds == 0 || self.duration_seconds > MAX_RAMP_SECONDS {
+            return Err(AudioError::InvalidOptions(format!(
+                "volume ramp must last between 1 and {MAX_RAMP_SECONDS} seconds"
+            )));
+        }
+        if self.start_volume > 100 {
+            return Err(AudioError::InvalidOptions(
+                "volume ramp must start between 0 and 100".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curves_run_from_silent_to_full_and_keep_rising() {
+        for curve in [
+            RampCurve::Linear,
+            RampCurve::Exponential,
+            RampCurve::Logarithmic,
+        ] {
+            assert!(curve.apply(0.0).abs() < 1e-6, "{curve:?} starts at 0");
+            assert!((curve.apply(1.0) - 1.0).abs() < 1e-6, "{curve:?} ends at 1");
+
+            let samples = (0..=20).map(|step| curve.apply(step as f32 / 20.0));
+            let mut previous = 0.0;
+            for value in samples {
+                assert!(value >= previous, "{curve:?} never falls");
+                previous = value;
+            }
+        }
+
+        assert!(RampCurve::Exponential.apply(0.5) < 0.5);
+        assert!(RampCurve::Logarithmic.apply(0.5) > 0.5);
+    }
+
+    #[test]
+    fn played_length_accounts_for_repeats_and_limits() {
+        let bell = Duration::from_secs(4);
+        let options = |repeat, max_duration_seconds| PlaybackOptions {
+            repeat,
+            max_duration_seconds,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            options(Repeat::Times { count: 3 }, None).played_length(bell),
+            Some(Duration::from_secs(12))
+        );
+        assert_eq!(
+            options(Repeat::Times { count: 3 }, Some(5)).played_length(bell),
+            Some(Duration::from_secs(8))
+        );
+        assert_eq!(
+            options(Repeat::Forever, Some(1200)).played_length(bell),
+            Some(Duration::from_secs(1203))
+        );
+        assert_eq!(options(Repeat::Forever, None).played_length(bell), None);
+
+        let quick_fade = PlaybackOptions {
+            fade_out_ms: Some(500),
+            ..options(Repeat::Forever, Some(1200))
+        };
+        assert_eq!(
+            quick_fade.played_length(bell),
+            Some(Duration::from_millis(1_200_500))
+        );
+
+        assert!(options(Repeat::Times { count: 0 }, None)
+            .validate()
+            .is_err());
+        assert!(options(Repeat::Once, Some(0)).validate().is_err());
+    }
+
+    #[test]
+    fn trim_is_checked_against_the_file_length() {
+        let trim = Trim {
+            start_ms: 45_000,
+            end_ms: Some(90_000),
+        };
+        assert!(trim.validate(Some(200_000)).is_ok());
+        assert_eq!(
+            trim.segment(Duration::from_secs(200)),
+            Duration::from_secs(45)
+        );
+        assert_eq!(
+            trim.segment(Duration::from_secs(60)),
+            Duration::from_secs(15)
+        );
+
+        let err = trim.validate(Some(60_000)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid playback options: trim end 1:30 is past the end of the file (1:00)"
+        );
+        assert!(trim.validate(Some(45_000)).is_err());
+        assert!(Trim {
+            start_ms: 5_000,
+            end_ms: Some(5_000),
+        }
+        .validate(None)
+        .is_err());
+    }
+
+    #[test]
+    fn ramp_defaults_and_limits() {
+        let ramp: VolumeRamp = serde_json::from_str(r#"{"duration_seconds":120}"#).unwrap();
+        assert_eq!(ramp.start_volume, 10);
+        assert_eq!(ramp.curve, RampCurve::Linear);
+        assert!(!ramp.loop_until_complete);
+        assert!(ramp.validate().is_ok());
+
+        let instant = VolumeRamp {
+            duration_seconds: 0,
+            ..ramp
+        };
+        assert!(instant.validate().is_err());
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rodio::cpal::{
+    self,
+    traits::{DeviceTrait, HostTrait},
+};
+use rodio::mixer::{self, Mixer, MixerSource};
+use rodio::{OutputStream, OutputStreamBuilder, StreamError};
+use serde::Serialize;
+
+use crate::audio::error::AudioError;
+
+/// Environment variable that picks the output backend: `device` (default),
+/// `null`, or `wav:<path>`.
+pub const OUTPUT_ENV_VAR: &str = "RESONATIFY_AUDIO_OUTPUT";
+
+/// Setting naming the device schedules play through unless they name one.
+pub const OUTPUT_DEVICE_SETTING: &str = "output_device";
+
+/// Format of the mixer used by the rendering backends.
+pub const RENDER_CHANNELS: u16 = 2;
+pub const RENDER_SAMPLE_RATE: u32 = 44_100;
+
+/// How often the rendering backends pull samples from the mixer.
+const RENDER_TICK: Duration = Duration::from_millis(10);
+
+/// Where the player's mixed audio goes.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum OutputBackend {
+    /// The system's default sound device.
+    #[default]
+    Device,
+    /// Consumes audio in real time and discards it, for machines without a
+    /// sound card.
+    Null,
+    /// Writes exactly what would have been played to a 32-bit float WAV file.
+    /// Nothing is written while the mixer has no sounds at all.
+    Wav(PathBuf),
+}
+
+impl OutputBackend {
+    /// The backend named by [`OUTPUT_ENV_VAR`], or the device if unset.
+    pub fn from_env() -> Result<Self, AudioError> {
+        match std::env::var(OUTPUT_ENV_VAR) {
+            Ok(value) => value.parse(),
+            Err(_) => Ok(Self::Device),
+        }
+    }
+
+    pub(crate) fn open(&self) -> Result<Output, AudioError> {
+        match self {
+            OutputBackend::Device => {
+                let lost = Arc::new(AtomicBool::new(false));
+                let (stream, name) = open_default_device(&lost).map_err(|err| match err {
+                    StreamError::NoDevice => AudioError::NoOutputDevice,
+                    other => AudioError::Stream(other),
+                })?;
+                Ok(Output::Device { stream, name, lost })
+            }
+            OutputBackend::Null => Ok(Output::Rendered {
+                renderer: Renderer::spawn(None)?,
+                name: self.to_string(),
+            }),
+            OutputBackend::Wav(path) => {
+                let spec = hound::WavSpec {
+                    channels: RENDER_CHANNELS,
+                    sample_rate: RENDER_SAMPLE_RATE,
+                    bits_per_sample: 32,
+                    sample_format: hound::SampleFormat::Float,
+                };
+                let writer = hound::WavWriter::create(path, spec)
+                    .map_err(|err| AudioError::Output(err.to_string()))?;
+                Ok(Output::Rendered {
+                    renderer: Renderer::spawn(Some(writer))?,
+                    name: self.to_string(),
+                })
+            }
+        }
+    }
+
+    /// Opens the output device called `name`. Only the device backend has
+    /// named devices.
+    pub(crate) fn open_named(&self, name: &str) -> Result<Output, AudioError> {
+        let not_found = || AudioError::DeviceNotFound(name.to_string());
+        if *self != OutputBackend::Device {
+            return Err(not_found());
+        }
+
+        let device = cpal::default_host()
+            .output_devices()
+            .map_err(|_| not_found())?
+            .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+            .ok_or_else(not_found)?;
+        let lost = Arc::new(AtomicBool::new(false));
+        let stream = open_device(device, &lost).map_err(AudioError::Stream)?;
+        Ok(Output::Device {
+            stream,
+            name: name.to_string(),
+            lost,
+        })
+    }
+}
+
+/// An output device a schedule can play through.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputDevice {
+    pub name: String,
+    pub is_default: bool,
+}
+
+/// The output devices currently connected, default first.
+pub fn list_output_devices() -> Result<Vec<OutputDevice>, AudioError> {
+    let host = cpal::default_host();
+    let default = host
+        .default_output_device()
+        .and_then(|device| device.name().ok());
+    let mut devices = host
+        .output_devices()
+        .map_err(|err| AudioError::Output(err.to_string()))?
+        .filter_map(|device| device.name().ok())
+        .map(|name| OutputDevice {
+            is_default: default.as_ref() == Some(&name),
+            name,
+        })
+        .collect::<Vec<_>>();
+
+    devices.sort_by_key(|device| !device.is_default);
+    let mut seen = HashSet::new();
+    devices.retain(|device| seen.insert(device.name.clone()));
+    Ok(devices)
+}
+
+/// Opens the default output device, falling back to any other device that
+/// works. Returns the stream and the name of the device it plays through.
+fn open_default_device(lost: &Arc<AtomicBool>) -> Result<(OutputStream, String), StreamError> {
+    let host = cpal::default_host();
+    let default = host.default_output_device().ok_or(StreamError::NoDevice);
+    default
+        .and_then(|device| {
+            let name = device_name(&device);
+            open_device(device, lost).map(|stream| (stream, name))
+        })
+        .or_else(|original_err| {
+            let mut devices = host.output_devices().map_err(|_| StreamError::NoDevice)?;
+            devices
+                .find_map(|device| {
+                    let name = device_name(&device);
+                    open_device(device, lost).ok().map(|stream| (stream, name))
+                })
+                .ok_or(original_err)
+        })
+}
+
+/// Opens `device`. `lost` is set if it disappears while the stream is open.
+fn open_device(device: cpal::Device, lost: &Arc<AtomicBool>) -> Result<OutputStream, StreamError> {
+    let lost = Arc::clone(lost);
+    OutputStreamBuilder::from_device(device)?
+        .with_error_callback(move |err: cpal::StreamError| match err {
+            cpal::StreamError::DeviceNotAvailable => lost.store(true, Ordering::Relaxed),
+            other => eprintln!("Audio output error: {}", other),
+        })
+        .open_stream_or_fallback()
+}
+
+fn device_name(device: &cpal::Device) -> String {
+    device.name().unwrap_or_else(|_| "unknown device".into())
+}
+
+impl FromStr for OutputBackend {
+    type Err = AudioError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "" | "device" => Ok(Self::Device),
+            "null" => Ok(Self::Null),
+            other => match other.strip_prefix("wav:") {
+                Some(path) if !path.is_empty() => Ok(Self::Wav(PathBuf::from(path))),
+                _ => Err(AudioError::Output(format!(
+                    "unknown audio output '{other}' (expected device, null or wav:<path>)"
+                ))),
+            },
+        }
+    }
+}
+
+impl fmt::Display for OutputBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputBackend::Device => write!(f, "device"),
+            OutputBackend::Null => write!(f, "null"),
+            OutputBackend::Wav(path) => write!(f, "wav:{}", path.display()),
+        }
+    }
+}
+
+/// An open backend; dropping it closes the device or stops rendering.
+pub(crate) enum Output {
+    Device {
+        stream: OutputStream,
+        name: String,
+        lost: Arc<AtomicBool>,
+    },
+    Rendered {
+        renderer: Renderer,
+        name: String,
+    },
+}
+
+impl Output {
+    pub(crate) fn mixer(&self) -> &Mixer {
+        match self {
+            Output::Device { stream, .. } => stream.mixer(),
+            Output::Rendered { renderer, .. } => &renderer.mixer,
+        }
+    }
+
+    /// The device being played through, or the rendering backend's name.
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            Output::Device { name, .. } | Output::Rendered { name, .. } => name,
+        }
+    }
+
+    /// Whether the device went away, e.g. headphones were unplugged.
+    pub(crate) fn is_lost(&self) -> bool {
+        match self {
+            Output::Device { lost, .. } => lost.load(Ordering::Relaxed),
+            Output::Rendered { .. } => false,
+        }
+    }
+}
+
+type WavFileWriter = hound::WavWriter<BufWriter<File>>;
+
+/// Pulls samples from a mixer at the rate a sound card would, so sinks,
+/// fades and durations behave as they do on a real device.
+pub(crate) struct Renderer {
+    mixer: Mixer,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Renderer {
+    fn spawn(writer: Option<WavFileWriter>) -> Result<Self, AudioError> {
+        let (mixer, source) = mixer::mixer(RENDER_CHANNELS, RENDER_SAMPLE_RATE);
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+
+        let thread = thread::Builder::new()
+            .name("audio-render".into())
+            .spawn(move || Self::run(source, writer, &stopped))
+            .map_err(AudioError::Io)?;
+
+        Ok(Self {
+            mixer,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    fn run(mut source: MixerSource, mut writer: Option<WavFileWriter>, stop: &AtomicBool) {
+        let channels = RENDER_CHANNELS as usize;
+        let started = Instant::now();
+        let mut frames = 0u64;
+        let mut frame = Vec::with_capacity(channels);
+
+        while !stop.load(Ordering::Relaxed) {
+            let due = started.elapsed().as_micros() as u64 * RENDER_SAMPLE_RATE as u64 / 1_000_000;
+            let mut wrote = false;
+
+            // Whole frames at a time, so the file never ends mid-frame.
+            while frames < due {
+                frames += 1;
+                frame.clear();
+                frame.extend((0..channels).map(|_| source.next()));
+                if frame.iter().all(Option::is_none) {
+                    continue;
+                }
+
+                if let Some(output) = writer.as_mut() {
+                    for sample in &frame {
+                        if let Err(err) = output.write_sample(sample.unwrap_or(0.0)) {
+                            eprintln!("Failed to write rendered audio: {}", err);
+                            writer = None;
+                            break;
+                        }
+                    }
+                    wrote = true;
+                }
+            }
+
+            // Keep the header current so the file can be read while rendering.
+            if let Some(output) = writer.as_mut().filter(|_| wrote) {
+                if let Err(err) = output.flush() {
+                    eprintln!("Failed to flush rendered audio: {}", err);
+                }
+            }
+
+            thread::sleep(RENDER_TICK);
+        }
+
+        if let Some(output) = writer {
+            if let Err(err) = output.finalize() {
+                eprintln!("Failed to finish rendered audio: {}", err);
+            }
+        }
+    }
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_backend_names() {
+        assert_eq!("device".parse::<OutputBackend>().unwrap(), OutputBackend::Device);
+        assert_eq!("null".parse::<OutputBackend>().unwrap(), OutputBackend::Null);
+        assert_eq!(
+            "wav:/tmp/out.wav".parse::<OutputBackend>().unwrap(),
+            OutputBackend::Wav(PathBuf::from("/tmp/out.wav"))
+        );
+        assert!("speakers".parse::<OutputBackend>().is_err());
+        assert!("wav:".parse::<OutputBackend>().is_err());
+    }
+}
@@ -1,155 +1,626 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufReader, Read, Seek};
 use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use chrono::Utc;
-use rodio::{decoder::DecoderBuilder, Decoder, OutputStream, OutputStreamBuilder, Sink};
+use rodio::source::Zero;
+use rodio::{decoder::DecoderBuilder, Decoder, Sink, Source};
 use serde::Serialize;
+use uuid::Uuid;
 
 use crate::audio::error::AudioError;
+use crate::audio::generator::GeneratedSound;
+use crate::audio::options::{format_ms, PlaybackOptions, PreRoll, Trim};
+use crate::audio::output::{Output, OutputBackend};
 use crate::audio::validator::AudioFileMetadata;
+use crate::audio::voice::{Envelope, Requeue, VoiceControl, VoiceShape, VoiceSource};
 
 #[derive(Debug, Clone, Serialize)]
 pub struct PlaybackContext {
+    /// Identifies the voice, e.g. to stop it on its own.
+    pub id: String,
     pub file_path: String,
     pub metadata: AudioFileMetadata,
     pub started_at: String,
     pub volume: f32,
+    /// The device (or rendering backend) the file plays through.
+    pub output_device: String,
+    /// Why the requested device was not used, if it was not.
+    pub fallback: Option<String>,
+    pub paused: bool,
+}
+
+/// Where a voice is in its file.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceProgress {
+    pub id: String,
+    /// Position within the (trimmed) file; zero during a pre-roll.
+    pub position_ms: u64,
+    /// Length of the (trimmed) file, if known.
+    pub duration_ms: Option<u64>,
+    pub paused: bool,
+}
+
+/// Why a voice stopped playing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// The file (and any repeats) played to the end.
+    Finished,
+    Stopped,
+    /// A new sound that does not mix took its place.
+    Replaced,
+    /// The output device went away.
+    OutputLost,
+}
+
+/// A voice that is no longer playing.
+#[derive(Debug, Clone)]
+pub struct FinishedVoice {
+    pub context: PlaybackContext,
+    /// How long it played, including any pre-roll.
+    pub played: Duration,
+    pub reason: FinishReason,
+}
+
+/// What a voice plays.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VoiceInput {
+    File(PathBuf),
+    Generated(GeneratedSound),
+}
+
+impl VoiceInput {
+    /// Opens the input from the start, limited to the `trim` segment if
+    /// there is one.
+    pub(crate) fn open(&self, trim: Option<Trim>) -> Result<Box<dyn Source + Send>, AudioError> {
+        match (self, trim) {
+            (Self::File(path), trim) => AudioPlayer::create_source(path, trim),
+            (Self::Generated(sound), Some(trim)) => {
+                Ok(Box::new(Segment::new(sound.source(), trim)?))
+            }
+            (Self::Generated(sound), None) => Ok(Box::new(sound.source())),
+        }
+    }
+}
+
+/// The file's path, or a description of the generated sound.
+impl fmt::Display for VoiceInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Generated(sound) => write!(f, "{sound}"),
+        }
+    }
+}
+
+/// Everything needed to start a voice.
+pub struct VoiceRequest {
+    pub input: VoiceInput,
+    pub metadata: AudioFileMetadata,
+    pub volume: f32,
+    /// Loudness normalization applied on top of `volume`.
+    pub gain: f32,
+    pub fade_duration: Duration,
+    pub options: PlaybackOptions,
+    /// Device to play through; `None` is the default output.
+    pub device: Option<String>,
+    pub preroll: Option<PreRoll>,
+}
+
+/// One sound playing on its own sink, mixed with any others.
+struct Voice {
+    sink: Sink,
+    /// The named device it plays through; `None` is the default output.
+    device: Option<String>,
+    context: PlaybackContext,
+    control: Arc<VoiceControl>,
+    /// Fade-out when stopped, if the schedule sets one.
+    fade_out: Option<Duration>,
+    started: Instant,
+    /// Length of one play of the (trimmed) file, if known.
+    length: Option<Duration>,
+    paused_at: Option<Instant>,
+    /// Time spent paused before the current pause.
+    paused_for: Duration,
+}
+
+impl Voice {
+    fn finish(&self, reason: FinishReason) -> FinishedVoice {
+        FinishedVoice {
+            context: self.context.clone(),
+            played: self.played(),
+            reason,
+        }
+    }
+
+    /// Time spent playing, not counting pauses.
+    fn played(&self) -> Duration {
+        let pausing = self.paused_at.map(|at| at.elapsed()).unwrap_or_default();
+        self.started
+            .elapsed()
+            .saturating_sub(self.paused_for + pausing)
+    }
+
+    fn pause(&mut self) {
+        if self.paused_at.is_none() {
+            self.sink.pause();
+            self.paused_at = Some(Instant::now());
+            self.context.paused = true;
+        }
+    }
+
+    fn resume(&mut self) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.paused_for += paused_at.elapsed();
+            self.sink.play();
+            self.context.paused = false;
+        }
+    }
+
+    /// Moves to `position` within the (trimmed) file.
+    fn seek(&self, position: Duration) -> Result<(), AudioError> {
+        if !self.control.has_started() {
+            return Err(AudioError::Seek("cannot seek during the pre-roll".into()));
+        }
+        if let Some(length) = self.length.filter(|length| position >= *length) {
+            return Err(AudioError::Seek(format!(
+                "position {} is past the end of the file ({})",
+                format_ms(position.as_millis() as u64),
+                format_ms(length.as_millis() as u64)
+            )));
+        }
+        self.sink
+            .try_seek(position)
+            .map_err(|err| AudioError::Seek(err.to_string()))
+    }
+
+    fn progress(&self) -> VoiceProgress {
+        VoiceProgress {
+            id: self.context.id.clone(),
+            position_ms: self.control.position().as_millis() as u64,
+            duration_ms: self.length.map(|length| length.as_millis() as u64),
+            paused: self.paused_at.is_some(),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.sink.empty()
+    }
+
+    /// Fades the voice out, leaving its sink to finish on the output thread.
+    /// A paused voice is silent already, and the pre-roll sits outside the
+    /// fade, so either stops straight away.
+    fn fade_out(self, default_fade: Duration) {
+        if self.paused_at.is_some() || !self.control.has_started() {
+            return self.stop_immediate();
+        }
+        self.control.fade_out(self.fade_out.unwrap_or(default_fade));
+        self.sink.detach();
+    }
+
+    fn stop_immediate(self) {
+        self.sink.stop();
+    }
 }
 
 pub struct AudioPlayer {
-    _stream: OutputStream,
-    sink: Option<Arc<Sink>>,
-    current: Option<PlaybackContext>,
-    fade_task: Option<JoinHandle<()>>,
+    backend: OutputBackend,
+    output: Output,
+    /// Named devices opened for schedules that target them.
+    devices: HashMap<String, Output>,
+    /// Sounds currently playing, oldest first.
+    voices: Vec<Voice>,
 }
 
 impl AudioPlayer {
-    pub fn new() -> Result<Self, AudioError> {
-        let stream = OutputStreamBuilder::open_default_stream().map_err(|err| match err {
-            rodio::StreamError::NoDevice => AudioError::NoOutputDevice,
-            other => AudioError::Stream(other),
-        })?;
-
+    pub fn new(backend: &OutputBackend) -> Result<Self, AudioError> {
         Ok(Self {
-            _stream: stream,
-            sink: None,
-            current: None,
-            fade_task: None,
+            backend: backend.clone(),
+            output: backend.open()?,
+            devices: HashMap::new(),
+            voices: Vec::new(),
         })
     }
 
+    /// Whether the default output device went away; the player must be
+    /// reopened.
+    pub fn is_output_lost(&self) -> bool {
+        self.output.is_lost()
+    }
+
+    /// Closes the named devices that went away and drops the voices playing
+    /// through them. The device is opened again when next asked for.
+    pub fn drop_lost_devices(&mut self) -> Vec<FinishedVoice> {
+        let lost = self
+            .devices
+            .iter()
+            .filter(|(_, output)| output.is_lost())
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        if lost.is_empty() {
+            return Vec::new();
+        }
+
+        let mut finished = Vec::new();
+        for name in &lost {
+            eprintln!("Audio output device '{}' was disconnected", name);
+            self.devices.remove(name);
+        }
+        self.voices.retain(|voice| {
+            let on_lost = voice.device.as_ref().is_some_and(|device| lost.contains(device));
+            if on_lost {
+                finished.push(voice.finish(FinishReason::OutputLost));
+                voice.sink.stop();
+            }
+            !on_lost
+        });
+        finished
+    }
+
+    /// Starts a new voice, after its pre-roll if it has one. Unless
+    /// `options.mix` is set, every voice already playing is stopped first;
+    /// those are returned as replaced.
     pub fn play(
         &mut self,
-        path: PathBuf,
-        metadata: AudioFileMetadata,
-        volume: f32,
-        fade_duration: Duration,
-    ) -> Result<PlaybackContext, AudioError> {
-        self.abort_fade();
-        self.stop_immediate();
-
-        let sink = Arc::new(Sink::connect_new(self._stream.mixer()));
-        let decoder = Self::create_decoder(&path)?;
-
-        sink.set_volume(0.0);
-        sink.append(decoder);
-        sink.play();
-
-        self.spawn_fade(
-            Arc::clone(&sink),
-            0.0,
+        request: VoiceRequest,
+    ) -> Result<(PlaybackContext, Vec<FinishedVoice>), AudioError> {
+        let VoiceRequest {
+            input,
+            metadata,
             volume,
+            gain,
             fade_duration,
-            false,
-        );
+            options,
+            device,
+            preroll,
+        } = request;
+
+        let (device, fallback) = self.output_for(device.as_deref());
+        let output = self.output(device.as_deref());
+        let output_device = output.name().to_string();
+        let sink = Sink::connect_new(output.mixer());
+        // Everything that can fail is opened before any voice is replaced.
+        let source = input.open(options.trim)?;
+        let preroll = preroll
+            .map(|preroll| Self::create_source(&preroll.path, None).map(|source| (source, preroll)))
+            .transpose()?;
+
+        let mut replaced = Vec::new();
+        if !options.mix {
+            for voice in self.voices.drain(..) {
+                replaced.push(voice.finish(FinishReason::Replaced));
+                voice.stop_immediate();
+            }
+        }
+
+        let start = match options.ramp {
+            Some(ramp) => Envelope {
+                from: (ramp.start_volume as f32 / 100.0).min(volume) * gain,
+                to: volume * gain,
+                duration: ramp.duration(),
+                curve: ramp.curve,
+            },
+            None => Envelope {
+                from: 0.0,
+                to: volume * gain,
+                duration: options.fade_in().unwrap_or(fade_duration),
+                curve: options.fade_curve,
+            },
+        };
+
+        let remaining = options.repeat.plays().map(|plays| plays.saturating_sub(1));
+        let ramp_loop = options
+            .ramp
+            .filter(|ramp| ramp.loop_until_complete)
+            .map(|ramp| ramp.duration())
+            .unwrap_or_default();
+        let requeue = (remaining != Some(0) || !ramp_loop.is_zero()).then(|| Requeue {
+            input: input.clone(),
+            trim: options.trim,
+            remaining,
+            until: ramp_loop,
+        });
+
+        let control = Arc::new(VoiceControl::new(preroll.is_some()));
+        let shape = VoiceShape {
+            start,
+            stop_at: options.max_duration(),
+            fade_out: options.limit_fade_out(),
+            fade_curve: options.fade_curve,
+        };
+
+        if let Some((source, preroll)) = preroll {
+            Self::queue_preroll(&sink, source, &preroll);
+        }
+        sink.append(VoiceSource::new(source, Arc::clone(&control), shape, requeue));
+        sink.play();
 
         let context = PlaybackContext {
-            file_path: path.display().to_string(),
+            id: Uuid::new_v4().to_string(),
+            file_path: input.to_string(),
             metadata,
             started_at: Utc::now().to_rfc3339(),
             volume,
+            output_device,
+            fallback,
+            paused: false,
         };
+        let length = metadata_length(&context.metadata).map(|file| match options.trim {
+            Some(trim) => trim.segment(file),
+            None => file,
+        });
 
-        self.sink = Some(sink);
-        self.current = Some(context.clone());
+        self.voices.push(Voice {
+            sink,
+            device,
+            context: context.clone(),
+            control,
+            fade_out: options.fade_out(),
+            started: Instant::now(),
+            length,
+            paused_at: None,
+            paused_for: Duration::ZERO,
+        });
+
+        Ok((context, replaced))
+    }
+
+    /// Fades out every voice.
+    pub fn stop(&mut self, fade_duration: Duration) -> Vec<FinishedVoice> {
+        self.voices
+            .drain(..)
+            .map(|voice| {
+                let finished = voice.finish(FinishReason::Stopped);
+                voice.fade_out(fade_duration);
+                finished
+            })
+            .collect()
+    }
+
+    /// Fades out the voice with `id`, leaving the others playing.
+    pub fn stop_voice(&mut self, id: &str, fade_duration: Duration) -> Option<FinishedVoice> {
+        let index = self.voices.iter().position(|voice| voice.context.id == id)?;
+        let voice = self.voices.remove(index);
+        let finished = voice.finish(FinishReason::Stopped);
+        voice.fade_out(fade_duration);
+        Some(finished)
+    }
+
+    /// Drops every voice, e.g. because the output went away.
+    pub fn abandon(&mut self, reason: FinishReason) -> Vec<FinishedVoice> {
+        self.voices
+            .drain(..)
+            .map(|voice| {
+                let finished = voice.finish(reason);
+                voice.stop_immediate();
+                finished
+            })
+            .collect()
+    }
 
-        Ok(context)
+    /// Removes the voices that have played to the end. Dropping their sinks
+    /// detaches them from the output.
+    pub fn take_finished(&mut self) -> Vec<FinishedVoice> {
+        let mut finished = Vec::new();
+        self.voices.retain(|voice| {
+            let done = voice.is_finished();
+            if done {
+                finished.push(voice.finish(FinishReason::Finished));
+            }
+            !done
+        });
+        finished
     }
 
-    pub fn stop(&mut self, fade_duration: Duration) -> Option<PlaybackContext> {
-        self.abort_fade();
+    /// Pauses the voice `id`, or every voice when `id` is `None`.
+    pub fn pause(&mut self, id: Option<&str>) -> Result<(), AudioError> {
+        self.voices_matching(id)?.for_each(Voice::pause);
+        Ok(())
+    }
 
-        let previous = self.current.clone();
+    /// Resumes the voice `id`, or every voice when `id` is `None`.
+    pub fn resume(&mut self, id: Option<&str>) -> Result<(), AudioError> {
+        self.voices_matching(id)?.for_each(Voice::resume);
+        Ok(())
+    }
 
-        if let Some(sink) = self.sink.take() {
-            let current_volume = sink.volume();
-            self.spawn_fade(sink, current_volume, 0.0, fade_duration, true);
+    /// Seeks the voice `id`, or the latest voice when `id` is `None`.
+    pub fn seek(&mut self, id: Option<&str>, position: Duration) -> Result<(), AudioError> {
+        let voice = match id {
+            Some(id) => self.voices.iter().find(|voice| voice.context.id == id),
+            None => self.voices.last(),
+        };
+        match voice {
+            Some(voice) => voice.seek(position),
+            None => Err(Self::missing_voice(id)),
         }
+    }
 
-        self.current = None;
-        previous
+    pub fn progress(&self) -> Vec<VoiceProgress> {
+        self.voices.iter().map(Voice::progress).collect()
     }
 
-    fn stop_immediate(&mut self) {
-        if let Some(sink) = self.sink.take() {
-            sink.stop();
+    fn voices_matching<'a>(
+        &'a mut self,
+        id: Option<&'a str>,
+    ) -> Result<impl Iterator<Item = &'a mut Voice>, AudioError> {
+        let found = match id {
+            Some(id) => self.voices.iter().any(|voice| voice.context.id == id),
+            None => !self.voices.is_empty(),
+        };
+        if !found {
+            return Err(Self::missing_voice(id));
         }
-        self.current = None;
+        Ok(self
+            .voices
+            .iter_mut()
+            .filter(move |voice| id.is_none_or(|id| voice.context.id == id)))
     }
 
-    fn abort_fade(&mut self) {
-        if let Some(handle) = self.fade_task.take() {
-            let _ = handle.join();
+    fn missing_voice(id: Option<&str>) -> AudioError {
+        match id {
+            Some(id) => AudioError::VoiceNotFound(id.to_string()),
+            None => AudioError::NothingPlaying,
         }
     }
 
-    fn spawn_fade(
-        &mut self,
-        sink: Arc<Sink>,
-        start_volume: f32,
-        end_volume: f32,
-        duration: Duration,
-        stop_after: bool,
-    ) {
-        let steps = 12u32;
-        let step_duration = if steps == 0 {
-            Duration::from_millis(0)
-        } else {
-            duration
-                .checked_div(steps)
-                .unwrap_or_else(|| Duration::from_millis(0))
+    pub fn is_playing(&self) -> bool {
+        !self.voices.is_empty()
+    }
+
+    /// The voices playing, oldest first.
+    pub fn voices(&self) -> Vec<PlaybackContext> {
+        self.voices.iter().map(|voice| voice.context.clone()).collect()
+    }
+
+    /// Queues the pre-roll, at its own volume, and its gap ahead of the file.
+    fn queue_preroll(sink: &Sink, source: Box<dyn Source + Send>, preroll: &PreRoll) {
+        let (channels, sample_rate) = (source.channels(), source.sample_rate());
+        sink.append(source.amplify((preroll.volume_percent as f32 / 100.0).clamp(0.0, 1.0)));
+        if !preroll.gap.is_zero() {
+            sink.append(Zero::new(channels, sample_rate).take_duration(preroll.gap));
+        }
+    }
+
+    /// The named device to play `name` through, opening it if needed, or
+    /// `None` for the default output. Falls back to the default output, with
+    /// the reason, when the device is unavailable.
+    fn output_for(&mut self, name: Option<&str>) -> (Option<String>, Option<String>) {
+        let Some(name) = name.filter(|name| *name != self.output.name()) else {
+            return (None, None);
         };
 
-        let task = thread::spawn(move || {
-            if steps == 0 {
-                sink.set_volume(end_volume.clamp(0.0, 1.0));
-            } else {
-                for step in 0..=steps {
-                    let t = step as f32 / steps as f32;
-                    let volume = start_volume + (end_volume - start_volume) * t;
-                    sink.set_volume(volume.clamp(0.0, 1.0));
-
-                    if step < steps {
-                        thread::sleep(step_duration);
-                    }
+        if self.devices.get(name).is_some_and(Output::is_lost) {
+            self.devices.remove(name);
+        }
+        if !self.devices.contains_key(name) {
+            match self.backend.open_named(name) {
+                Ok(output) => {
+                    self.devices.insert(name.to_string(), output);
+                }
+                Err(err) => {
+                    let reason = format!("{}; played on '{}' instead", err, self.output.name());
+                    return (None, Some(reason));
                 }
             }
+        }
+        (Some(name.to_string()), None)
+    }
+
+    /// The output for a device from [`output_for`](Self::output_for).
+    fn output(&self, device: Option<&str>) -> &Output {
+        device
+            .and_then(|name| self.devices.get(name))
+            .unwrap_or(&self.output)
+    }
+}
+
+/// Length of a file from its metadata, if known.
+fn metadata_length(metadata: &AudioFileMetadata) -> Option<Duration> {
+    metadata.duration_ms.map(Duration::from_millis)
+}
+
+/// The trimmed part of a source. Seeking is relative to the segment's start
+/// and keeps its end point, unlike `take_duration`.
+struct Segment<S> {
+    input: S,
+    start: Duration,
+    length: Option<Duration>,
+    /// Samples left before the end point.
+    remaining: Option<u64>,
+}
+
+impl<S: Source> Segment<S> {
+    fn new(mut input: S, trim: Trim) -> Result<Self, AudioError> {
+        if trim.start_ms > 0 {
+            input
+                .try_seek(trim.start())
+                .map_err(|err| AudioError::Seek(err.to_string()))?;
+        }
+        let mut segment = Self {
+            input,
+            start: trim.start(),
+            length: trim.length(),
+            remaining: None,
+        };
+        segment.remaining = segment.samples_after(Duration::ZERO);
+        Ok(segment)
+    }
+
+    fn samples_after(&self, position: Duration) -> Option<u64> {
+        let rate = self.input.sample_rate() as f64 * self.input.channels() as f64;
+        self.length
+            .map(|length| (length.saturating_sub(position).as_secs_f64() * rate) as u64)
+    }
+}
+
+impl<S: Source> Iterator for Segment<S> {
+    type Item = rodio::Sample;
 
-            if stop_after {
-                sink.stop();
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.remaining.as_mut() {
+            Some(0) => None,
+            Some(remaining) => {
+                *remaining -= 1;
+                self.input.next()
             }
-        });
+            None => self.input.next(),
+        }
+    }
+}
+
+impl<S: Source> Source for Segment<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        let span = self.input.current_span_len();
+        match self.remaining {
+            Some(remaining) => Some(span.map_or(remaining as usize, |span| span.min(remaining as usize))),
+            None => span,
+        }
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.input.channels()
+    }
 
-        self.fade_task = Some(task);
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let total = self.input.total_duration().map(|total| total.saturating_sub(self.start));
+        match (total, self.length) {
+            (Some(total), Some(length)) => Some(total.min(length)),
+            (total, length) => length.or(total),
+        }
+    }
+
+    fn try_seek(&mut self, position: Duration) -> Result<(), rodio::source::SeekError> {
+        self.input.try_seek(self.start + position)?;
+        self.remaining = self.samples_after(position);
+        Ok(())
     }
 }
 
 impl AudioPlayer {
+    /// Decodes `path`, limited to the `trim` segment if there is one.
+    pub(crate) fn create_source(
+        path: &Path,
+        trim: Option<Trim>,
+    ) -> Result<Box<dyn Source + Send>, AudioError> {
+        let decoder = Self::create_decoder(path)?;
+        match trim {
+            Some(trim) => Ok(Box::new(Segment::new(decoder, trim)?)),
+            None => Ok(Box::new(decoder)),
+        }
+    }
+
     fn create_decoder(path: &Path) -> Result<Decoder<BufReader<File>>, AudioError> {
         let file = File::open(path)?;
         let metadata = file.metadata()?;
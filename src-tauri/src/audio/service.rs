@@ -1,43 +1,165 @@
+use std::collections::VecDeque;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::{self, Sender};
-use std::sync::{Arc, RwLock};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use chrono::Utc;
 use serde::Serialize;
-use tokio::sync::oneshot;
+use tokio::sync::{broadcast, oneshot};
 
 use crate::audio::error::{AudioError, AudioValidationError};
-use crate::audio::player::{AudioPlayer, PlaybackContext};
+use crate::audio::generator::GeneratedSound;
+use crate::audio::loudness::{Loudness, LoudnessCache};
+use crate::audio::options::{PlaybackOptions, PreRoll};
+use crate::audio::output::OutputBackend;
+use crate::audio::player::{
+    AudioPlayer, FinishReason, FinishedVoice, PlaybackContext, VoiceInput, VoiceProgress,
+    VoiceRequest,
+};
 use crate::audio::validator::{AudioFileMetadata, AudioValidator};
 
+const DEFAULT_FADE_IN: Duration = Duration::from_millis(400);
+const FADE_OUT: Duration = Duration::from_millis(300);
+/// How often a missing or lost output is reopened.
+const OUTPUT_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+/// How often playing voices are checked for having finished.
+const VOICE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Completions kept for callers that start waiting after a short sound has
+/// already ended.
+const RECENT_COMPLETIONS: usize = 32;
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PlaybackState {
     pub is_playing: bool,
+    /// The most recently started voice.
     pub current: Option<PlaybackContext>,
+    /// Every voice playing, oldest first.
+    pub voices: Vec<PlaybackContext>,
+    pub output: OutputStatus,
 }
 
 impl PlaybackState {
-    fn idle() -> Self {
+    fn set_voices(&mut self, voices: Vec<PlaybackContext>) {
+        self.is_playing = !voices.is_empty();
+        self.current = voices.last().cloned();
+        self.voices = voices;
+    }
+}
+
+/// Sent when a voice stops playing, for whatever reason.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackCompletion {
+    pub id: String,
+    pub file_path: String,
+    pub started_at: String,
+    pub finished_at: String,
+    /// How long the voice actually played, including any pre-roll.
+    pub played_ms: u64,
+    pub reason: FinishReason,
+}
+
+impl From<FinishedVoice> for PlaybackCompletion {
+    fn from(voice: FinishedVoice) -> Self {
         Self {
-            is_playing: false,
-            current: None,
+            id: voice.context.id,
+            file_path: voice.context.file_path,
+            started_at: voice.context.started_at,
+            finished_at: Utc::now().to_rfc3339(),
+            played_ms: voice.played.as_millis() as u64,
+            reason: voice.reason,
+        }
+    }
+}
+
+/// Fans completions out to subscribers and remembers the latest few.
+struct Completions {
+    sender: broadcast::Sender<PlaybackCompletion>,
+    recent: Mutex<VecDeque<PlaybackCompletion>>,
+}
+
+impl Completions {
+    fn new() -> Self {
+        Self {
+            sender: broadcast::channel(RECENT_COMPLETIONS).0,
+            recent: Mutex::new(VecDeque::with_capacity(RECENT_COMPLETIONS)),
+        }
+    }
+
+    fn publish(&self, completion: PlaybackCompletion) {
+        let mut recent = self.recent.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if recent.len() == RECENT_COMPLETIONS {
+            recent.pop_front();
+        }
+        recent.push_back(completion.clone());
+        let _ = self.sender.send(completion);
+    }
+
+    fn recent(&self, id: &str) -> Option<PlaybackCompletion> {
+        self.recent
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .find(|completion| completion.id == id)
+            .cloned()
+    }
+}
+
+/// Whether there is an output to play through. Without one the service runs
+/// degraded: playback fails and the output is retried in the background.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputStatus {
+    pub backend: String,
+    pub available: bool,
+    /// Why the output could not be opened or was lost.
+    pub error: Option<String>,
+    /// When the output last became available or unavailable.
+    pub changed_at: String,
+}
+
+impl OutputStatus {
+    fn new(backend: &OutputBackend, error: Option<String>) -> Self {
+        Self {
+            backend: backend.to_string(),
+            available: error.is_none(),
+            error,
+            changed_at: Utc::now().to_rfc3339(),
         }
     }
 }
 
 enum AudioCommand {
     Play {
-        path: PathBuf,
-        metadata: AudioFileMetadata,
-        volume: f32,
-        fade_duration: Duration,
+        voice: Box<VoiceRequest>,
         respond_to: oneshot::Sender<Result<PlaybackState, AudioError>>,
     },
     Stop {
         respond_to: oneshot::Sender<Result<PlaybackState, AudioError>>,
     },
+    StopVoice {
+        id: String,
+        respond_to: oneshot::Sender<Result<PlaybackState, AudioError>>,
+    },
+    Pause {
+        id: Option<String>,
+        respond_to: oneshot::Sender<Result<PlaybackState, AudioError>>,
+    },
+    Resume {
+        id: Option<String>,
+        respond_to: oneshot::Sender<Result<PlaybackState, AudioError>>,
+    },
+    Seek {
+        id: Option<String>,
+        position: Duration,
+        respond_to: oneshot::Sender<Result<PlaybackState, AudioError>>,
+    },
+    Progress {
+        respond_to: oneshot::Sender<Vec<VoiceProgress>>,
+    },
 }
 
 #[derive(Clone)]
@@ -45,6 +167,11 @@ pub struct AudioService {
     validator: AudioValidator,
     commands: Sender<AudioCommand>,
     state: Arc<RwLock<PlaybackState>>,
+    /// Device used by schedules that do not name one; `None` is the
+    /// system default.
+    default_device: Arc<RwLock<Option<String>>>,
+    completions: Arc<Completions>,
+    loudness: Arc<LoudnessCache>,
 }
 
 impl AudioService {
@@ -53,90 +180,127 @@ impl AudioService {
     }
 
     pub fn with_validator(validator: AudioValidator) -> Result<Self, AudioError> {
+        Self::with_backend(validator, OutputBackend::Device)
+    }
+
+    /// Plays through `backend` instead of the default sound device, e.g. to
+    /// run headless or render to a file. Starts degraded, rather than
+    /// failing, if the output cannot be opened.
+    pub fn with_backend(
+        validator: AudioValidator,
+        backend: OutputBackend,
+    ) -> Result<Self, AudioError> {
         let (command_tx, command_rx) = mpsc::channel();
-        let state = Arc::new(RwLock::new(PlaybackState::idle()));
+        let state = Arc::new(RwLock::new(PlaybackState {
+            is_playing: false,
+            current: None,
+            voices: Vec::new(),
+            output: OutputStatus::new(&backend, None),
+        }));
+        let completions = Arc::new(Completions::new());
         let (init_tx, init_rx) = mpsc::sync_channel(1);
         let state_for_thread = Arc::clone(&state);
+        let completions_for_thread = Arc::clone(&completions);
 
         thread::Builder::new()
             .name("audio-service".into())
             .spawn(move || {
-                let mut player = match AudioPlayer::new() {
-                    Ok(player) => {
-                        let _ = init_tx.send(Ok(()));
-                        player
-                    }
-                    Err(err) => {
-                        let _ = init_tx.send(Err(err));
-                        return;
-                    }
-                };
-
-                const FADE_OUT: Duration = Duration::from_millis(300);
-
-                while let Ok(command) = command_rx.recv() {
-                    match command {
-                        AudioCommand::Play {
-                            path,
-                            metadata,
-                            volume,
-                            fade_duration,
-                            respond_to,
-                        } => {
-                            let result = player.play(path, metadata, volume, fade_duration).map(|context| {
-                                let new_state = PlaybackState {
-                                    is_playing: true,
-                                    current: Some(context.clone()),
-                                };
-
-                                if let Ok(mut guard) = state_for_thread.write() {
-                                    *guard = new_state.clone();
-                                }
-
-                                new_state
-                            });
-
-                            let _ = respond_to.send(result);
-                        }
-                        AudioCommand::Stop { respond_to } => {
-                            player.stop(FADE_OUT);
-
-                            let new_state = PlaybackState::idle();
-                            if let Ok(mut guard) = state_for_thread.write() {
-                                *guard = new_state.clone();
-                            }
-
-                            let _ = respond_to.send(Ok(new_state));
-                        }
-                    }
-                }
+                let mut output =
+                    OutputSupervisor::new(backend, state_for_thread, completions_for_thread);
+                let _ = init_tx.send(());
+                output.run(command_rx);
             })
             .map_err(AudioError::Io)?;
 
-        match init_rx.recv() {
-            Ok(Ok(())) => Ok(Self {
-                validator,
-                commands: command_tx,
-                state,
-            }),
-            Ok(Err(err)) => Err(err),
-            Err(_) => Err(AudioError::EngineUnavailable),
-        }
+        init_rx.recv().map_err(|_| AudioError::EngineUnavailable)?;
+        Ok(Self {
+            validator,
+            commands: command_tx,
+            state,
+            default_device: Arc::new(RwLock::new(None)),
+            completions,
+            loudness: Arc::new(LoudnessCache::default()),
+        })
     }
 
-    pub async fn validate<P: AsRef<Path>>(
+    /// Checks the file can be played and reads its length and format,
+    /// without measuring its loudness.
+    pub fn metadata<P: AsRef<Path>>(
         &self,
         path: P,
     ) -> Result<AudioFileMetadata, AudioValidationError> {
         self.validator.validate(path)
     }
 
+    /// Checks the file can be played and measures its loudness, so it is
+    /// ready for normalized playback.
+    pub async fn validate<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<AudioFileMetadata, AudioValidationError> {
+        let mut metadata = self.metadata(path)?;
+        match self.analyze(&metadata.path).await {
+            Ok(loudness) => metadata.loudness = Some(loudness),
+            Err(err) => eprintln!("Failed to measure loudness of {}: {}", metadata.path, err),
+        }
+        Ok(metadata)
+    }
+
+    /// Measures the file's loudness, or returns the earlier measurement if
+    /// it has not changed since.
+    pub async fn analyze<P: AsRef<Path>>(&self, path: P) -> Result<Loudness, AudioError> {
+        let cache = Arc::clone(&self.loudness);
+        let path = path.as_ref().to_path_buf();
+        tokio::task::spawn_blocking(move || cache.analyze(&path))
+            .await
+            .map_err(|_| AudioError::EngineUnavailable)?
+    }
+
+    /// Starts measuring the file in the background unless it has been
+    /// measured since it last changed, so normalized playback of it is ready.
+    pub fn measure_in_background<P: AsRef<Path>>(&self, path: P) {
+        let path = path.as_ref();
+        if self.loudness.measured(path).is_none() {
+            self.loudness.analyze_in_background(path.to_path_buf());
+        }
+    }
+
+    /// Gain that brings the input to the normalization target. Decoding a
+    /// whole file would hold up playback, so a file not measured yet plays
+    /// unadjusted while it is measured in the background; so does a file
+    /// that cannot be measured.
+    async fn normalization_gain(&self, input: &VoiceInput, metadata: &AudioFileMetadata) -> f32 {
+        let measured = match input {
+            VoiceInput::File(_) => match self.loudness.measured(Path::new(&metadata.path)) {
+                Some(loudness) => Ok(loudness),
+                None => {
+                    self.measure_in_background(&metadata.path);
+                    return 1.0;
+                }
+            },
+            VoiceInput::Generated(sound) => {
+                let cache = Arc::clone(&self.loudness);
+                let sound = sound.clone();
+                tokio::task::spawn_blocking(move || cache.analyze_generated(&sound))
+                    .await
+                    .map_err(|_| AudioError::EngineUnavailable)
+            }
+        };
+        match measured {
+            Ok(loudness) => loudness.normalization_gain(),
+            Err(err) => {
+                eprintln!("Failed to measure loudness of {}: {}", input, err);
+                1.0
+            }
+        }
+    }
+
     pub async fn play<P: Into<PathBuf>>(
         &self,
         path: P,
         volume_percent: u8,
     ) -> Result<PlaybackState, AudioError> {
-        self.play_with_fade(path, volume_percent, Duration::from_millis(400)).await
+        self.play_with_fade(path, volume_percent, DEFAULT_FADE_IN).await
     }
 
     pub async fn play_with_fade<P: Into<PathBuf>>(
@@ -145,17 +309,108 @@ impl AudioService {
         volume_percent: u8,
         fade_duration: Duration,
     ) -> Result<PlaybackState, AudioError> {
-        let path = path.into();
-        let metadata = self.validator.validate(&path)?;
+        self.send_play(
+            VoiceInput::File(path.into()),
+            volume_percent,
+            fade_duration,
+            PlaybackOptions::default(),
+            None,
+        )
+        .await
+    }
+
+    /// Plays with a schedule's playback options, e.g. a crescendo.
+    pub async fn play_with_options<P: Into<PathBuf>>(
+        &self,
+        path: P,
+        volume_percent: u8,
+        options: PlaybackOptions,
+    ) -> Result<PlaybackState, AudioError> {
+        self.play_with_preroll(path, volume_percent, options, None)
+            .await
+    }
+
+    /// Like [`play_with_options`](Self::play_with_options), with `preroll`
+    /// queued ahead of the file so the two play back to back. The schedule's
+    /// fade or crescendo starts with the file itself.
+    pub async fn play_with_preroll<P: Into<PathBuf>>(
+        &self,
+        path: P,
+        volume_percent: u8,
+        options: PlaybackOptions,
+        preroll: Option<PreRoll>,
+    ) -> Result<PlaybackState, AudioError> {
+        options.validate()?;
+        self.send_play(
+            VoiceInput::File(path.into()),
+            volume_percent,
+            DEFAULT_FADE_IN,
+            options,
+            preroll,
+        )
+        .await
+    }
+
+    /// Plays a generated sound with a schedule's playback options, after
+    /// `preroll` if given.
+    pub async fn play_generated(
+        &self,
+        sound: GeneratedSound,
+        volume_percent: u8,
+        options: PlaybackOptions,
+        preroll: Option<PreRoll>,
+    ) -> Result<PlaybackState, AudioError> {
+        options.validate()?;
+        sound.validate()?;
+        self.send_play(
+            VoiceInput::Generated(sound),
+            volume_percent,
+            DEFAULT_FADE_IN,
+            options,
+            preroll,
+        )
+        .await
+    }
+
+    async fn send_play(
+        &self,
+        input: VoiceInput,
+        volume_percent: u8,
+        fade_duration: Duration,
+        options: PlaybackOptions,
+        preroll: Option<PreRoll>,
+    ) -> Result<PlaybackState, AudioError> {
+        let metadata = match &input {
+            VoiceInput::File(path) => self.validator.validate(path)?,
+            VoiceInput::Generated(sound) => sound.metadata(),
+        };
+        if let Some(trim) = &options.trim {
+            trim.validate(metadata.duration_ms)?;
+        }
+        if let Some(preroll) = &preroll {
+            self.validator.validate(&preroll.path)?;
+        }
         let volume = Self::volume_from_percent(volume_percent);
+        let gain = if options.normalize {
+            self.normalization_gain(&input, &metadata).await
+        } else {
+            1.0
+        };
+        let device = options.output_device.clone().or_else(|| self.default_device());
         let (tx, rx) = oneshot::channel();
 
         self.commands
             .send(AudioCommand::Play {
-                path,
-                metadata,
-                volume,
-                fade_duration,
+                voice: Box::new(VoiceRequest {
+                    input,
+                    metadata,
+                    volume,
+                    gain,
+                    fade_duration,
+                    options,
+                    device,
+                    preroll,
+                }),
                 respond_to: tx,
             })
             .map_err(|_| AudioError::EngineUnavailable)?;
@@ -172,11 +427,120 @@ impl AudioService {
         rx.await.map_err(|_| AudioError::EngineUnavailable)?
     }
 
+    /// Fades out one voice, leaving any others playing.
+    pub async fn stop_voice(&self, id: impl Into<String>) -> Result<PlaybackState, AudioError> {
+        let (tx, rx) = oneshot::channel();
+        self.commands
+            .send(AudioCommand::StopVoice {
+                id: id.into(),
+                respond_to: tx,
+            })
+            .map_err(|_| AudioError::EngineUnavailable)?;
+
+        rx.await.map_err(|_| AudioError::EngineUnavailable)?
+    }
+
+    /// Pauses the voice `id`, or everything playing when `id` is `None`.
+    pub async fn pause(&self, id: Option<String>) -> Result<PlaybackState, AudioError> {
+        self.request(|respond_to| AudioCommand::Pause { id, respond_to })
+            .await?
+    }
+
+    /// Resumes the voice `id`, or everything paused when `id` is `None`.
+    pub async fn resume(&self, id: Option<String>) -> Result<PlaybackState, AudioError> {
+        self.request(|respond_to| AudioCommand::Resume { id, respond_to })
+            .await?
+    }
+
+    /// Moves the voice `id`, or the latest one when `id` is `None`, to
+    /// `position` within its (trimmed) file.
+    pub async fn seek(
+        &self,
+        id: Option<String>,
+        position: Duration,
+    ) -> Result<PlaybackState, AudioError> {
+        self.request(|respond_to| AudioCommand::Seek {
+            id,
+            position,
+            respond_to,
+        })
+        .await?
+    }
+
+    /// Position and length of every voice playing.
+    pub async fn progress(&self) -> Result<Vec<VoiceProgress>, AudioError> {
+        self.request(|respond_to| AudioCommand::Progress { respond_to })
+            .await
+    }
+
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> AudioCommand,
+    ) -> Result<T, AudioError> {
+        let (tx, rx) = oneshot::channel();
+        self.commands
+            .send(command(tx))
+            .map_err(|_| AudioError::EngineUnavailable)?;
+
+        rx.await.map_err(|_| AudioError::EngineUnavailable)
+    }
+
     pub async fn status(&self) -> PlaybackState {
         self.state
             .read()
             .map(|state| state.clone())
-            .unwrap_or_else(|_| PlaybackState::idle())
+            .unwrap_or_else(|poisoned| poisoned.into_inner().clone())
+    }
+
+    pub async fn output_status(&self) -> OutputStatus {
+        self.status().await.output
+    }
+
+    /// Receives a [`PlaybackCompletion`] each time a voice stops playing.
+    pub fn subscribe_completions(&self) -> broadcast::Receiver<PlaybackCompletion> {
+        self.completions.sender.subscribe()
+    }
+
+    /// Waits for the voice `id` to stop playing. Returns `None` if no such
+    /// voice is playing or has recently finished.
+    pub async fn wait_for_completion(&self, id: &str) -> Option<PlaybackCompletion> {
+        let mut completions = self.subscribe_completions();
+        if let Some(completion) = self.completions.recent(id) {
+            return Some(completion);
+        }
+        if !self.status().await.voices.iter().any(|voice| voice.id == id) {
+            return None;
+        }
+
+        loop {
+            match completions.recv().await {
+                Ok(completion) if completion.id == id => return Some(completion),
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    if let Some(completion) = self.completions.recent(id) {
+                        return Some(completion);
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Sets the device used when a schedule does not name one. `None` plays
+    /// through the system default.
+    pub fn set_default_device(&self, name: Option<String>) {
+        let mut guard = self
+            .default_device
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        *guard = name.filter(|name| !name.trim().is_empty());
+    }
+
+    pub fn default_device(&self) -> Option<String> {
+        self.default_device
+            .read()
+            .map(|name| name.clone())
+            .unwrap_or_else(|poisoned| poisoned.into_inner().clone())
     }
 
     fn volume_from_percent(volume_percent: u8) -> f32 {
@@ -184,15 +548,252 @@ impl AudioService {
     }
 }
 
+/// Owns the player on the audio thread, reopening the output when it is
+/// missing or lost.
+struct OutputSupervisor {
+    backend: OutputBackend,
+    player: Option<AudioPlayer>,
+    last_attempt: Instant,
+    state: Arc<RwLock<PlaybackState>>,
+    completions: Arc<Completions>,
+}
+
+impl OutputSupervisor {
+    fn new(
+        backend: OutputBackend,
+        state: Arc<RwLock<PlaybackState>>,
+        completions: Arc<Completions>,
+    ) -> Self {
+        let mut supervisor = Self {
+            backend,
+            player: None,
+            last_attempt: Instant::now(),
+            state,
+            completions,
+        };
+        if let Err(err) = supervisor.reopen() {
+            eprintln!("Audio output unavailable, will keep retrying: {}", err);
+        }
+        supervisor
+    }
+
+    fn run(&mut self, commands: Receiver<AudioCommand>) {
+        loop {
+            // Poll often while anything plays so completions are prompt.
+            let playing = self.player.as_ref().is_some_and(AudioPlayer::is_playing);
+            let timeout = if playing {
+                VOICE_POLL_INTERVAL
+            } else {
+                OUTPUT_RETRY_INTERVAL
+            };
+            match commands.recv_timeout(timeout) {
+                Ok(command) => self.handle(command),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            self.check_lost();
+            self.collect_finished();
+            let retry_due = self.last_attempt.elapsed() >= OUTPUT_RETRY_INTERVAL;
+            if self.player.is_none() && retry_due && self.reopen().is_ok() {
+                eprintln!("Audio output recovered ({})", self.backend);
+            }
+        }
+    }
+
+    fn handle(&mut self, command: AudioCommand) {
+        match command {
+            AudioCommand::Play { voice, respond_to } => {
+                let result = self.player().and_then(|player| player.play(*voice));
+                let result = result.map(|(_, replaced)| {
+                    let state = self.refresh_voices();
+                    self.publish(replaced);
+                    state
+                });
+                let _ = respond_to.send(result);
+            }
+            AudioCommand::Stop { respond_to } => {
+                let stopped = self
+                    .player
+                    .as_mut()
+                    .map(|player| player.stop(FADE_OUT))
+                    .unwrap_or_default();
+                let state = self.refresh_voices();
+                self.publish(stopped);
+                let _ = respond_to.send(Ok(state));
+            }
+            AudioCommand::StopVoice { id, respond_to } => {
+                let stopped = self
+                    .player
+                    .as_mut()
+                    .and_then(|player| player.stop_voice(&id, FADE_OUT));
+                let result = match stopped {
+                    Some(stopped) => {
+                        let state = self.refresh_voices();
+                        self.publish(vec![stopped]);
+                        Ok(state)
+                    }
+                    None => Err(AudioError::VoiceNotFound(id)),
+                };
+                let _ = respond_to.send(result);
+            }
+            AudioCommand::Pause { id, respond_to } => {
+                let result = self.with_voices(|player| player.pause(id.as_deref()));
+                let _ = respond_to.send(result);
+            }
+            AudioCommand::Resume { id, respond_to } => {
+                let result = self.with_voices(|player| player.resume(id.as_deref()));
+                let _ = respond_to.send(result);
+            }
+            AudioCommand::Seek {
+                id,
+                position,
+                respond_to,
+            } => {
+                let result = self.with_voices(|player| player.seek(id.as_deref(), position));
+                let _ = respond_to.send(result);
+            }
+            AudioCommand::Progress { respond_to } => {
+                let progress = self
+                    .player
+                    .as_ref()
+                    .map(AudioPlayer::progress)
+                    .unwrap_or_default();
+                let _ = respond_to.send(progress);
+            }
+        }
+    }
+
+    /// Applies `change` to the playing voices and returns the new state.
+    fn with_voices(
+        &mut self,
+        change: impl FnOnce(&mut AudioPlayer) -> Result<(), AudioError>,
+    ) -> Result<PlaybackState, AudioError> {
+        let player = self.player.as_mut().ok_or(AudioError::NothingPlaying)?;
+        change(player)?;
+        Ok(self.refresh_voices())
+    }
+
+    /// Publishes the voices that played to the end and drops them from the
+    /// state, so `is_playing` goes false as soon as the sound does.
+    fn collect_finished(&mut self) {
+        let finished = self
+            .player
+            .as_mut()
+            .map(AudioPlayer::take_finished)
+            .unwrap_or_default();
+        if !finished.is_empty() {
+            self.refresh_voices();
+            self.publish(finished);
+        }
+    }
+
+    /// Announces stopped voices; the state is updated first so subscribers
+    /// see them gone.
+    fn publish(&self, voices: Vec<FinishedVoice>) {
+        for voice in voices {
+            self.completions.publish(voice.into());
+        }
+    }
+
+    /// Copies the player's voices into the shared state.
+    fn refresh_voices(&mut self) -> PlaybackState {
+        let voices = self
+            .player
+            .as_ref()
+            .map(AudioPlayer::voices)
+            .unwrap_or_default();
+        self.update(|state| state.set_voices(voices))
+    }
+
+    /// The open player, trying the output again straight away if it is
+    /// missing so a newly connected device is used at once.
+    fn player(&mut self) -> Result<&mut AudioPlayer, AudioError> {
+        self.check_lost();
+        if self.player.is_none() {
+            self.reopen()?;
+        }
+        self.player.as_mut().ok_or(AudioError::NoOutputDevice)
+    }
+
+    /// Reopens the player if the default output went away, and ends the
+    /// voices on any named device that did.
+    fn check_lost(&mut self) {
+        if self.player.as_ref().is_some_and(AudioPlayer::is_output_lost) {
+            eprintln!("Audio output device was disconnected; retrying");
+            let abandoned = self
+                .player
+                .take()
+                .map(|mut player| player.abandon(FinishReason::OutputLost))
+                .unwrap_or_default();
+            self.last_attempt = Instant::now();
+            self.update(|state| {
+                state.set_voices(Vec::new());
+                state.output = OutputStatus::new(
+                    &self.backend,
+                    Some("audio output device was disconnected".into()),
+                );
+            });
+            self.publish(abandoned);
+            return;
+        }
+
+        let lost = self
+            .player
+            .as_mut()
+            .map(AudioPlayer::drop_lost_devices)
+            .unwrap_or_default();
+        if !lost.is_empty() {
+            self.refresh_voices();
+            self.publish(lost);
+        }
+    }
+
+    fn reopen(&mut self) -> Result<(), AudioError> {
+        self.last_attempt = Instant::now();
+        let result = AudioPlayer::new(&self.backend);
+        let error = result.as_ref().err().map(|err| err.to_string());
+
+        let changed = self
+            .state
+            .read()
+            .map(|state| state.output.available != error.is_none() || state.output.error != error)
+            .unwrap_or(true);
+        if changed {
+            let status = OutputStatus::new(&self.backend, error);
+            self.update(|state| state.output = status);
+        }
+
+        self.player = Some(result?);
+        Ok(())
+    }
+
+    fn update(&self, change: impl FnOnce(&mut PlaybackState)) -> PlaybackState {
+        let mut guard = self
+            .state
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        change(&mut guard);
+        guard.clone()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::audio::options::{Repeat, Trim};
+    use crate::audio::output::RENDER_SAMPLE_RATE;
 
-    fn create_service() -> Result<AudioService, AudioError> {
-        AudioService::with_validator(AudioValidator::new(20 * 1024 * 1024))
+    fn create_service(backend: OutputBackend) -> AudioService {
+        AudioService::with_backend(AudioValidator::new(20 * 1024 * 1024), backend).unwrap()
     }
 
+    /// Writes a tenth of a second of a constant half-scale mono signal.
     fn write_sample_wav(path: &Path) {
+        write_wav_of_length(path, Duration::from_millis(100));
+    }
+
+    fn write_wav_of_length(path: &Path, length: Duration) {
         let spec = hound::WavSpec {
             channels: 1,
             sample_rate: 44_100,
@@ -200,19 +801,15 @@ mod tests {
             sample_format: hound::SampleFormat::Int,
         };
         let mut writer = hound::WavWriter::create(path, spec).unwrap();
-        for _ in 0..(spec.sample_rate / 10) {
-            writer.write_sample(0i16).unwrap();
+        for _ in 0..(spec.sample_rate as u64 * length.as_millis() as u64 / 1000) {
+            writer.write_sample(i16::MAX / 2).unwrap();
         }
         writer.finalize().unwrap();
     }
 
     #[tokio::test]
     async fn play_and_stop_updates_state() {
-        let service = match create_service() {
-            Ok(service) => service,
-            Err(AudioError::NoOutputDevice) => return,
-            Err(other) => panic!("unexpected error initialising audio service: {other:?}"),
-        };
+        let service = create_service(OutputBackend::Null);
 
         let temp_dir = tempfile::TempDir::new().unwrap();
         let file_path = temp_dir.path().join("service.wav");
@@ -227,15 +824,483 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn status_returns_idle_when_not_playing() {
-        let service = match create_service() {
-            Ok(service) => service,
-            Err(AudioError::NoOutputDevice) => return,
-            Err(other) => panic!("unexpected error initialising audio service: {other:?}"),
+    async fn finished_playback_is_reported_and_clears_is_playing() {
+        let service = create_service(OutputBackend::Null);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("tone.wav");
+        write_sample_wav(&file_path);
+
+        let mut completions = service.subscribe_completions();
+        let state = service
+            .play_with_fade(file_path.clone(), 80, Duration::ZERO)
+            .await
+            .unwrap();
+        let id = state.current.unwrap().id;
+
+        let completion = tokio::time::timeout(Duration::from_secs(2), service.wait_for_completion(&id))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(completion.reason, FinishReason::Finished);
+        assert!((100..600).contains(&completion.played_ms), "played {}ms", completion.played_ms);
+        assert_eq!(completions.recv().await.unwrap().id, id);
+        assert!(!service.status().await.is_playing);
+
+        // Finished voices are still found by late waiters.
+        assert!(service.wait_for_completion(&id).await.is_some());
+        assert!(service.wait_for_completion("unknown").await.is_none());
+
+        let looping = PlaybackOptions {
+            repeat: Repeat::Forever,
+            ..Default::default()
         };
+        let state = service
+            .play_with_options(file_path, 80, looping)
+            .await
+            .unwrap();
+        service.stop().await.unwrap();
+        let stopped = service
+            .wait_for_completion(&state.current.unwrap().id)
+            .await
+            .unwrap();
+        assert_eq!(stopped.reason, FinishReason::Stopped);
+    }
+
+    #[tokio::test]
+    async fn pause_resume_and_seek_move_the_reported_position() {
+        let service = create_service(OutputBackend::Null);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("long.wav");
+        write_wav_of_length(&file_path, Duration::from_secs(3));
+
+        service.play(file_path.clone(), 80).await.unwrap();
+        let state = service.pause(None).await.unwrap();
+        assert!(state.is_playing);
+        assert!(state.voices[0].paused);
+
+        let before = service.progress().await.unwrap()[0].position_ms;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let progress = &service.progress().await.unwrap()[0];
+        assert!(progress.paused);
+        assert_eq!(progress.position_ms, before);
+        assert_eq!(progress.duration_ms, Some(3000));
+
+        service.seek(None, Duration::from_secs(2)).await.unwrap();
+        let position = service.progress().await.unwrap()[0].position_ms;
+        assert!((2000..2100).contains(&position), "at {position}ms");
+        let err = service.seek(None, Duration::from_secs(4)).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "failed to seek audio stream: position 0:04 is past the end of the file (0:03)"
+        );
+
+        let state = service.resume(None).await.unwrap();
+        assert!(!state.voices[0].paused);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let position = service.progress().await.unwrap()[0].position_ms;
+        assert!(position > 2100, "at {position}ms");
+
+        // Seeking a trimmed file is relative to the trim and keeps its end.
+        let trimmed = PlaybackOptions {
+            trim: Some(Trim {
+                start_ms: 1000,
+                end_ms: Some(2500),
+            }),
+            ..Default::default()
+        };
+        let state = service
+            .play_with_options(file_path, 80, trimmed)
+            .await
+            .unwrap();
+        let id = state.current.unwrap().id;
+        assert!(service.seek(None, Duration::from_millis(1500)).await.is_err());
+        service.seek(None, Duration::from_millis(1300)).await.unwrap();
+        let completion = tokio::time::timeout(Duration::from_secs(1), service.wait_for_completion(&id))
+            .await
+            .expect("the trimmed file stops at its end point")
+            .unwrap();
+        assert_eq!(completion.reason, FinishReason::Finished);
+
+        assert!(matches!(
+            service.pause(None).await,
+            Err(AudioError::NothingPlaying)
+        ));
+    }
+
+    #[tokio::test]
+    async fn status_returns_idle_when_not_playing() {
+        let service = create_service(OutputBackend::Null);
 
         let status = service.status().await;
         assert!(!status.is_playing);
         assert!(status.current.is_none());
     }
+
+    #[tokio::test]
+    async fn starts_degraded_and_recovers_when_output_appears() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("tone.wav");
+        let output_dir = temp_dir.path().join("renders");
+        write_sample_wav(&file_path);
+
+        let service = create_service(OutputBackend::Wav(output_dir.join("out.wav")));
+        let output = service.output_status().await;
+        assert!(!output.available);
+        assert!(output.error.is_some());
+
+        let err = service.play(file_path.clone(), 80).await.unwrap_err();
+        assert!(err.is_output_unavailable(), "unexpected error: {err}");
+
+        std::fs::create_dir(&output_dir).unwrap();
+        let state = service.play(file_path, 80).await.unwrap();
+        assert!(state.is_playing);
+        assert!(state.output.available);
+        assert!(state.output.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn mixed_voices_play_together_and_stop_independently() {
+        let service = create_service(OutputBackend::Null);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("tone.wav");
+        write_sample_wav(&file_path);
+        let looping = PlaybackOptions {
+            repeat: Repeat::Forever,
+            ..Default::default()
+        };
+        let mixed = PlaybackOptions {
+            mix: true,
+            ..looping.clone()
+        };
+
+        let ambience = service
+            .play_with_options(file_path.clone(), 40, looping.clone())
+            .await
+            .unwrap();
+        let ambience_id = ambience.current.unwrap().id;
+        let state = service
+            .play_with_options(file_path.clone(), 80, mixed)
+            .await
+            .unwrap();
+        assert_eq!(state.voices.len(), 2);
+        let chime_id = state.current.unwrap().id;
+
+        let state = service.stop_voice(&chime_id).await.unwrap();
+        assert!(state.is_playing);
+        assert_eq!(state.voices.len(), 1);
+        assert_eq!(state.voices[0].id, ambience_id);
+        assert!(service.stop_voice(&chime_id).await.is_err());
+
+        // Without `mix` a new sound replaces everything playing.
+        let state = service
+            .play_with_options(file_path, 80, looping)
+            .await
+            .unwrap();
+        assert_eq!(state.voices.len(), 1);
+        assert_ne!(state.voices[0].id, ambience_id);
+    }
+
+    #[tokio::test]
+    async fn failed_preroll_leaves_the_playing_voice_alone() {
+        let service = create_service(OutputBackend::Null);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("tone.wav");
+        write_sample_wav(&file_path);
+        let looping = PlaybackOptions {
+            repeat: Repeat::Forever,
+            ..Default::default()
+        };
+
+        let playing = service
+            .play_with_options(file_path.clone(), 80, looping.clone())
+            .await
+            .unwrap();
+        let playing_id = playing.current.unwrap().id;
+
+        let missing = PreRoll {
+            path: temp_dir.path().join("missing.wav"),
+            volume_percent: 100,
+            gap: Duration::ZERO,
+        };
+        assert!(service
+            .play_with_preroll(file_path, 80, looping, Some(missing))
+            .await
+            .is_err());
+
+        let state = service.status().await;
+        assert_eq!(state.voices.len(), 1);
+        assert_eq!(state.voices[0].id, playing_id);
+    }
+
+    #[tokio::test]
+    async fn missing_device_falls_back_to_the_default_output() {
+        let service = create_service(OutputBackend::Null);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("tone.wav");
+        write_sample_wav(&file_path);
+
+        let state = service.play(file_path.clone(), 80).await.unwrap();
+        let current = state.current.unwrap();
+        assert_eq!(current.output_device, "null");
+        assert!(current.fallback.is_none());
+
+        service.set_default_device(Some("Living Room".into()));
+        let options = PlaybackOptions {
+            output_device: Some("Bedroom Speaker".into()),
+            ..Default::default()
+        };
+        let state = service
+            .play_with_options(file_path, 80, options)
+            .await
+            .unwrap();
+        let current = state.current.unwrap();
+        assert_eq!(current.output_device, "null");
+        assert_eq!(
+            current.fallback.as_deref(),
+            Some("output device 'Bedroom Speaker' was not found; played on 'null' instead")
+        );
+    }
+
+    #[tokio::test]
+    async fn wav_backend_renders_what_was_played() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("tone.wav");
+        let rendered_path = temp_dir.path().join("rendered.wav");
+        write_sample_wav(&file_path);
+
+        let service = create_service(OutputBackend::Wav(rendered_path.clone()));
+        service
+            .play_with_fade(file_path, 100, Duration::ZERO)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        drop(service);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut reader = hound::WavReader::open(&rendered_path).unwrap();
+        assert_eq!(reader.spec().channels, crate::audio::output::RENDER_CHANNELS);
+        let samples = reader
+            .samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        // The mono tenth of a second comes out on both channels, followed by
+        // the idle sink's silence.
+        let audible = samples.iter().filter(|sample| **sample != 0.0).count();
+        let full = samples
+            .iter()
+            .filter(|sample| (**sample - 0.5).abs() < 0.01)
+            .count();
+        assert!(audible <= 4_410 * 2, "{audible} audible samples");
+        assert!(full > 4_410 * 2 * 9 / 10, "only {full} samples at full volume");
+    }
+
+    #[tokio::test]
+    async fn preroll_plays_on_the_same_sink_before_the_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("tone.wav");
+        let rendered_path = temp_dir.path().join("rendered.wav");
+        write_sample_wav(&file_path);
+
+        let service = create_service(OutputBackend::Wav(rendered_path.clone()));
+        let preroll = PreRoll {
+            path: file_path.clone(),
+            volume_percent: 50,
+            gap: Duration::from_millis(200),
+        };
+        let state = service
+            .play_with_preroll(file_path, 100, PlaybackOptions::default(), Some(preroll))
+            .await
+            .unwrap();
+        assert_eq!(state.voices.len(), 1);
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        drop(service);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let samples = hound::WavReader::open(&rendered_path)
+            .unwrap()
+            .samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        // The pre-roll plays at its own volume, not the file's fade-in.
+        let preroll = samples
+            .iter()
+            .filter(|sample| (**sample - 0.25).abs() < 0.01)
+            .count();
+        assert!(preroll > 4_410 * 2 * 9 / 10, "only {preroll} pre-roll samples");
+
+        // Then the gap, then the file.
+        let started = samples.iter().position(|sample| *sample != 0.0).unwrap();
+        let first_silent = started
+            + samples[started..]
+                .iter()
+                .position(|sample| *sample == 0.0)
+                .unwrap();
+        let resumed = samples[first_silent..]
+            .iter()
+            .position(|sample| *sample != 0.0)
+            .expect("the file plays after the gap");
+        let gap_ms = resumed as u64 * 1000 / (RENDER_SAMPLE_RATE as u64 * 2);
+        assert!((190..400).contains(&gap_ms), "gap of {gap_ms}ms");
+    }
+
+    #[tokio::test]
+    async fn stopping_during_the_preroll_silences_it() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("tone.wav");
+        let preroll_path = temp_dir.path().join("announcement.wav");
+        let rendered_path = temp_dir.path().join("rendered.wav");
+        write_sample_wav(&file_path);
+        write_wav_of_length(&preroll_path, Duration::from_secs(2));
+
+        let service = create_service(OutputBackend::Wav(rendered_path.clone()));
+        let preroll = PreRoll {
+            path: preroll_path,
+            volume_percent: 100,
+            gap: Duration::ZERO,
+        };
+        service
+            .play_with_preroll(file_path, 100, PlaybackOptions::default(), Some(preroll))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        service.stop().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(800)).await;
+        drop(service);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let samples = hound::WavReader::open(&rendered_path)
+            .unwrap()
+            .samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        // Well under the announcement's two seconds, and none of the file.
+        let audible = samples.iter().filter(|sample| **sample != 0.0).count();
+        let audible_ms = audible as u64 * 1000 / (RENDER_SAMPLE_RATE as u64 * 2);
+        assert!(audible_ms < 600, "{audible_ms}ms audible after stopping");
+    }
+
+    #[tokio::test]
+    async fn normalized_playback_brings_quiet_files_up_to_the_target() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("quiet.wav");
+        let rendered_path = temp_dir.path().join("rendered.wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 44_100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&file_path, spec).unwrap();
+        for frame in 0..44_100 {
+            let phase = 2.0 * std::f32::consts::PI * 997.0 * frame as f32 / 44_100.0;
+            writer
+                .write_sample((0.1 * phase.sin() * i16::MAX as f32) as i16)
+                .unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let service = create_service(OutputBackend::Wav(rendered_path.clone()));
+        let loudness = service.validate(&file_path).await.unwrap().loudness.unwrap();
+        let integrated = loudness.integrated_lufs.unwrap();
+        assert!((integrated + 23.0).abs() < 0.2, "{integrated}");
+
+        let options = PlaybackOptions {
+            normalize: true,
+            fade_in_ms: Some(0),
+            ..Default::default()
+        };
+        service
+            .play_with_options(file_path, 100, options)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        drop(service);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let peak = hound::WavReader::open(&rendered_path)
+            .unwrap()
+            .samples::<f32>()
+            .map(|sample| sample.unwrap().abs())
+            .fold(0.0, f32::max);
+        let expected = 0.1 * loudness.normalization_gain();
+        assert!(expected > 0.15);
+        assert!((peak - expected).abs() < 0.01, "peak {peak}, expected {expected}");
+    }
+
+    #[tokio::test]
+    async fn unmeasured_files_play_unadjusted_while_they_are_measured() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("tone.wav");
+        let rendered_path = temp_dir.path().join("rendered.wav");
+        write_sample_wav(&file_path);
+
+        let service = create_service(OutputBackend::Wav(rendered_path.clone()));
+        let options = PlaybackOptions {
+            normalize: true,
+            fade_in_ms: Some(0),
+            ..Default::default()
+        };
+        service
+            .play_with_options(&file_path, 100, options)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(service.loudness.measured(&file_path).is_some());
+        drop(service);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let peak = hound::WavReader::open(&rendered_path)
+            .unwrap()
+            .samples::<f32>()
+            .map(|sample| sample.unwrap().abs())
+            .fold(0.0, f32::max);
+        assert!((peak - 0.5).abs() < 0.01, "peak {peak}");
+    }
+
+    #[tokio::test]
+    async fn generated_sounds_play_without_a_file() {
+        let service = create_service(OutputBackend::Null);
+        let beeps = GeneratedSound::Beeps {
+            frequency_hz: 880.0,
+            count: 2,
+            duration_ms: 100,
+            spacing_ms: 100,
+            waveform: Default::default(),
+        };
+
+        let state = service
+            .play_generated(beeps, 80, PlaybackOptions::default(), None)
+            .await
+            .unwrap();
+        let current = state.current.unwrap();
+        assert_eq!(current.file_path, "2 beeps at 880 Hz");
+        assert_eq!(current.metadata.duration_ms, Some(300));
+
+        let completion =
+            tokio::time::timeout(Duration::from_secs(2), service.wait_for_completion(&current.id))
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(completion.reason, FinishReason::Finished);
+        assert!((300..800).contains(&completion.played_ms), "played {}ms", completion.played_ms);
+
+        let silent_chime = GeneratedSound::Chime {
+            frequencies_hz: Vec::new(),
+            duration_ms: 500,
+            spacing_ms: 100,
+        };
+        let err = service
+            .play_generated(silent_chime, 80, PlaybackOptions::default(), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AudioError::InvalidOptions(_)));
+    }
 }
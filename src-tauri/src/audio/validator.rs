@@ -7,8 +7,10 @@ use rodio::{decoder::DecoderBuilder, Decoder, Source};
 use serde::Serialize;
 
 use crate::audio::error::AudioValidationError;
+use crate::audio::loudness::Loudness;
 
-const SUPPORTED_EXTENSIONS: &[&str] = &["mp3", "wav", "flac", "ogg", "oga", "m4a", "aac"];
+/// Lower-case file extensions the player can decode.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["mp3", "wav", "flac", "ogg", "oga", "m4a", "aac"];
 const DEFAULT_MAX_FILE_SIZE_BYTES: u64 = 512 * 1024 * 1024; // 512MB
 
 #[derive(Debug, Clone, Serialize)]
@@ -21,6 +23,9 @@ pub struct AudioFileMetadata {
     pub sample_rate: u32,
     pub channels: u16,
     pub size_bytes: u64,
+    /// Measured by [`AudioService::validate`](crate::audio::AudioService::validate).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loudness: Option<Loudness>,
 }
 
 #[derive(Debug, Clone)]
@@ -36,6 +41,14 @@ impl Default for AudioValidator {
     }
 }
 
+/// Whether `path` has one of the [`SUPPORTED_EXTENSIONS`], ignoring case.
+pub fn has_supported_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| SUPPORTED_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
 impl AudioValidator {
     pub fn new(max_file_size_bytes: u64) -> Self {
         Self {
@@ -116,6 +129,7 @@ impl AudioValidator {
             sample_rate,
             channels,
             size_bytes,
+            loudness: None,
         })
     }
 }
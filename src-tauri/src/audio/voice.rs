@@ -0,0 +1,456 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::{ChannelCount, Sample, SampleRate, Source};
+
+use crate::audio::options::{RampCurve, Trim};
+use crate::audio::player::VoiceInput;
+
+/// Shared between a [`VoiceSource`] on the output thread and the player.
+#[derive(Default)]
+pub(crate) struct VoiceControl {
+    /// Fade-out length requested by the player, picked up on the next frame.
+    fade_out: Mutex<Option<Duration>>,
+    fade_out_requested: AtomicBool,
+    /// Set once the file begins, after any pre-roll.
+    started: AtomicBool,
+    /// Frames into the current play of the file, for progress reporting.
+    position: AtomicU64,
+    sample_rate: AtomicU32,
+}
+
+impl VoiceControl {
+    /// A voice with a pre-roll counts as started only once its file begins.
+    pub(crate) fn new(has_preroll: bool) -> Self {
+        Self {
+            started: AtomicBool::new(!has_preroll),
+            ..Default::default()
+        }
+    }
+
+    /// Fades the voice out from wherever its volume is, then ends it.
+    pub(crate) fn fade_out(&self, duration: Duration) {
+        *self
+            .fade_out
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(duration);
+        self.fade_out_requested.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn has_started(&self) -> bool {
+        self.started.load(Ordering::Relaxed)
+    }
+
+    /// Position within the current play of the file.
+    pub(crate) fn position(&self) -> Duration {
+        let rate = self.sample_rate.load(Ordering::Relaxed);
+        if rate == 0 {
+            return Duration::ZERO;
+        }
+        let frames = self.position.load(Ordering::Relaxed);
+        Duration::from_secs_f64(frames as f64 / rate as f64)
+    }
+
+    fn take_fade_out(&self) -> Option<Duration> {
+        if !self.fade_out_requested.swap(false, Ordering::Acquire) {
+            return None;
+        }
+        self.fade_out
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take()
+    }
+}
+
+/// A change of gain from `from` to `to` over `duration`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Envelope {
+    pub from: f32,
+    pub to: f32,
+    pub duration: Duration,
+    pub curve: RampCurve,
+}
+
+struct Ramp {
+    envelope: Envelope,
+    frames: u64,
+    elapsed: u64,
+}
+
+impl Ramp {
+    fn new(envelope: Envelope, sample_rate: SampleRate) -> Self {
+        Self {
+            envelope,
+            frames: (envelope.duration.as_secs_f64() * sample_rate as f64) as u64,
+            elapsed: 0,
+        }
+    }
+
+    /// Gain for the next frame; `None` once the ramp is complete.
+    fn next_gain(&mut self) -> Option<f32> {
+        if self.elapsed >= self.frames {
+            return None;
+        }
+        let progress = self.elapsed as f32 / self.frames as f32;
+        self.elapsed += 1;
+        let Envelope {
+            from, to, curve, ..
+        } = self.envelope;
+        Some(from + (to - from) * curve.apply(progress))
+    }
+}
+
+/// Plays the file again each time it runs out.
+pub(crate) struct Requeue {
+    pub input: VoiceInput,
+    pub trim: Option<Trim>,
+    /// Plays still to go; `None` repeats until stopped.
+    pub remaining: Option<u32>,
+    /// Keeps repeating at least until playback has run this long.
+    pub until: Duration,
+}
+
+impl Requeue {
+    fn wants_more(&self, elapsed: Duration) -> bool {
+        self.remaining.is_none_or(|remaining| remaining > 0) || elapsed < self.until
+    }
+}
+
+/// How a voice's volume changes over its life.
+pub(crate) struct VoiceShape {
+    /// Fade-in or crescendo at the start.
+    pub start: Envelope,
+    /// Fades out and ends once playback has run this long.
+    pub stop_at: Option<Duration>,
+    /// Length and curve of the fade-out at `stop_at` or on request.
+    pub fade_out: Duration,
+    pub fade_curve: RampCurve,
+}
+
+/// A voice's file, its repeats and its volume envelope as a single source.
+/// The gain is worked out per frame, so fades are exact and take effect on
+/// the very next frame when requested; nothing runs on another thread.
+pub(crate) struct VoiceSource {
+    input: Box<dyn Source + Send>,
+    control: Arc<VoiceControl>,
+    shape: VoiceShape,
+    requeue: Option<Requeue>,
+    ramp: Option<Ramp>,
+    /// Ends the voice when the current ramp finishes.
+    ending: bool,
+    gain: f32,
+    /// Frames played in total, across repeats.
+    frames: u64,
+    /// Frames into the current play of the file.
+    position: u64,
+    /// Sample within the current frame.
+    channel: ChannelCount,
+}
+
+impl VoiceSource {
+    pub(crate) fn new(
+        input: Box<dyn Source + Send>,
+        control: Arc<VoiceControl>,
+        shape: VoiceShape,
+        requeue: Option<Requeue>,
+    ) -> Self {
+        control
+            .sample_rate
+            .store(input.sample_rate(), Ordering::Relaxed);
+        let ramp = Ramp::new(shape.start, input.sample_rate());
+        Self {
+            input,
+            control,
+            gain: shape.start.from,
+            shape,
+            requeue,
+            ramp: Some(ramp),
+            ending: false,
+            frames: 0,
+            position: 0,
+            channel: 0,
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(self.frames as f64 / self.input.sample_rate() as f64)
+    }
+
+    fn begin_fade_out(&mut self, duration: Duration) {
+        let envelope = Envelope {
+            from: self.gain,
+            to: 0.0,
+            duration,
+            curve: self.shape.fade_curve,
+        };
+        self.ramp = Some(Ramp::new(envelope, self.input.sample_rate()));
+        self.ending = true;
+        self.requeue = None;
+        self.shape.stop_at = None;
+    }
+
+    /// Works out the gain for the frame about to start. Returns `false` once
+    /// a fade-out has finished and the voice should end.
+    fn start_frame(&mut self) -> bool {
+        if self.frames == 0 {
+            self.control.started.store(true, Ordering::Relaxed);
+        }
+        if let Some(duration) = self.control.take_fade_out() {
+            self.begin_fade_out(duration);
+        }
+        if self
+            .shape
+            .stop_at
+            .is_some_and(|limit| self.elapsed() >= limit)
+        {
+            self.begin_fade_out(self.shape.fade_out);
+        }
+
+        match self.ramp.as_mut().map(Ramp::next_gain) {
+            Some(Some(gain)) => self.gain = gain,
+            Some(None) => {
+                self.gain = self.ramp.take().map_or(self.gain, |ramp| ramp.envelope.to);
+                if self.ending {
+                    return false;
+                }
+            }
+            None => {}
+        }
+
+        self.frames += 1;
+        true
+    }
+
+    /// Starts the next play of the file, if one is due.
+    fn next_input(&mut self) -> bool {
+        let elapsed = self.elapsed();
+        let Some(requeue) = self
+            .requeue
+            .as_mut()
+            .filter(|requeue| requeue.wants_more(elapsed))
+        else {
+            return false;
+        };
+
+        match requeue.input.open(requeue.trim) {
+            Ok(source)
+                if source.channels() == self.input.channels()
+                    && source.sample_rate() == self.input.sample_rate() =>
+            {
+                requeue.remaining = requeue.remaining.map(|left| left.saturating_sub(1));
+                self.input = source;
+                self.position = 0;
+                true
+            }
+            Ok(_) => {
+                eprintln!("{} changed format; not repeating it", requeue.input);
+                self.requeue = None;
+                false
+            }
+            Err(err) => {
+                eprintln!("Failed to repeat {}: {}", requeue.input, err);
+                self.requeue = None;
+                false
+            }
+        }
+    }
+}
+
+impl Iterator for VoiceSource {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.channel == 0 && !self.start_frame() {
+            return None;
+        }
+
+        let sample = match self.input.next() {
+            Some(sample) => sample,
+            None if self.channel == 0 && self.next_input() => self.input.next()?,
+            None => return None,
+        };
+        // Counted once the frame's input is known, so a repeat that resets
+        // the position still counts its own first frame.
+        if self.channel == 0 {
+            self.position += 1;
+            self.control
+                .position
+                .store(self.position, Ordering::Relaxed);
+        }
+        self.channel = (self.channel + 1) % self.input.channels();
+        Some(sample * self.gain)
+    }
+}
+
+impl Source for VoiceSource {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(position)?;
+        self.position = (position.as_secs_f64() * self.input.sample_rate() as f64) as u64;
+        self.control
+            .position
+            .store(self.position, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::generator::GENERATED_SAMPLE_RATE;
+    use crate::audio::{GeneratedSound, Waveform};
+    use rodio::buffer::SamplesBuffer;
+
+    fn shape(start: Envelope) -> VoiceShape {
+        VoiceShape {
+            start,
+            stop_at: None,
+            fade_out: Duration::from_millis(10),
+            fade_curve: RampCurve::Linear,
+        }
+    }
+
+    /// A second of a constant full-scale mono signal at 1 kHz.
+    fn ones() -> Box<dyn Source + Send> {
+        Box::new(SamplesBuffer::new(1, 1000, vec![1.0; 1000]))
+    }
+
+    #[test]
+    fn fades_in_frame_by_frame() {
+        let fade_in = Envelope {
+            from: 0.0,
+            to: 0.5,
+            duration: Duration::from_millis(100),
+            curve: RampCurve::Linear,
+        };
+        let control = Arc::new(VoiceControl::default());
+        let samples: Vec<f32> =
+            VoiceSource::new(ones(), Arc::clone(&control), shape(fade_in), None).collect();
+
+        assert_eq!(samples.len(), 1000);
+        assert_eq!(samples[0], 0.0);
+        assert!((samples[50] - 0.25).abs() < 1e-3);
+        assert!(samples[100..].iter().all(|sample| *sample == 0.5));
+        assert!(control.has_started());
+    }
+
+    #[test]
+    fn fade_out_takes_effect_on_the_next_frame_and_ends_the_voice() {
+        let instant = Envelope {
+            from: 1.0,
+            to: 1.0,
+            duration: Duration::ZERO,
+            curve: RampCurve::Linear,
+        };
+        let control = Arc::new(VoiceControl::default());
+        let mut source = VoiceSource::new(ones(), Arc::clone(&control), shape(instant), None);
+
+        assert_eq!(source.by_ref().take(200).last(), Some(1.0));
+        control.fade_out(Duration::from_millis(20));
+        let rest: Vec<f32> = source.collect();
+
+        assert_eq!(rest.len(), 20);
+        assert_eq!(rest[0], 1.0);
+        assert!(rest.windows(2).all(|pair| pair[1] < pair[0]));
+        assert_eq!(control.position(), Duration::from_millis(220));
+    }
+
+    /// Ten milliseconds of a full-scale mono signal, in the generator's
+    /// format so a generated tone can follow it.
+    fn short() -> Box<dyn Source + Send> {
+        Box::new(SamplesBuffer::new(1, GENERATED_SAMPLE_RATE, vec![1.0; 441]))
+    }
+
+    fn tone() -> VoiceInput {
+        VoiceInput::Generated(GeneratedSound::Tone {
+            frequency_hz: 440.0,
+            duration_ms: 10,
+            waveform: Waveform::Sine,
+        })
+    }
+
+    #[test]
+    fn repeats_a_set_number_of_times() {
+        let instant = Envelope {
+            from: 1.0,
+            to: 1.0,
+            duration: Duration::ZERO,
+            curve: RampCurve::Linear,
+        };
+        // `Repeat::Times(3)`: the first play and two more.
+        let requeue = Requeue {
+            input: tone(),
+            trim: None,
+            remaining: Some(2),
+            until: Duration::ZERO,
+        };
+        let control = Arc::new(VoiceControl::default());
+        let samples: Vec<f32> =
+            VoiceSource::new(short(), Arc::clone(&control), shape(instant), Some(requeue))
+                .collect();
+
+        assert_eq!(samples.len(), 3 * 441);
+        assert!(samples[..441].iter().all(|sample| *sample == 1.0));
+        // The position restarts with each play and counts all of it.
+        assert_eq!(control.position.load(Ordering::Relaxed), 441);
+    }
+
+    #[test]
+    fn loops_until_the_crescendo_completes() {
+        let crescendo = Envelope {
+            from: 0.1,
+            to: 1.0,
+            duration: Duration::from_millis(25),
+            curve: RampCurve::Linear,
+        };
+        let requeue = Requeue {
+            input: tone(),
+            trim: None,
+            remaining: Some(0),
+            until: crescendo.duration,
+        };
+        let control = Arc::new(VoiceControl::default());
+        let samples: Vec<f32> =
+            VoiceSource::new(short(), control, shape(crescendo), Some(requeue)).collect();
+
+        // Plays end at 10, 20 and 30 ms; only the third outlasts the ramp.
+        assert_eq!(samples.len(), 3 * 441);
+    }
+
+    #[test]
+    fn stops_at_the_limit_after_fading_out() {
+        let instant = Envelope {
+            from: 1.0,
+            to: 1.0,
+            duration: Duration::ZERO,
+            curve: RampCurve::Linear,
+        };
+        let limited = VoiceShape {
+            stop_at: Some(Duration::from_millis(300)),
+            ..shape(instant)
+        };
+        let control = Arc::new(VoiceControl::default());
+        let samples: Vec<f32> = VoiceSource::new(ones(), control, limited, None).collect();
+
+        assert_eq!(samples.len(), 310);
+        assert_eq!(samples[299], 1.0);
+        assert!(samples[309] < 0.2);
+    }
+}
@@ -0,0 +1,13 @@
+use thiserror::Error;
+
+use crate::db::DatabaseError;
+
+#[derive(Debug, Error)]
+pub enum CalendarError {
+    #[error("database error: {0}")]
+    Database(#[from] DatabaseError),
+    #[error("failed to write calendar file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid holiday file: {0}")]
+    InvalidHolidays(String),
+}
@@ -0,0 +1,463 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, Timelike, Utc, Weekday};
+
+use crate::db::models::{AudioSource, RepeatType, Schedule};
+use crate::db::Database;
+use crate::scheduler::recurrence::{Frequency, RecurrenceRule, RecurrenceSet, WeekdayNum};
+
+use super::CalendarError;
+
+pub const FEED_PATH_SETTING: &str = "calendar_feed_path";
+
+const PRODUCT_ID: &str = "-//Resonatify//Schedules//EN";
+const MAX_LINE_OCTETS: usize = 75;
+
+/// Serializes schedules into a VCALENDAR with one VEVENT (plus a display
+/// VALARM) per schedule, or several where a custom interval needs more than
+/// one daily rule. Times are written as floating local times so the
+/// events follow the viewer's wall clock, as the scheduler does.
+///
+/// Schedules that can never fire (invalid time, empty weekly day list, invalid
+/// recurrence rule) are left out.
+pub fn schedules_to_ics(schedules: &[Schedule], generated_at: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".into(),
+        format!("PRODID:{PRODUCT_ID}"),
+        "CALSCALE:GREGORIAN".into(),
+        "X-WR-CALNAME:Resonatify".into(),
+    ];
+
+    for schedule in schedules {
+        if let Some(event) = schedule_event(schedule, generated_at) {
+            lines.extend(event);
+        }
+    }
+
+    lines.push("END:VCALENDAR".into());
+
+    let mut output = String::new();
+    for line in lines {
+        output.push_str(&fold_line(&line));
+        output.push_str("\r\n");
+    }
+    output
+}
+
+/// Rewrites the feed file configured under `calendar_feed_path` with all
+/// enabled schedules. Returns the path written, or `None` when no feed is
+/// configured.
+pub async fn refresh_feed(database: &Database) -> Result<Option<PathBuf>, CalendarError> {
+    let Some(setting) = database
+        .settings_repository()
+        .get(FEED_PATH_SETTING)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let path = setting.value.trim();
+    if path.is_empty() {
+        return Ok(None);
+    }
+
+    let schedules = database.schedule_repository().get_enabled().await?;
+    let path = PathBuf::from(path);
+    write_ics(&path, &schedules_to_ics(&schedules, Utc::now())).await?;
+
+    Ok(Some(path))
+}
+
+/// Writes the calendar through a temporary file so subscribers never read a
+/// half-written feed.
+pub async fn write_ics(path: &Path, contents: &str) -> Result<(), CalendarError> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let temp_path = path.with_extension("ics.tmp");
+    tokio::fs::write(&temp_path, contents).await?;
+    tokio::fs::rename(&temp_path, path).await?;
+
+    Ok(())
+}
+
+fn schedule_event(schedule: &Schedule, generated_at: DateTime<Utc>) -> Option<Vec<String>> {
+    let time = NaiveTime::parse_from_str(&schedule.scheduled_time, "%H:%M").ok()?;
+    let created = DateTime::parse_from_rfc3339(&schedule.created_at)
+        .map(|created| created.with_timezone(&Local))
+        .unwrap_or_else(|_| generated_at.with_timezone(&Local));
+    let created_date = created.date_naive();
+
+    // Each series becomes one VEVENT: its first date, its first time that
+    // day and its recurrence lines.
+    let series: Vec<(NaiveDate, NaiveTime, Vec<String>)> = match &schedule.repeat_type {
+        RepeatType::Once => {
+            let date = if created_date.and_time(time) >= created.naive_local() {
+                created_date
+            } else {
+                created_date.succ_opt()?
+            };
+            vec![(date, time, Vec::new())]
+        }
+        RepeatType::Custom {
+            interval_minutes,
+            until,
+        } => {
+            if *interval_minutes == 0 {
+                return None;
+            }
+            let until = match until {
+                Some(until) => NaiveTime::parse_from_str(until, "%H:%M").ok()?,
+                None => NaiveTime::from_hms_opt(23, 59, 0)?,
+            };
+            if until < time {
+                return None;
+            }
+            custom_interval_rules(time, until, *interval_minutes)
+                .into_iter()
+                .map(|(first, rule)| (created_date, first, vec![rule]))
+                .collect()
+        }
+        RepeatType::Rrule { rule } => {
+            let set: RecurrenceSet = rule.parse().ok()?;
+            let anchor = set
+                .start
+                .map(|start| start.local_date())
+                .unwrap_or(created_date);
+
+            // DTSTART must be the first instance of the rule; exclusions are
+            // carried separately as EXDATE so COUNT keeps its meaning.
+            let unexcluded = RecurrenceSet {
+                exdates: Vec::new(),
+                ..set.clone()
+            };
+            let first = unexcluded.occurrences(anchor, time).next()?;
+
+            let mut lines = vec![format!("RRULE:{}", set.rule)];
+            lines.extend(
+                set.exdates
+                    .iter()
+                    .map(|exdate| format!("EXDATE:{}", format_local(exdate.local_date(), time))),
+            );
+            vec![(first, time, lines)]
+        }
+        repeat => {
+            let set = RecurrenceSet {
+                start: None,
+                rule: date_rule(repeat)?,
+                exdates: Vec::new(),
+            };
+            let first = set
+                .occurrences(created_date, time)
+                .find(|date| date.and_time(time) >= created.naive_local())?;
+            vec![(first, time, vec![format!("RRULE:{}", set.rule)])]
+        }
+    };
+
+    let file_name = match &schedule.audio_source {
+        AudioSource::File => Path::new(&schedule.audio_file_path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&schedule.audio_file_path)
+            .to_string(),
+        AudioSource::Playlist { items, .. } => format!("a playlist of {} files", items.len()),
+        AudioSource::Directory { path, .. } => format!("a file from {path}"),
+        AudioSource::Generated { sound } => sound.to_string(),
+    };
+    let summary = escape_text(&schedule.name);
+
+    let description = escape_text(&format!(
+        "{}. Plays {file_name} at {}% volume",
+        schedule.describe(),
+        schedule.volume
+    ));
+
+    let mut lines = Vec::new();
+    let several = series.len() > 1;
+    for (index, (start, first_time, recurrence)) in series.into_iter().enumerate() {
+        let uid = if several {
+            format!("{}-{}@resonatify", schedule.id, index + 1)
+        } else {
+            format!("{}@resonatify", schedule.id)
+        };
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{uid}"),
+            format!("DTSTAMP:{}", generated_at.format("%Y%m%dT%H%M%SZ")),
+            format!("DTSTART:{}", format_local(start, first_time)),
+            format!("SUMMARY:{summary}"),
+            format!("DESCRIPTION:{description}"),
+        ]);
+        lines.extend(recurrence);
+        lines.extend([
+            "BEGIN:VALARM".to_string(),
+            "ACTION:DISPLAY".into(),
+            "TRIGGER:PT0S".into(),
+            format!("DESCRIPTION:{summary}"),
+            "END:VALARM".into(),
+        ]);
+        for alert in schedule.pre_alerts.iter().filter(|alert| alert.minutes_before > 0) {
+            lines.extend([
+                "BEGIN:VALARM".to_string(),
+                "ACTION:DISPLAY".into(),
+                format!("TRIGGER:-PT{}M", alert.minutes_before),
+                format!("DESCRIPTION:{summary}"),
+                "END:VALARM".into(),
+            ]);
+        }
+        lines.push("END:VEVENT".into());
+    }
+
+    Some(lines)
+}
+
+/// Daily rules for a custom interval, which restarts at `time` every day and
+/// runs until `until`. Minutes that fall in the same hours share a rule, so
+/// each rule's `BYHOUR` and `BYMINUTE` together give exactly its slots;
+/// intervals that do not divide an hour need several. Returns each rule with
+/// its first slot of the day.
+fn custom_interval_rules(
+    time: NaiveTime,
+    until: NaiveTime,
+    interval_minutes: u32,
+) -> Vec<(NaiveTime, String)> {
+    let minute_of_day = |time: NaiveTime| time.hour() * 60 + time.minute();
+    let mut hours_by_minute: BTreeMap<u32, BTreeSet<u32>> = BTreeMap::new();
+    for slot in (minute_of_day(time)..=minute_of_day(until)).step_by(interval_minutes as usize) {
+        hours_by_minute.entry(slot % 60).or_default().insert(slot / 60);
+    }
+
+    let mut minutes_by_hours: BTreeMap<BTreeSet<u32>, Vec<u32>> = BTreeMap::new();
+    for (minute, hours) in hours_by_minute {
+        minutes_by_hours.entry(hours).or_default().push(minute);
+    }
+
+    let join = |values: &mut dyn Iterator<Item = &u32>| {
+        values.map(u32::to_string).collect::<Vec<_>>().join(",")
+    };
+    let mut rules: Vec<(NaiveTime, String)> = minutes_by_hours
+        .into_iter()
+        .filter_map(|(hours, minutes)| {
+            let first = NaiveTime::from_hms_opt(*hours.first()?, *minutes.first()?, 0)?;
+            let rule = format!(
+                "RRULE:FREQ=DAILY;BYHOUR={};BYMINUTE={}",
+                join(&mut hours.iter()),
+                join(&mut minutes.iter())
+            );
+            Some((first, rule))
+        })
+        .collect();
+    rules.sort();
+    rules
+}
+
+/// Day-based repeat types expressed as the equivalent recurrence rule.
+fn date_rule(repeat: &RepeatType) -> Option<RecurrenceRule> {
+    let weekly = |days: &[Weekday]| {
+        let mut rule = RecurrenceRule::new(Frequency::Weekly);
+        rule.by_day = days
+            .iter()
+            .map(|weekday| WeekdayNum {
+                ordinal: None,
+                weekday: *weekday,
+            })
+            .collect();
+        rule
+    };
+
+    match repeat {
+        RepeatType::Daily => Some(RecurrenceRule::new(Frequency::Daily)),
+        RepeatType::Weekdays => Some(weekly(&[
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+        ])),
+        RepeatType::Weekends => Some(weekly(&[Weekday::Sat, Weekday::Sun])),
+        RepeatType::Weekly { days } if !days.is_empty() => Some(weekly(days)),
+        _ => None,
+    }
+}
+
+fn format_local(date: NaiveDate, time: NaiveTime) -> String {
+    date.and_time(time).format("%Y%m%dT%H%M%S").to_string()
+}
+
+fn escape_text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            other => escaped.push(other),
+        }
+    }
+    escaped
+}
+
+/// Folds a content line at 75 octets as required by RFC 5545, never splitting
+/// a UTF-8 sequence.
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + line.len() / MAX_LINE_OCTETS * 3);
+    let mut width = 0;
+
+    for ch in line.chars() {
+        let len = ch.len_utf8();
+        if width + len > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(ch);
+        width += len;
+    }
+
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::PreAlert;
+    use chrono::TimeZone;
+
+    fn schedule(repeat_type: RepeatType, name: &str) -> Schedule {
+        let created = Local.with_ymd_and_hms(2025, 11, 12, 10, 0, 0).unwrap();
+        Schedule {
+            id: "abc".into(),
+            name: name.into(),
+            audio_file_path: "/tmp/bell.mp3".into(),
+            scheduled_time: "09:30".into(),
+            enabled: true,
+            repeat_type,
+            volume: 80,
+            created_at: created.to_rfc3339(),
+            updated_at: created.to_rfc3339(),
+            last_run_at: None,
+            pre_alerts: Vec::new(),
+            holiday_calendar_id: None,
+            holiday_rule: Default::default(),
+            late_tolerance_seconds: None,
+            min_spacing_seconds: None,
+            dst_policy: Default::default(),
+            audio_source: Default::default(),
+            audio_source_position: 0,
+            playback: Default::default(),
+        }
+    }
+
+    fn generated_at() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 11, 12, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn exports_weekday_schedule_with_alarm() {
+        // Created Wednesday 10:00, so 09:30 that day has already passed.
+        let ics = schedules_to_ics(
+            &[schedule(RepeatType::Weekdays, "Stand-up")],
+            generated_at(),
+        );
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert!(ics.contains("UID:abc@resonatify\r\n"));
+        assert!(ics.contains("DTSTART:20251113T093000\r\n"));
+        assert!(ics.contains("RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR\r\n"));
+        assert!(ics.contains("BEGIN:VALARM\r\nACTION:DISPLAY\r\nTRIGGER:PT0S\r\n"));
+        assert!(ics.contains("DESCRIPTION:Every weekday at 09:30. Plays "));
+    }
+
+    #[test]
+    fn exports_pre_alerts_as_extra_alarms() {
+        let mut schedule = schedule(RepeatType::Daily, "Wake up");
+        schedule.pre_alerts = vec![PreAlert {
+            minutes_before: 10,
+            audio_file_path: None,
+            volume: None,
+        }];
+
+        let ics = schedules_to_ics(&[schedule], generated_at());
+
+        assert!(ics.contains("TRIGGER:-PT10M\r\n"));
+    }
+
+    #[test]
+    fn exports_rrule_schedule_with_first_instance_and_exdates() {
+        let ics = schedules_to_ics(
+            &[schedule(
+                RepeatType::Rrule {
+                    rule: "RRULE:FREQ=MONTHLY;BYDAY=1MO\nEXDATE;VALUE=DATE:20251201".into(),
+                },
+                "Review",
+            )],
+            generated_at(),
+        );
+
+        assert!(ics.contains("DTSTART:20251201T093000\r\n"));
+        assert!(ics.contains("RRULE:FREQ=MONTHLY;BYDAY=1MO\r\n"));
+        assert!(ics.contains("EXDATE:20251201T093000\r\n"));
+    }
+
+    #[test]
+    fn exports_custom_intervals_as_exact_daily_rules() {
+        let hourly = schedules_to_ics(
+            &[schedule(
+                RepeatType::Custom {
+                    interval_minutes: 60,
+                    until: Some("17:00".into()),
+                },
+                "Stretch",
+            )],
+            generated_at(),
+        );
+        assert!(hourly.contains("UID:abc@resonatify\r\n"));
+        assert!(hourly.contains("DTSTART:20251112T093000\r\n"));
+        assert!(hourly.contains("RRULE:FREQ=DAILY;BYHOUR=9,10,11,12,13,14,15,16;BYMINUTE=30\r\n"));
+
+        // 09:30, 10:15, 11:00 and 11:45 do not share their hours, so they
+        // need a rule for each set of hours.
+        let uneven = schedules_to_ics(
+            &[schedule(
+                RepeatType::Custom {
+                    interval_minutes: 45,
+                    until: Some("12:00".into()),
+                },
+                "Stretch",
+            )],
+            generated_at(),
+        );
+        assert_eq!(uneven.matches("BEGIN:VEVENT").count(), 3);
+        assert!(uneven.contains("UID:abc-1@resonatify\r\n"));
+        assert!(uneven.contains("RRULE:FREQ=DAILY;BYHOUR=9;BYMINUTE=30\r\n"));
+        assert!(uneven.contains("RRULE:FREQ=DAILY;BYHOUR=10;BYMINUTE=15\r\n"));
+        assert!(uneven.contains("DTSTART:20251112T110000\r\n"));
+        assert!(uneven.contains("RRULE:FREQ=DAILY;BYHOUR=11;BYMINUTE=0,45\r\n"));
+    }
+
+    #[test]
+    fn skips_schedules_that_never_fire() {
+        let ics = schedules_to_ics(
+            &[schedule(RepeatType::Weekly { days: Vec::new() }, "Never")],
+            generated_at(),
+        );
+
+        assert!(!ics.contains("BEGIN:VEVENT"));
+    }
+
+    #[test]
+    fn escapes_and_folds_long_text() {
+        let name = format!("Tea, biscuits; {}", "é".repeat(60));
+        let ics = schedules_to_ics(&[schedule(RepeatType::Once, &name)], generated_at());
+
+        assert!(ics.contains("SUMMARY:Tea\\, biscuits\\; "));
+        for line in ics.split("\r\n") {
+            assert!(line.len() <= MAX_LINE_OCTETS, "line too long: {line}");
+        }
+    }
+}
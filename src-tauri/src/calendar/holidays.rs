@@ -0,0 +1,280 @@
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use chrono::{Datelike, Days, Months, NaiveDate, NaiveTime};
+
+use crate::db::models::RecurringHoliday;
+use crate::scheduler::recurrence::{IcalDateTime, RecurrenceRule, RecurrenceSet};
+
+use super::import::{parse_value, split_property, unfold_lines};
+use super::CalendarError;
+
+/// Recurring holidays (e.g. a yearly `RRULE`) are expanded this many years
+/// past the date being looked up.
+const RECURRENCE_HORIZON_YEARS: i32 = 5;
+/// Longest run of days a single all-day event may cover.
+const MAX_EVENT_DAYS: i64 = 366;
+
+/// Reads holidays from an `.ics` calendar (every all-day or timed event
+/// counts for the days it covers) or a `.csv` file whose first column holds
+/// `YYYY-MM-DD` dates. Rows that are not dates, such as headers, are ignored.
+/// Returns the one-off dates and the recurring holidays.
+pub fn load_holidays(
+    path: &Path,
+) -> Result<(Vec<NaiveDate>, Vec<RecurringHoliday>), CalendarError> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+
+    let contents = std::fs::read_to_string(path)?;
+    let (mut dates, recurring) = match extension.as_deref() {
+        Some("ics") => parse_ics_holidays(&contents),
+        Some("csv") | Some("txt") => (parse_csv_dates(&contents), Vec::new()),
+        _ => {
+            return Err(CalendarError::InvalidHolidays(format!(
+                "unsupported file type '{}', expected .ics or .csv",
+                path.display()
+            )))
+        }
+    };
+
+    if dates.is_empty() && recurring.is_empty() {
+        return Err(CalendarError::InvalidHolidays(format!(
+            "no dates found in '{}'",
+            path.display()
+        )));
+    }
+
+    dates.sort();
+    dates.dedup();
+    Ok((dates, recurring))
+}
+
+pub fn parse_csv_dates(contents: &str) -> Vec<NaiveDate> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let field = line.split([',', ';', '\t']).next()?;
+            let field = field.trim().trim_matches('"');
+            NaiveDate::parse_from_str(field, "%Y-%m-%d")
+                .or_else(|_| NaiveDate::parse_from_str(field, "%Y%m%d"))
+                .ok()
+        })
+        .collect()
+}
+
+/// Holidays in the VEVENTs of an iCalendar document: the dates covered by
+/// one-off events, and recurring events kept as rules.
+pub fn parse_ics_holidays(contents: &str) -> (Vec<NaiveDate>, Vec<RecurringHoliday>) {
+    let mut dates = Vec::new();
+    let mut recurring = Vec::new();
+    let mut current: Option<HolidayEvent> = None;
+    let mut nested_depth = 0usize;
+
+    for line in unfold_lines(contents) {
+        let Some((name, params, value)) = split_property(&line) else {
+            continue;
+        };
+
+        match (name.as_str(), value.to_ascii_uppercase().as_str()) {
+            ("BEGIN", "VEVENT") => {
+                current = Some(HolidayEvent::default());
+                nested_depth = 0;
+                continue;
+            }
+            ("END", "VEVENT") => {
+                match current.take().and_then(HolidayEvent::into_holiday) {
+                    Some(Holiday::Dates(event_dates)) => dates.extend(event_dates),
+                    Some(Holiday::Recurring(holiday)) => recurring.push(holiday),
+                    None => {}
+                }
+                continue;
+            }
+            ("BEGIN", _) if current.is_some() => {
+                nested_depth += 1;
+                continue;
+            }
+            ("END", _) if current.is_some() => {
+                nested_depth = nested_depth.saturating_sub(1);
+                continue;
+            }
+            _ => {}
+        }
+
+        let Some(event) = current.as_mut().filter(|_| nested_depth == 0) else {
+            continue;
+        };
+
+        let is_date = params
+            .iter()
+            .any(|param| param.eq_ignore_ascii_case("VALUE=DATE"));
+        match name.as_str() {
+            "DTSTART" => event.start = parse_value(&value, is_date),
+            "DTEND" => event.end = parse_value(&value, is_date),
+            "RRULE" => event.rule = value.parse().ok(),
+            "STATUS" => event.cancelled = value.eq_ignore_ascii_case("CANCELLED"),
+            "EXDATE" => event.exdates.extend(
+                value
+                    .split(',')
+                    .filter_map(|item| parse_value(item, is_date)),
+            ),
+            _ => {}
+        }
+    }
+
+    (dates, recurring)
+}
+
+/// The holidays of a calendar, with its recurring ones expanded on demand.
+#[derive(Debug, Clone, Default)]
+pub struct Holidays {
+    dates: BTreeSet<NaiveDate>,
+    recurring: Vec<(RecurrenceSet, u32)>,
+}
+
+impl Holidays {
+    pub fn new(dates: &[NaiveDate], recurring: &[RecurringHoliday]) -> Self {
+        let recurring = recurring
+            .iter()
+            .filter_map(|holiday| match holiday.rule.parse::<RecurrenceSet>() {
+                Ok(set) if set.start.is_some() => Some((set, holiday.days)),
+                _ => {
+                    eprintln!("Ignoring invalid recurring holiday '{}'", holiday.rule);
+                    None
+                }
+            })
+            .collect();
+
+        Self {
+            dates: dates.iter().copied().collect(),
+            recurring,
+        }
+    }
+
+    /// Every holiday from a year before `today` to the end of the year
+    /// [`RECURRENCE_HORIZON_YEARS`] after it, plus all one-off dates.
+    pub fn around(&self, today: NaiveDate) -> Cow<'_, BTreeSet<NaiveDate>> {
+        if self.recurring.is_empty() {
+            return Cow::Borrowed(&self.dates);
+        }
+
+        let from = today.checked_sub_months(Months::new(12)).unwrap_or(today);
+        let until = NaiveDate::from_ymd_opt(today.year() + RECURRENCE_HORIZON_YEARS, 12, 31)
+            .unwrap_or(NaiveDate::MAX);
+
+        let mut dates = self.dates.clone();
+        for (set, days) in &self.recurring {
+            let Some(anchor) = set.start.as_ref().map(IcalDateTime::local_date) else {
+                continue;
+            };
+            let earliest = from
+                .checked_sub_days(Days::new(u64::from(*days)))
+                .unwrap_or(from);
+            let starts = set
+                .occurrences_from(anchor, NaiveTime::MIN, earliest)
+                .skip_while(|date| *date < earliest)
+                .take_while(|date| *date <= until);
+            dates.extend(starts.flat_map(|date| covered_days(date, *days)));
+        }
+        Cow::Owned(dates)
+    }
+}
+
+/// `days` consecutive dates starting at `start`.
+fn covered_days(start: NaiveDate, days: u32) -> impl Iterator<Item = NaiveDate> {
+    (0..days).filter_map(move |offset| start.checked_add_days(Days::new(u64::from(offset))))
+}
+
+enum Holiday {
+    Dates(Vec<NaiveDate>),
+    Recurring(RecurringHoliday),
+}
+
+#[derive(Default)]
+struct HolidayEvent {
+    start: Option<IcalDateTime>,
+    end: Option<IcalDateTime>,
+    rule: Option<RecurrenceRule>,
+    exdates: Vec<IcalDateTime>,
+    cancelled: bool,
+}
+
+impl HolidayEvent {
+    fn into_holiday(self) -> Option<Holiday> {
+        let start = self.start.filter(|_| !self.cancelled)?;
+        let first = start.local_date();
+
+        // An all-day DTEND is exclusive; a timed one covers its own day.
+        let span = match self.end {
+            Some(IcalDateTime::Date(end)) => (end - first).num_days().max(1),
+            Some(end) => (end.local_date() - first).num_days().max(0) + 1,
+            None => 1,
+        }
+        .min(MAX_EVENT_DAYS) as u32;
+
+        Some(match self.rule {
+            Some(rule) => Holiday::Recurring(RecurringHoliday {
+                rule: RecurrenceSet {
+                    start: Some(IcalDateTime::Date(first)),
+                    rule,
+                    exdates: self.exdates,
+                }
+                .to_string(),
+                days: span,
+            }),
+            None => Holiday::Dates(covered_days(first, span).collect()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn reads_all_day_spans_and_yearly_holidays() {
+        let calendar = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+SUMMARY:Christmas\r
+DTSTART;VALUE=DATE:20251225\r
+DTEND;VALUE=DATE:20251227\r
+RRULE:FREQ=YEARLY\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:Founders' day\r
+DTSTART;VALUE=DATE:20251107\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+        let (dates, recurring) = parse_ics_holidays(calendar);
+        let holidays = Holidays::new(&dates, &recurring);
+        let dates = holidays.around(date(2025, 11, 1));
+
+        assert!(dates.contains(&date(2025, 11, 7)));
+        assert!(dates.contains(&date(2025, 12, 25)));
+        assert!(dates.contains(&date(2025, 12, 26)));
+        assert!(!dates.contains(&date(2025, 12, 27)));
+        assert!(dates.contains(&date(2030, 12, 26)));
+        assert!(!dates.contains(&date(2031, 12, 25)));
+
+        // Looked up decades later, the yearly holiday still applies.
+        let later = holidays.around(date(2060, 6, 1));
+        assert!(later.contains(&date(2060, 12, 25)));
+        assert!(later.contains(&date(2060, 12, 26)));
+        assert!(later.contains(&date(2025, 11, 7)));
+    }
+
+    #[test]
+    fn reads_first_csv_column_and_ignores_headers() {
+        let dates = parse_csv_dates("date,name\n2025-12-25,Christmas\n\"2026-01-01\";New Year\n");
+
+        assert_eq!(dates, vec![date(2025, 12, 25), date(2026, 1, 1)]);
+    }
+}
@@ -0,0 +1,501 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Duration, Local, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+
+use crate::scheduler::recurrence::{IcalDateTime, RecurrenceRule, RecurrenceSet};
+
+use super::CalendarError;
+
+/// A timed VEVENT read from a subscribed calendar. All-day and cancelled
+/// events are dropped while parsing, since there is no start time to remind
+/// before.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalendarEvent {
+    pub uid: String,
+    pub summary: String,
+    /// Wall time in `zone`, which recurrences repeat in.
+    pub start: NaiveDateTime,
+    /// The `TZID` of the start; `None` is local time.
+    pub zone: Option<Tz>,
+    pub recurrence: Option<RecurrenceSet>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reminder {
+    pub uid: String,
+    pub summary: String,
+    pub starts_at: DateTime<Local>,
+    pub fire_at: DateTime<Local>,
+}
+
+/// Identifies the current contents of a subscription source so the engine
+/// only re-parses it after a change.
+pub type SourceFingerprint = Vec<(PathBuf, Option<SystemTime>, u64)>;
+
+impl CalendarEvent {
+    /// Start times of this event as wall time in its zone, in order, from
+    /// the first one on or after `from`.
+    fn starts_from(&self, from: DateTime<Local>) -> Box<dyn Iterator<Item = NaiveDateTime> + '_> {
+        let from = match self.zone {
+            Some(zone) => from.with_timezone(&zone).naive_local(),
+            None => from.naive_local(),
+        };
+        let time = self.start.time();
+        match &self.recurrence {
+            None => Box::new(std::iter::once(self.start).filter(move |start| *start >= from)),
+            Some(set) => Box::new(
+                set.occurrences_from(self.start.date(), time, from.date())
+                    .map(move |date| date.and_time(time))
+                    .skip_while(move |start| *start < from),
+            ),
+        }
+    }
+}
+
+/// `.ics` files making up a subscription: the file itself, or every `.ics`
+/// file directly inside a folder.
+pub fn source_files(source: &Path) -> Result<Vec<PathBuf>, CalendarError> {
+    if !source.is_dir() {
+        std::fs::metadata(source)?;
+        return Ok(vec![source.to_path_buf()]);
+    }
+
+    let mut files = std::fs::read_dir(source)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("ics"))
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+
+    Ok(files)
+}
+
+pub fn source_fingerprint(source: &Path) -> Result<SourceFingerprint, CalendarError> {
+    source_files(source)?
+        .into_iter()
+        .map(|path| {
+            let metadata = std::fs::metadata(&path)?;
+            Ok((path, metadata.modified().ok(), metadata.len()))
+        })
+        .collect()
+}
+
+pub fn load_events(source: &Path) -> Result<Vec<CalendarEvent>, CalendarError> {
+    let mut events = Vec::new();
+    for path in source_files(source)? {
+        let contents = std::fs::read_to_string(&path)?;
+        events.extend(parse_events(&contents));
+    }
+
+    Ok(events)
+}
+
+/// Parses the timed VEVENTs of an iCalendar document. Events whose recurrence
+/// rule is not supported are skipped rather than failing the whole file.
+///
+/// A VEVENT with `RECURRENCE-ID` replaces that instance of its series: the
+/// original date is excluded from the series and the override is kept as a
+/// single event.
+pub fn parse_events(contents: &str) -> Vec<CalendarEvent> {
+    let mut events = Vec::new();
+    let mut overridden: HashMap<String, Vec<IcalDateTime>> = HashMap::new();
+    let mut current: Option<EventBuilder> = None;
+    let mut nested_depth = 0usize;
+
+    for line in unfold_lines(contents) {
+        let Some((name, params, value)) = split_property(&line) else {
+            continue;
+        };
+
+        match (name.as_str(), value.to_ascii_uppercase().as_str()) {
+            ("BEGIN", "VEVENT") => {
+                current = Some(EventBuilder::default());
+                nested_depth = 0;
+                continue;
+            }
+            ("END", "VEVENT") => {
+                if let Some(builder) = current.take() {
+                    if let Some(recurrence_id) = builder.recurrence_id {
+                        overridden
+                            .entry(builder.uid.clone().unwrap_or_default())
+                            .or_default()
+                            .push(recurrence_id);
+                    }
+                    if let Some(event) = builder.build() {
+                        events.push(event);
+                    }
+                }
+                continue;
+            }
+            ("BEGIN", _) if current.is_some() => {
+                nested_depth += 1;
+                continue;
+            }
+            ("END", _) if current.is_some() => {
+                nested_depth = nested_depth.saturating_sub(1);
+                continue;
+            }
+            _ => {}
+        }
+
+        let Some(builder) = current.as_mut().filter(|_| nested_depth == 0) else {
+            continue;
+        };
+
+        let is_date = params
+            .iter()
+            .any(|param| param.eq_ignore_ascii_case("VALUE=DATE"));
+        match name.as_str() {
+            "UID" => builder.uid = Some(value),
+            "SUMMARY" => builder.summary = Some(unescape_text(&value)),
+            "STATUS" => builder.cancelled = value.eq_ignore_ascii_case("CANCELLED"),
+            "DTSTART" => {
+                builder.start = parse_value(&value, is_date);
+                builder.tzid = param_value(&params, "TZID");
+            }
+            "RRULE" => builder.rule = Some(value.parse()),
+            "RECURRENCE-ID" => builder.recurrence_id = parse_value(&value, is_date),
+            "EXDATE" => builder.exdates.extend(
+                value
+                    .split(',')
+                    .filter_map(|item| parse_value(item, is_date)),
+            ),
+            _ => {}
+        }
+    }
+
+    for event in &mut events {
+        if let (Some(set), Some(exdates)) = (event.recurrence.as_mut(), overridden.get(&event.uid))
+        {
+            set.exdates.extend(exdates.iter().copied());
+        }
+    }
+
+    events
+}
+
+/// Reminders due in the half-open window `(after, until]`, in firing order.
+pub fn reminders_between(
+    events: &[CalendarEvent],
+    lead: Duration,
+    after: DateTime<Local>,
+    until: DateTime<Local>,
+) -> Vec<Reminder> {
+    let mut due = Vec::new();
+    for event in events {
+        for start in event.starts_from(after + lead) {
+            let Some(reminder) = reminder_for(event, start, lead) else {
+                continue;
+            };
+            if reminder.fire_at > until {
+                break;
+            }
+            if reminder.fire_at > after {
+                due.push(reminder);
+            }
+        }
+    }
+
+    due.sort_by_key(|reminder| reminder.fire_at);
+    due
+}
+
+/// The first reminder that fires strictly after `after`.
+pub fn next_reminder(
+    events: &[CalendarEvent],
+    lead: Duration,
+    after: DateTime<Local>,
+) -> Option<Reminder> {
+    events
+        .iter()
+        .filter_map(|event| {
+            event
+                .starts_from(after + lead)
+                .filter_map(|start| reminder_for(event, start, lead))
+                .find(|reminder| reminder.fire_at > after)
+        })
+        .min_by_key(|reminder| reminder.fire_at)
+}
+
+fn reminder_for(event: &CalendarEvent, start: NaiveDateTime, lead: Duration) -> Option<Reminder> {
+    let starts_at = match event.zone {
+        Some(zone) => zone.from_local_datetime(&start).earliest()?.with_timezone(&Local),
+        None => Local.from_local_datetime(&start).earliest()?,
+    };
+    Some(Reminder {
+        uid: event.uid.clone(),
+        summary: event.summary.clone(),
+        starts_at,
+        fire_at: starts_at - lead,
+    })
+}
+
+#[derive(Default)]
+struct EventBuilder {
+    uid: Option<String>,
+    summary: Option<String>,
+    start: Option<IcalDateTime>,
+    /// The `TZID` parameter of `DTSTART`.
+    tzid: Option<String>,
+    rule: Option<Result<RecurrenceRule, crate::scheduler::recurrence::RecurrenceError>>,
+    exdates: Vec<IcalDateTime>,
+    recurrence_id: Option<IcalDateTime>,
+    cancelled: bool,
+}
+
+impl EventBuilder {
+    fn build(self) -> Option<CalendarEvent> {
+        if self.cancelled {
+            return None;
+        }
+
+        let (start, zone) = match self.start? {
+            IcalDateTime::Date(_) => return None,
+            IcalDateTime::Floating(start) => (start, self.tzid.as_deref().and_then(parse_zone)),
+            IcalDateTime::Utc(start) => (start.with_timezone(&Local).naive_local(), None),
+        };
+
+        let recurrence = match (self.rule, self.recurrence_id) {
+            (_, Some(_)) | (None, None) => None,
+            (Some(Ok(rule)), None) => Some(RecurrenceSet {
+                start: None,
+                rule,
+                exdates: self.exdates,
+            }),
+            (Some(Err(err)), None) => {
+                eprintln!(
+                    "Skipping calendar event '{}': {}",
+                    self.summary.as_deref().unwrap_or_default(),
+                    err
+                );
+                return None;
+            }
+        };
+
+        Some(CalendarEvent {
+            uid: self.uid.unwrap_or_default(),
+            summary: self.summary.unwrap_or_default(),
+            start,
+            zone,
+            recurrence,
+        })
+    }
+}
+
+pub(super) fn parse_value(value: &str, is_date: bool) -> Option<IcalDateTime> {
+    let value = value.trim();
+    if is_date {
+        return value
+            .get(..8)
+            .and_then(|date| date.parse::<IcalDateTime>().ok());
+    }
+    value.parse().ok()
+}
+
+/// The zone a `TZID` names. Unknown zones, such as custom `VTIMEZONE`
+/// names, fall back to local time.
+fn parse_zone(tzid: &str) -> Option<Tz> {
+    let zone = tzid.trim_start_matches('/').parse().ok();
+    if zone.is_none() {
+        eprintln!("Unknown time zone '{}' in calendar; using local time", tzid);
+    }
+    zone
+}
+
+/// The value of parameter `name` in `params`, unquoted.
+fn param_value(params: &[String], name: &str) -> Option<String> {
+    params.iter().find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+pub(super) fn unfold_lines(contents: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in contents.lines() {
+        let raw = raw.trim_end_matches('\r');
+        match raw.strip_prefix([' ', '\t']) {
+            Some(continuation) if !lines.is_empty() => {
+                if let Some(last) = lines.last_mut() {
+                    last.push_str(continuation);
+                }
+            }
+            _ => lines.push(raw.to_string()),
+        }
+    }
+    lines
+}
+
+/// Splits `NAME;PARAM=x;PARAM=y:value`, upper-casing the name.
+pub(super) fn split_property(line: &str) -> Option<(String, Vec<String>, String)> {
+    let (head, value) = line.split_once(':')?;
+    let mut parts = head.split(';');
+    let name = parts.next()?.trim().to_ascii_uppercase();
+    let params = parts.map(|param| param.trim().to_string()).collect();
+
+    Some((name, params, value.to_string()))
+}
+
+fn unescape_text(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            unescaped.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+UID:standup\r
+SUMMARY:Stand-up\\, daily\r
+DTSTART:20251110T093000\r
+RRULE:FREQ=WEEKLY;BYDAY=MO,\r
+ WE,FR\r
+EXDATE:20251112T093000\r
+BEGIN:VALARM\r
+SUMMARY:ignored\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:standup\r
+RECURRENCE-ID:20251114T093000\r
+SUMMARY:Stand-up (moved)\r
+DTSTART:20251114T110000\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:holiday\r
+SUMMARY:Holiday\r
+DTSTART;VALUE=DATE:20251124\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:cancelled\r
+SUMMARY:Cancelled\r
+DTSTART:20251110T120000\r
+STATUS:CANCELLED\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    fn local(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn parses_timed_events_with_overrides() {
+        let events = parse_events(CALENDAR);
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].summary, "Stand-up, daily");
+        assert_eq!(
+            events[0].start,
+            NaiveDate::from_ymd_opt(2025, 11, 10)
+                .unwrap()
+                .and_hms_opt(9, 30, 0)
+                .unwrap()
+        );
+
+        let set = events[0].recurrence.as_ref().unwrap();
+        assert_eq!(set.rule.by_day.len(), 3);
+        assert_eq!(set.exdates.len(), 2);
+        assert!(events[1].recurrence.is_none());
+    }
+
+    #[test]
+    fn reminders_skip_exdates_and_use_overrides() {
+        let events = parse_events(CALENDAR);
+        let lead = Duration::minutes(10);
+
+        let due = reminders_between(
+            &events,
+            lead,
+            local(2025, 11, 10, 0, 0),
+            local(2025, 11, 15, 0, 0),
+        );
+
+        let fire_times = due.iter().map(|r| r.fire_at).collect::<Vec<_>>();
+        assert_eq!(
+            fire_times,
+            vec![local(2025, 11, 10, 9, 20), local(2025, 11, 14, 10, 50)]
+        );
+    }
+
+    #[test]
+    fn next_reminder_is_strictly_after_reference() {
+        let events = parse_events(CALENDAR);
+
+        let next =
+            next_reminder(&events, Duration::minutes(5), local(2025, 11, 10, 9, 25)).unwrap();
+
+        assert_eq!(next.fire_at, local(2025, 11, 14, 10, 55));
+        assert_eq!(next.starts_at, local(2025, 11, 14, 11, 0));
+    }
+
+    #[test]
+    fn event_starts_resolve_in_their_tzid() {
+        let events = parse_events(
+            "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:sync\r
+SUMMARY:Sync\r
+DTSTART;TZID=\"America/New_York\":20251110T090000\r
+RRULE:FREQ=DAILY;COUNT=2\r
+END:VEVENT\r
+END:VCALENDAR\r
+",
+        );
+        assert_eq!(events[0].zone, Some(chrono_tz::America::New_York));
+
+        let due = reminders_between(
+            &events,
+            Duration::zero(),
+            local(2025, 11, 9, 0, 0),
+            local(2025, 11, 13, 0, 0),
+        );
+
+        let expected = [10, 11].map(|day| {
+            chrono_tz::America::New_York
+                .with_ymd_and_hms(2025, 11, day, 9, 0, 0)
+                .unwrap()
+                .with_timezone(&Local)
+        });
+        let starts = due.iter().map(|r| r.starts_at).collect::<Vec<_>>();
+        assert_eq!(starts, expected);
+    }
+
+    #[test]
+    fn folder_sources_only_read_ics_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("work.ics"), CALENDAR).unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), "BEGIN:VEVENT").unwrap();
+
+        let events = load_events(temp_dir.path()).unwrap();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(source_fingerprint(temp_dir.path()).unwrap().len(), 1);
+    }
+}
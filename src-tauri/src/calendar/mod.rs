@@ -0,0 +1,8 @@
+pub mod error;
+pub mod export;
+pub mod holidays;
+pub mod import;
+
+pub use error::CalendarError;
+pub use export::{refresh_feed, schedules_to_ics};
+pub use import::{CalendarEvent, Reminder};
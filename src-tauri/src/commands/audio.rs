@@ -1,6 +1,11 @@
+use std::time::Duration;
+
 use tauri::State;
 
-use crate::audio::{AudioFileMetadata, AudioService, PlaybackState};
+use crate::audio::{
+    self, AudioFileMetadata, AudioService, GeneratedSound, OutputDevice, PlaybackOptions,
+    PlaybackState, VoiceProgress,
+};
 use crate::AppState;
 
 fn audio_service(state: &State<'_, AppState>) -> AudioService {
@@ -34,6 +39,23 @@ pub async fn play_audio_file(
         .map_err(|err| err.to_string())
 }
 
+/// Previews a generated sound, e.g. while choosing its parameters.
+#[tauri::command]
+pub async fn play_generated_sound(
+    sound: GeneratedSound,
+    volume: u8,
+    state: State<'_, AppState>,
+) -> Result<PlaybackState, String> {
+    if volume > 100 {
+        return Err("volume must be between 0 and 100".into());
+    }
+
+    audio_service(&state)
+        .play_generated(sound, volume, PlaybackOptions::default(), None)
+        .await
+        .map_err(|err| err.to_string())
+}
+
 #[tauri::command]
 pub async fn stop_audio(state: State<'_, AppState>) -> Result<PlaybackState, String> {
     audio_service(&state)
@@ -42,7 +64,68 @@ pub async fn stop_audio(state: State<'_, AppState>) -> Result<PlaybackState, Str
         .map_err(|err| err.to_string())
 }
 
+/// Stops one of several sounds playing at once.
+#[tauri::command]
+pub async fn stop_audio_voice(
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<PlaybackState, String> {
+    audio_service(&state)
+        .stop_voice(id)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Pauses one sound, or everything playing when no id is given.
+#[tauri::command]
+pub async fn pause_audio(
+    id: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<PlaybackState, String> {
+    audio_service(&state)
+        .pause(id)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn resume_audio(
+    id: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<PlaybackState, String> {
+    audio_service(&state)
+        .resume(id)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Jumps to `position_ms` in one sound, or the latest when no id is given.
+#[tauri::command]
+pub async fn seek_audio(
+    position_ms: u64,
+    id: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<PlaybackState, String> {
+    audio_service(&state)
+        .seek(id, Duration::from_millis(position_ms))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn get_audio_progress(state: State<'_, AppState>) -> Result<Vec<VoiceProgress>, String> {
+    audio_service(&state)
+        .progress()
+        .await
+        .map_err(|err| err.to_string())
+}
+
 #[tauri::command]
 pub async fn get_audio_status(state: State<'_, AppState>) -> Result<PlaybackState, String> {
     Ok(audio_service(&state).status().await)
 }
+
+#[tauri::command]
+pub async fn list_output_devices() -> Result<Vec<OutputDevice>, String> {
+    audio::list_output_devices().map_err(|err| err.to_string())
+}
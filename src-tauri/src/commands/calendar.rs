@@ -0,0 +1,207 @@
+use std::path::PathBuf;
+
+use chrono::Utc;
+use tauri::State;
+
+use crate::calendar::{self, schedules_to_ics};
+use crate::db::models::{
+    CalendarSubscription, CreateCalendarSubscriptionInput, CreateHolidayCalendarInput,
+    HolidayCalendar, UpdateCalendarSubscriptionInput, UpdateHolidayCalendarInput,
+};
+use crate::db::Database;
+use crate::scheduler::SchedulerEngine;
+use crate::AppState;
+
+fn database(state: &State<'_, AppState>) -> Database {
+    state.database.clone()
+}
+
+fn scheduler(state: &State<'_, AppState>) -> SchedulerEngine {
+    state.scheduler.clone()
+}
+
+/// Exports the given schedules (or every enabled schedule when `schedule_ids`
+/// is omitted) as an iCalendar document, optionally also writing it to `path`.
+#[tauri::command]
+pub async fn export_schedules_ics(
+    schedule_ids: Option<Vec<String>>,
+    path: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let repo = database(&state).schedule_repository();
+
+    let schedules = match schedule_ids {
+        Some(ids) => {
+            let mut selected = Vec::with_capacity(ids.len());
+            for id in ids {
+                selected.push(repo.get_by_id(&id).await.map_err(|err| err.to_string())?);
+            }
+            selected
+        }
+        None => repo.get_enabled().await.map_err(|err| err.to_string())?,
+    };
+
+    let contents = schedules_to_ics(&schedules, Utc::now());
+
+    if let Some(path) = path {
+        calendar::export::write_ics(&PathBuf::from(path), &contents)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
+    Ok(contents)
+}
+
+#[tauri::command]
+pub async fn get_calendar_subscriptions(
+    state: State<'_, AppState>,
+) -> Result<Vec<CalendarSubscription>, String> {
+    database(&state)
+        .calendar_subscription_repository()
+        .get_all()
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn create_calendar_subscription(
+    input: CreateCalendarSubscriptionInput,
+    state: State<'_, AppState>,
+) -> Result<CalendarSubscription, String> {
+    let repo = database(&state).calendar_subscription_repository();
+    let created = repo.create(input).await.map_err(|err| err.to_string())?;
+
+    scheduler(&state)
+        .reload()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(created)
+}
+
+#[tauri::command]
+pub async fn update_calendar_subscription(
+    id: String,
+    input: UpdateCalendarSubscriptionInput,
+    state: State<'_, AppState>,
+) -> Result<CalendarSubscription, String> {
+    let repo = database(&state).calendar_subscription_repository();
+    let updated = repo
+        .update(&id, input)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    scheduler(&state)
+        .reload()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(updated)
+}
+
+#[tauri::command]
+pub async fn delete_calendar_subscription(
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let repo = database(&state).calendar_subscription_repository();
+    repo.delete(&id).await.map_err(|err| err.to_string())?;
+
+    scheduler(&state)
+        .reload()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_holiday_calendars(
+    state: State<'_, AppState>,
+) -> Result<Vec<HolidayCalendar>, String> {
+    database(&state)
+        .holiday_calendar_repository()
+        .get_all()
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn create_holiday_calendar(
+    input: CreateHolidayCalendarInput,
+    state: State<'_, AppState>,
+) -> Result<HolidayCalendar, String> {
+    let repo = database(&state).holiday_calendar_repository();
+    let created = repo.create(input).await.map_err(|err| err.to_string())?;
+
+    scheduler(&state)
+        .reload()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(created)
+}
+
+/// Creates a holiday calendar from the dates in a local `.ics` or `.csv` file.
+#[tauri::command]
+pub async fn import_holiday_calendar(
+    name: String,
+    path: String,
+    state: State<'_, AppState>,
+) -> Result<HolidayCalendar, String> {
+    let (dates, recurring) =
+        calendar::holidays::load_holidays(&PathBuf::from(path)).map_err(|err| err.to_string())?;
+
+    let repo = database(&state).holiday_calendar_repository();
+    let created = repo
+        .create(CreateHolidayCalendarInput {
+            name,
+            dates,
+            recurring,
+        })
+        .await
+        .map_err(|err| err.to_string())?;
+
+    scheduler(&state)
+        .reload()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(created)
+}
+
+#[tauri::command]
+pub async fn update_holiday_calendar(
+    id: String,
+    input: UpdateHolidayCalendarInput,
+    state: State<'_, AppState>,
+) -> Result<HolidayCalendar, String> {
+    let repo = database(&state).holiday_calendar_repository();
+    let updated = repo
+        .update(&id, input)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    scheduler(&state)
+        .reload()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(updated)
+}
+
+#[tauri::command]
+pub async fn delete_holiday_calendar(
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let repo = database(&state).holiday_calendar_repository();
+    repo.delete(&id).await.map_err(|err| err.to_string())?;
+
+    scheduler(&state)
+        .reload()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(())
+}
@@ -1,4 +1,5 @@
 pub mod audio;
+pub mod calendar;
 pub mod dialogs;
 pub mod scheduler;
 pub mod schedules;
@@ -1,8 +1,23 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::Local;
+use serde::Serialize;
 use tauri::State;
 
-use crate::db::models::{CreateScheduleInput, Schedule, UpdateScheduleInput};
+use crate::audio::PlaybackOptions;
+use crate::calendar;
+use crate::db::models::{
+    AudioSource, CreateScheduleInput, PlaylistMode, RepeatType, Schedule, SettingsSnapshot,
+    UpdateScheduleInput,
+};
 use crate::db::Database;
-use crate::scheduler::SchedulerEngine;
+use crate::scheduler::audio_source::scan_folder;
+use crate::scheduler::describe::{describe_next_run, Language};
+use crate::scheduler::phrase::{self, ParsedPhrase};
+use crate::scheduler::recurrence::RecurrenceSet;
+use crate::scheduler::{SchedulerEngine, SchedulerError, UpcomingKind};
 use crate::AppState;
 
 fn database(state: &State<'_, AppState>) -> Database {
@@ -13,6 +28,94 @@ fn scheduler(state: &State<'_, AppState>) -> SchedulerEngine {
     state.scheduler.clone()
 }
 
+/// Rejects playlists that are empty or contain a file that cannot be played,
+/// folders that are missing or hold no supported audio files, and generated
+/// sounds with parameters out of range.
+async fn validate_audio_source(
+    state: &State<'_, AppState>,
+    source: &AudioSource,
+) -> Result<(), String> {
+    match source {
+        AudioSource::File => Ok(()),
+        AudioSource::Playlist { items, .. } => {
+            if items.is_empty() {
+                return Err(SchedulerError::EmptyPlaylist.to_string());
+            }
+
+            for (index, item) in items.iter().enumerate() {
+                state
+                    .audio
+                    .validate(item)
+                    .await
+                    .map_err(|err| format!("playlist item {} ({}): {}", index + 1, item, err))?;
+            }
+            Ok(())
+        }
+        AudioSource::Directory {
+            path, recursive, ..
+        } => scan_folder(Path::new(path), *recursive)
+            .map(|_| ())
+            .map_err(|err| err.to_string()),
+        AudioSource::Generated { sound } => sound.validate().map_err(|err| err.to_string()),
+    }
+}
+
+/// Checks playback options against the source they will play. Every item of
+/// a playlist plays in turn, so none of them may repeat without end, and a
+/// trim must fit within the schedule's own file or generated sound. Playlist
+/// and folder items are checked against a trim when they play. A file to be
+/// normalized is measured now, so it is ready by the time it plays.
+async fn validate_playback(
+    state: &State<'_, AppState>,
+    source: &AudioSource,
+    audio_file_path: &str,
+    playback: &PlaybackOptions,
+) -> Result<(), String> {
+    playback.validate().map_err(|err| err.to_string())?;
+
+    let plays_every_item = matches!(
+        source,
+        AudioSource::Playlist { items, mode: PlaylistMode::Sequential | PlaylistMode::Shuffle }
+            if items.len() > 1
+    );
+    if plays_every_item && playback.played_length(Duration::ZERO).is_none() {
+        return Err("a playlist cannot repeat forever without a maximum play duration".into());
+    }
+
+    if matches!(source, AudioSource::File) && (playback.trim.is_some() || playback.normalize) {
+        let metadata = state
+            .audio
+            .validate(audio_file_path)
+            .await
+            .map_err(|err| err.to_string())?;
+        if let Some(trim) = &playback.trim {
+            trim.validate(metadata.duration_ms)
+                .map_err(|err| err.to_string())?;
+        }
+    }
+    if let (AudioSource::Generated { sound }, Some(trim)) = (source, &playback.trim) {
+        trim.validate(sound.metadata().duration_ms)
+            .map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+/// Rejects a recurrence rule that does not parse, so it is not saved only to
+/// fail when the scheduler loads it.
+fn validate_repeat_type(repeat_type: &RepeatType) -> Result<(), String> {
+    if let RepeatType::Rrule { rule } = repeat_type {
+        rule.parse::<RecurrenceSet>()
+            .map_err(|err| format!("invalid recurrence rule: {}", err))?;
+    }
+    Ok(())
+}
+
+async fn refresh_calendar_feed(state: &State<'_, AppState>) {
+    if let Err(err) = calendar::refresh_feed(&database(state)).await {
+        eprintln!("Failed to refresh calendar feed: {}", err);
+    }
+}
+
 #[tauri::command]
 pub async fn get_all_schedules(state: State<'_, AppState>) -> Result<Vec<Schedule>, String> {
     database(&state)
@@ -22,11 +125,29 @@ pub async fn get_all_schedules(state: State<'_, AppState>) -> Result<Vec<Schedul
         .map_err(|err| err.to_string())
 }
 
+/// Everything `create_schedule` checks before saving.
+async fn validate_input(
+    state: &State<'_, AppState>,
+    input: &CreateScheduleInput,
+) -> Result<(), String> {
+    validate_repeat_type(&input.repeat_type)?;
+    validate_audio_source(state, &input.audio_source).await?;
+    validate_playback(
+        state,
+        &input.audio_source,
+        &input.audio_file_path,
+        &input.playback,
+    )
+    .await
+}
+
 #[tauri::command]
 pub async fn create_schedule(
     input: CreateScheduleInput,
     state: State<'_, AppState>,
 ) -> Result<Schedule, String> {
+    validate_input(&state, &input).await?;
+
     let repo = database(&state).schedule_repository();
     let created = repo.create(input).await.map_err(|err| err.to_string())?;
 
@@ -34,6 +155,7 @@ pub async fn create_schedule(
         .reload()
         .await
         .map_err(|err| err.to_string())?;
+    refresh_calendar_feed(&state).await;
 
     Ok(created)
 }
@@ -44,7 +166,29 @@ pub async fn update_schedule(
     input: UpdateScheduleInput,
     state: State<'_, AppState>,
 ) -> Result<Schedule, String> {
+    if let Some(repeat_type) = &input.repeat_type {
+        validate_repeat_type(repeat_type)?;
+    }
+    if let Some(source) = &input.audio_source {
+        validate_audio_source(&state, source).await?;
+    }
+
     let repo = database(&state).schedule_repository();
+    if input.audio_source.is_some() || input.playback.is_some() || input.audio_file_path.is_some()
+    {
+        let current = repo.get_by_id(&id).await.map_err(|err| err.to_string())?;
+        validate_playback(
+            &state,
+            input.audio_source.as_ref().unwrap_or(&current.audio_source),
+            input
+                .audio_file_path
+                .as_deref()
+                .unwrap_or(&current.audio_file_path),
+            input.playback.as_ref().unwrap_or(&current.playback),
+        )
+        .await?;
+    }
+
     let updated = repo
         .update(&id, input)
         .await
@@ -54,6 +198,7 @@ pub async fn update_schedule(
         .reload()
         .await
         .map_err(|err| err.to_string())?;
+    refresh_calendar_feed(&state).await;
 
     Ok(updated)
 }
@@ -67,6 +212,7 @@ pub async fn delete_schedule(id: String, state: State<'_, AppState>) -> Result<(
         .reload()
         .await
         .map_err(|err| err.to_string())?;
+    refresh_calendar_feed(&state).await;
 
     Ok(())
 }
@@ -93,6 +239,99 @@ pub async fn toggle_schedule_enabled(
         .reload()
         .await
         .map_err(|err| err.to_string())?;
+    refresh_calendar_feed(&state).await;
 
     Ok(updated)
 }
+
+#[derive(Debug, Serialize)]
+pub struct ScheduleDescription {
+    pub schedule_id: String,
+    /// e.g. "Every weekday at 07:30".
+    pub repeat: String,
+    /// e.g. "Next: tomorrow 07:30".
+    pub next: String,
+}
+
+/// Human-readable repeat and next-run text for every schedule, so the UI
+/// does not have to word recurrences itself.
+#[tauri::command]
+pub async fn get_schedule_descriptions(
+    state: State<'_, AppState>,
+) -> Result<Vec<ScheduleDescription>, String> {
+    let schedules = database(&state)
+        .schedule_repository()
+        .get_all()
+        .await
+        .map_err(|err| err.to_string())?;
+    let settings: SettingsSnapshot = database(&state)
+        .settings_repository()
+        .get_all()
+        .await
+        .map_err(|err| err.to_string())?
+        .into();
+    let language = Language::from_code(&settings.language);
+
+    // The engine's own next runs already account for holidays and grace.
+    let next_runs = scheduler(&state)
+        .upcoming_executions(usize::MAX)
+        .await
+        .into_iter()
+        .filter(|entry| entry.kind == UpcomingKind::Alarm)
+        .map(|entry| (entry.schedule_id, entry.scheduled_for))
+        .collect::<HashMap<_, _>>();
+
+    let now = Local::now();
+    Ok(schedules
+        .iter()
+        .map(|schedule| ScheduleDescription {
+            schedule_id: schedule.id.clone(),
+            repeat: schedule.describe_in(language),
+            next: describe_next_run(next_runs.get(&schedule.id).copied(), now, language),
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParsedSchedulePhrase {
+    /// The repeat type and time, and how the phrase was understood, e.g.
+    /// "Every weekday at 07:30".
+    #[serde(flatten)]
+    pub phrase: ParsedPhrase,
+    /// Ready to pass to `create_schedule`; `None` until an audio file is
+    /// given.
+    pub schedule: Option<CreateScheduleInput>,
+}
+
+/// Parses a phrase like "every weekday at 7:30" without saving anything.
+/// With an audio file the schedule is built and checked as `create_schedule`
+/// would check it, so it can be passed there once confirmed.
+#[tauri::command]
+pub async fn parse_schedule_phrase(
+    phrase: String,
+    name: Option<String>,
+    audio_file_path: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<ParsedSchedulePhrase, String> {
+    let parsed = phrase::parse_phrase(&phrase).map_err(|err| err.to_string())?;
+    let Some(audio_file_path) = audio_file_path.filter(|path| !path.trim().is_empty()) else {
+        return Ok(ParsedSchedulePhrase {
+            phrase: parsed,
+            schedule: None,
+        });
+    };
+
+    state
+        .audio
+        .validate(&audio_file_path)
+        .await
+        .map_err(|err| err.to_string())?;
+    let name = name.unwrap_or_else(|| phrase.trim().to_string());
+    let schedule = parsed.clone().into_input(name, audio_file_path);
+    validate_input(&state, &schedule).await?;
+
+    Ok(ParsedSchedulePhrase {
+        phrase: parsed,
+        schedule: Some(schedule),
+    })
+}
@@ -2,6 +2,8 @@ use serde::Deserialize;
 use tauri::{AppHandle, State};
 use tauri_plugin_autostart::ManagerExt;
 
+use crate::audio::output::OUTPUT_DEVICE_SETTING;
+use crate::calendar::{self, export::FEED_PATH_SETTING};
 use crate::db::models::{Setting, SettingsSnapshot};
 use crate::db::Database;
 use crate::AppState;
@@ -22,6 +24,17 @@ pub struct UpdateSettingsPayload {
     pub default_volume: Option<u8>,
     pub announcement_enabled: Option<bool>,
     pub announcement_sound: Option<String>,
+    /// File the iCalendar feed is kept in; an empty string turns the feed off.
+    pub calendar_feed_path: Option<String>,
+    /// Device schedules play through by default; an empty string uses the
+    /// system default.
+    pub output_device: Option<String>,
+    /// Default for schedules without their own late tolerance.
+    pub late_tolerance_seconds: Option<u32>,
+    /// Default for schedules without their own minimum spacing.
+    pub min_spacing_seconds: Option<u32>,
+    /// Language code for schedule descriptions, e.g. "en" or "es".
+    pub language: Option<String>,
 }
 
 #[tauri::command]
@@ -90,6 +103,67 @@ pub async fn update_settings(
             .map_err(|err| err.to_string())?;
     }
 
+    if let Some(path) = payload.calendar_feed_path {
+        let path = path.trim();
+        if path.is_empty() {
+            repo.delete(FEED_PATH_SETTING)
+                .await
+                .map_err(|err| err.to_string())?;
+        } else {
+            repo.upsert(FEED_PATH_SETTING, path)
+                .await
+                .map_err(|err| err.to_string())?;
+            calendar::refresh_feed(&database(&state))
+                .await
+                .map_err(|err| err.to_string())?;
+        }
+    }
+
+    if let Some(name) = payload.output_device {
+        let name = name.trim();
+        if name.is_empty() {
+            repo.delete(OUTPUT_DEVICE_SETTING)
+                .await
+                .map_err(|err| err.to_string())?;
+        } else {
+            repo.upsert(OUTPUT_DEVICE_SETTING, name)
+                .await
+                .map_err(|err| err.to_string())?;
+        }
+        state
+            .audio
+            .set_default_device(Some(name.to_string()).filter(|name| !name.is_empty()));
+    }
+
+    let grace_changed =
+        payload.late_tolerance_seconds.is_some() || payload.min_spacing_seconds.is_some();
+
+    if let Some(seconds) = payload.late_tolerance_seconds {
+        repo.upsert("late_tolerance_seconds", &seconds.to_string())
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
+    if let Some(seconds) = payload.min_spacing_seconds {
+        repo.upsert("min_spacing_seconds", &seconds.to_string())
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
+    if let Some(language) = payload.language {
+        repo.upsert("language", language.trim())
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
+    if grace_changed {
+        state
+            .scheduler
+            .reload()
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
     get_settings(state).await
 }
 
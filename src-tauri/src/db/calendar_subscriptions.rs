@@ -0,0 +1,159 @@
+use chrono::Local;
+use sqlx::{query, query_as, SqlitePool};
+use uuid::Uuid;
+
+use super::models::{
+    CalendarSubscription, CalendarSubscriptionRow, CreateCalendarSubscriptionInput,
+    UpdateCalendarSubscriptionInput,
+};
+use super::DbResult;
+
+#[derive(Clone)]
+pub struct CalendarSubscriptionRepository {
+    pool: SqlitePool,
+}
+
+impl CalendarSubscriptionRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create(
+        &self,
+        input: CreateCalendarSubscriptionInput,
+    ) -> DbResult<CalendarSubscription> {
+        let now = Local::now().to_rfc3339();
+        let id = Uuid::new_v4().to_string();
+
+        let CreateCalendarSubscriptionInput {
+            name,
+            source_path,
+            audio_file_path,
+            lead_minutes,
+            volume,
+            enabled,
+        } = input;
+
+        query(
+            r#"
+                INSERT INTO calendar_subscriptions (
+                    id,
+                    name,
+                    source_path,
+                    audio_file_path,
+                    lead_minutes,
+                    volume,
+                    enabled,
+                    created_at,
+                    updated_at
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&id)
+        .bind(name)
+        .bind(source_path)
+        .bind(audio_file_path)
+        .bind(lead_minutes as i64)
+        .bind(volume as i64)
+        .bind(enabled as i64)
+        .bind(&now)
+        .bind(&now)
+        .execute(&self.pool)
+        .await?;
+
+        self.get_by_id(&id).await
+    }
+
+    pub async fn get_all(&self) -> DbResult<Vec<CalendarSubscription>> {
+        let rows = query_as::<_, CalendarSubscriptionRow>(
+            r#"SELECT * FROM calendar_subscriptions ORDER BY name"#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(CalendarSubscription::from).collect())
+    }
+
+    pub async fn get_by_id(&self, id: &str) -> DbResult<CalendarSubscription> {
+        let row = query_as::<_, CalendarSubscriptionRow>(
+            r#"SELECT * FROM calendar_subscriptions WHERE id = ?"#,
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(CalendarSubscription::from(row))
+    }
+
+    pub async fn get_enabled(&self) -> DbResult<Vec<CalendarSubscription>> {
+        let rows = query_as::<_, CalendarSubscriptionRow>(
+            r#"SELECT * FROM calendar_subscriptions WHERE enabled = 1 ORDER BY name"#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(CalendarSubscription::from).collect())
+    }
+
+    pub async fn update(
+        &self,
+        id: &str,
+        input: UpdateCalendarSubscriptionInput,
+    ) -> DbResult<CalendarSubscription> {
+        let mut current = self.get_by_id(id).await?;
+
+        if let Some(name) = input.name {
+            current.name = name;
+        }
+        if let Some(path) = input.source_path {
+            current.source_path = path;
+        }
+        if let Some(path) = input.audio_file_path {
+            current.audio_file_path = path;
+        }
+        if let Some(lead_minutes) = input.lead_minutes {
+            current.lead_minutes = lead_minutes;
+        }
+        if let Some(volume) = input.volume {
+            current.volume = volume;
+        }
+        if let Some(enabled) = input.enabled {
+            current.enabled = enabled;
+        }
+
+        query(
+            r#"
+                UPDATE calendar_subscriptions
+                SET name = ?,
+                    source_path = ?,
+                    audio_file_path = ?,
+                    lead_minutes = ?,
+                    volume = ?,
+                    enabled = ?,
+                    updated_at = ?
+                WHERE id = ?
+            "#,
+        )
+        .bind(&current.name)
+        .bind(&current.source_path)
+        .bind(&current.audio_file_path)
+        .bind(current.lead_minutes as i64)
+        .bind(current.volume as i64)
+        .bind(current.enabled as i64)
+        .bind(Local::now().to_rfc3339())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        self.get_by_id(id).await
+    }
+
+    pub async fn delete(&self, id: &str) -> DbResult<()> {
+        query(r#"DELETE FROM calendar_subscriptions WHERE id = ?"#)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
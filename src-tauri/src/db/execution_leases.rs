@@ -0,0 +1,88 @@
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use sqlx::{query, query_scalar, SqlitePool};
+
+use super::DbResult;
+
+/// Claims on individual occurrences. Whichever engine inserts the lease for an
+/// occurrence first plays it; everyone else skips it.
+#[derive(Clone)]
+pub struct ExecutionLeaseRepository {
+    pool: SqlitePool,
+}
+
+impl ExecutionLeaseRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Key for one scheduled occurrence of a schedule.
+    pub fn schedule_key<Tz: TimeZone>(schedule_id: &str, occurrence: &DateTime<Tz>) -> String {
+        format!("schedule:{schedule_id}:{}", utc_timestamp(occurrence))
+    }
+
+    /// Key for the pre-alert `minutes_before` ahead of one scheduled occurrence.
+    pub fn pre_alert_key<Tz: TimeZone>(
+        schedule_id: &str,
+        occurrence: &DateTime<Tz>,
+        minutes_before: u32,
+    ) -> String {
+        format!(
+            "pre-alert:{schedule_id}:{}:{minutes_before}",
+            utc_timestamp(occurrence)
+        )
+    }
+
+    /// Key for one reminder of a calendar subscription.
+    pub fn reminder_key<Tz: TimeZone>(
+        subscription_id: &str,
+        event_uid: &str,
+        starts_at: &DateTime<Tz>,
+    ) -> String {
+        format!(
+            "subscription:{subscription_id}:{event_uid}:{}",
+            utc_timestamp(starts_at)
+        )
+    }
+
+    /// Returns `true` if `owner` now holds the lease, `false` if another owner
+    /// claimed `key` first. Re-acquiring a lease already held by `owner`
+    /// succeeds.
+    pub async fn try_acquire(&self, key: &str, owner: &str) -> DbResult<bool> {
+        query(
+            r#"
+                INSERT OR IGNORE INTO execution_leases (lease_key, owner, acquired_at)
+                VALUES (?, ?, ?)
+            "#,
+        )
+        .bind(key)
+        .bind(owner)
+        .bind(utc_timestamp(&Utc::now()))
+        .execute(&self.pool)
+        .await?;
+
+        let holder: Option<String> =
+            query_scalar(r#"SELECT owner FROM execution_leases WHERE lease_key = ?"#)
+                .bind(key)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(holder.as_deref() == Some(owner))
+    }
+
+    /// Drops leases acquired before `cutoff`; their occurrences are long past.
+    pub async fn prune_before(&self, cutoff: DateTime<Utc>) -> DbResult<u64> {
+        let result = query(r#"DELETE FROM execution_leases WHERE acquired_at < ?"#)
+            .bind(utc_timestamp(&cutoff))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// UTC with a fixed width so keys and timestamps compare as plain strings.
+fn utc_timestamp<Tz: TimeZone>(value: &DateTime<Tz>) -> String {
+    value
+        .with_timezone(&Utc)
+        .to_rfc3339_opts(SecondsFormat::Millis, true)
+}
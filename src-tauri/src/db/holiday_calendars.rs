@@ -0,0 +1,133 @@
+use chrono::{Local, NaiveDate};
+use sqlx::{query, query_as, SqlitePool};
+use uuid::Uuid;
+
+use super::models::{
+    CreateHolidayCalendarInput, HolidayCalendar, HolidayCalendarRow, UpdateHolidayCalendarInput,
+};
+use super::{DatabaseError, DbResult};
+
+#[derive(Clone)]
+pub struct HolidayCalendarRepository {
+    pool: SqlitePool,
+}
+
+impl HolidayCalendarRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create(&self, input: CreateHolidayCalendarInput) -> DbResult<HolidayCalendar> {
+        let now = Local::now().to_rfc3339();
+        let id = Uuid::new_v4().to_string();
+
+        let CreateHolidayCalendarInput {
+            name,
+            dates,
+            recurring,
+        } = input;
+        let dates_json = serde_json::to_string(&normalize_dates(dates))?;
+        let recurring_json = serde_json::to_string(&recurring)?;
+
+        query(
+            r#"
+                INSERT INTO holiday_calendars (
+                    id,
+                    name,
+                    dates,
+                    recurring,
+                    created_at,
+                    updated_at
+                ) VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&id)
+        .bind(name)
+        .bind(dates_json)
+        .bind(recurring_json)
+        .bind(&now)
+        .bind(&now)
+        .execute(&self.pool)
+        .await?;
+
+        self.get_by_id(&id).await
+    }
+
+    pub async fn get_all(&self) -> DbResult<Vec<HolidayCalendar>> {
+        let rows =
+            query_as::<_, HolidayCalendarRow>(r#"SELECT * FROM holiday_calendars ORDER BY name"#)
+                .fetch_all(&self.pool)
+                .await?;
+
+        rows.into_iter()
+            .map(HolidayCalendar::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(DatabaseError::from)
+    }
+
+    pub async fn get_by_id(&self, id: &str) -> DbResult<HolidayCalendar> {
+        let row =
+            query_as::<_, HolidayCalendarRow>(r#"SELECT * FROM holiday_calendars WHERE id = ?"#)
+                .bind(id)
+                .fetch_one(&self.pool)
+                .await?;
+
+        HolidayCalendar::try_from(row).map_err(DatabaseError::from)
+    }
+
+    pub async fn update(
+        &self,
+        id: &str,
+        input: UpdateHolidayCalendarInput,
+    ) -> DbResult<HolidayCalendar> {
+        let mut current = self.get_by_id(id).await?;
+
+        if let Some(name) = input.name {
+            current.name = name;
+        }
+        if let Some(dates) = input.dates {
+            current.dates = dates;
+        }
+        if let Some(recurring) = input.recurring {
+            current.recurring = recurring;
+        }
+
+        let dates_json = serde_json::to_string(&normalize_dates(current.dates))?;
+        let recurring_json = serde_json::to_string(&current.recurring)?;
+
+        query(
+            r#"
+                UPDATE holiday_calendars
+                SET name = ?,
+                    dates = ?,
+                    recurring = ?,
+                    updated_at = ?
+                WHERE id = ?
+            "#,
+        )
+        .bind(&current.name)
+        .bind(dates_json)
+        .bind(recurring_json)
+        .bind(Local::now().to_rfc3339())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        self.get_by_id(id).await
+    }
+
+    pub async fn delete(&self, id: &str) -> DbResult<()> {
+        query(r#"DELETE FROM holiday_calendars WHERE id = ?"#)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+fn normalize_dates(mut dates: Vec<NaiveDate>) -> Vec<NaiveDate> {
+    dates.sort();
+    dates.dedup();
+    dates
+}
@@ -0,0 +1,62 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::{DatabaseError, DbResult};
+
+const LOCK_FILE_NAME: &str = "resonatify.lock";
+
+/// Exclusive lock on a data directory, held for as long as this value lives.
+/// A second process opening the same directory fails instead of running a
+/// second scheduler against the same database.
+#[derive(Debug)]
+pub struct DataDirLock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl DataDirLock {
+    pub fn acquire(dir: &Path) -> DbResult<Self> {
+        let path = dir.join(LOCK_FILE_NAME);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(std::fs::TryLockError::WouldBlock) => {
+                return Err(DatabaseError::InstanceLocked(path));
+            }
+            Err(std::fs::TryLockError::Error(err)) => return Err(err.into()),
+        }
+
+        // The pid is only informational; the OS lock is what matters.
+        file.set_len(0)?;
+        writeln!(file, "{}", std::process::id())?;
+
+        Ok(Self { path, _file: file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_lock_on_same_directory_fails() {
+        let dir = tempfile::TempDir::new().unwrap();
+
+        let first = DataDirLock::acquire(dir.path()).unwrap();
+        let err = DataDirLock::acquire(dir.path()).unwrap_err();
+        assert!(matches!(err, DatabaseError::InstanceLocked(_)));
+
+        drop(first);
+        assert!(DataDirLock::acquire(dir.path()).is_ok());
+    }
+}
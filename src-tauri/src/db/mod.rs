@@ -1,3 +1,7 @@
+pub mod calendar_subscriptions;
+pub mod execution_leases;
+pub mod holiday_calendars;
+pub mod lock;
 pub mod models;
 pub mod playback_history;
 pub mod schedules;
@@ -8,9 +12,14 @@ use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
 use sqlx::SqlitePool;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
 use tauri::path::BaseDirectory;
 use tauri::{AppHandle, Manager};
 
+use self::calendar_subscriptions::CalendarSubscriptionRepository;
+use self::execution_leases::ExecutionLeaseRepository;
+use self::holiday_calendars::HolidayCalendarRepository;
+use self::lock::DataDirLock;
 use self::models::ModelConversionError;
 use self::playback_history::PlaybackHistoryRepository;
 use self::schedules::ScheduleRepository;
@@ -30,6 +39,8 @@ pub enum DatabaseError {
     InvalidData(String),
     #[error("migration error: {0}")]
     Migration(#[from] MigrateError),
+    #[error("another instance is already using the data directory (lock file {})", .0.display())]
+    InstanceLocked(PathBuf),
 }
 
 pub type DbResult<T> = Result<T, DatabaseError>;
@@ -37,11 +48,22 @@ pub type DbResult<T> = Result<T, DatabaseError>;
 #[derive(Clone)]
 pub struct Database {
     pool: SqlitePool,
+    lock: Option<Arc<DataDirLock>>,
 }
 
 impl Database {
     pub fn new(pool: SqlitePool) -> Self {
-        Self { pool }
+        Self { pool, lock: None }
+    }
+
+    /// Keeps `lock` held for as long as any clone of this database is alive.
+    pub fn with_lock(mut self, lock: DataDirLock) -> Self {
+        self.lock = Some(Arc::new(lock));
+        self
+    }
+
+    pub fn lock(&self) -> Option<&DataDirLock> {
+        self.lock.as_deref()
     }
 
     pub fn pool(&self) -> &SqlitePool {
@@ -59,14 +81,31 @@ impl Database {
     pub fn playback_history_repository(&self) -> PlaybackHistoryRepository {
         PlaybackHistoryRepository::new(self.pool.clone())
     }
+
+    pub fn calendar_subscription_repository(&self) -> CalendarSubscriptionRepository {
+        CalendarSubscriptionRepository::new(self.pool.clone())
+    }
+
+    pub fn holiday_calendar_repository(&self) -> HolidayCalendarRepository {
+        HolidayCalendarRepository::new(self.pool.clone())
+    }
+
+    pub fn execution_lease_repository(&self) -> ExecutionLeaseRepository {
+        ExecutionLeaseRepository::new(self.pool.clone())
+    }
 }
 
 pub async fn init_db(app_handle: &AppHandle) -> DbResult<Database> {
     let db_path = resolve_database_path(app_handle)?;
 
-    if let Some(parent) = db_path.parent() {
-        tokio::fs::create_dir_all(parent).await?;
-    }
+    let data_dir = db_path
+        .parent()
+        .ok_or(DatabaseError::AppDirectoryNotFound)?;
+    tokio::fs::create_dir_all(data_dir).await?;
+
+    // Taken before the database is touched so a second instance never runs
+    // migrations or schedules against it.
+    let lock = DataDirLock::acquire(data_dir)?;
 
     let database_url = format!("sqlite://{}", db_path.to_string_lossy());
 
@@ -82,7 +121,7 @@ pub async fn init_db(app_handle: &AppHandle) -> DbResult<Database> {
 
     sqlx::migrate!("./migrations").run(&pool).await?;
 
-    Ok(Database::new(pool))
+    Ok(Database::new(pool).with_lock(lock))
 }
 
 fn resolve_database_path(app_handle: &AppHandle) -> DbResult<PathBuf> {
@@ -105,7 +144,9 @@ impl From<ModelConversionError> for DatabaseError {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::db::models::{CreateScheduleInput, RepeatType};
+    use crate::db::models::{
+        CreateHolidayCalendarInput, CreateScheduleInput, HolidayRule, RepeatType,
+    };
 
     async fn setup_test_database() -> Database {
         let connect_options = SqliteConnectOptions::from_str("sqlite::memory:")
@@ -144,6 +185,14 @@ mod tests {
                 repeat_type: RepeatType::Daily,
                 volume: 80,
                 last_run_at: None,
+                pre_alerts: Vec::new(),
+                holiday_calendar_id: None,
+                holiday_rule: Default::default(),
+                late_tolerance_seconds: None,
+                min_spacing_seconds: None,
+                dst_policy: Default::default(),
+                audio_source: Default::default(),
+                playback: Default::default(),
             })
             .await
             .unwrap();
@@ -196,12 +245,20 @@ mod tests {
                 repeat_type: RepeatType::Once,
                 volume: 100,
                 last_run_at: None,
+                pre_alerts: Vec::new(),
+                holiday_calendar_id: None,
+                holiday_rule: Default::default(),
+                late_tolerance_seconds: None,
+                min_spacing_seconds: None,
+                dst_policy: Default::default(),
+                audio_source: Default::default(),
+                playback: Default::default(),
             })
             .await
             .unwrap();
 
         let record = history
-            .record(&schedule.id, super::models::PlaybackStatus::Success, None)
+            .record(&schedule.id, super::models::PlaybackStatus::Success, None, None)
             .await
             .unwrap();
 
@@ -216,4 +273,67 @@ mod tests {
         let recent_after_delete = history.list_recent(10).await.unwrap();
         assert!(recent_after_delete.is_empty());
     }
+
+    #[tokio::test]
+    async fn deleting_holiday_calendar_detaches_schedules() {
+        let database = setup_test_database().await;
+        let calendars = database.holiday_calendar_repository();
+        let date = |d| chrono::NaiveDate::from_ymd_opt(2025, 12, d).unwrap();
+
+        let calendar = calendars
+            .create(CreateHolidayCalendarInput {
+                name: "Public holidays".into(),
+                dates: vec![date(26), date(25), date(25)],
+                recurring: Vec::new(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(calendar.dates, vec![date(25), date(26)]);
+
+        let schedule = database
+            .schedule_repository()
+            .create(CreateScheduleInput {
+                name: "Stand-up".into(),
+                audio_file_path: "/tmp/standup.mp3".into(),
+                scheduled_time: "09:00".into(),
+                enabled: true,
+                repeat_type: RepeatType::Weekdays,
+                volume: 60,
+                last_run_at: None,
+                pre_alerts: Vec::new(),
+                holiday_calendar_id: Some(calendar.id.clone()),
+                holiday_rule: HolidayRule::NextBusinessDay,
+                late_tolerance_seconds: None,
+                min_spacing_seconds: None,
+                dst_policy: Default::default(),
+                audio_source: Default::default(),
+                playback: Default::default(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(schedule.holiday_rule, HolidayRule::NextBusinessDay);
+
+        calendars.delete(&calendar.id).await.unwrap();
+
+        let detached = database
+            .schedule_repository()
+            .get_by_id(&schedule.id)
+            .await
+            .unwrap();
+        assert!(detached.holiday_calendar_id.is_none());
+    }
+
+    #[tokio::test]
+    async fn pre_alert_leases_are_claimed_once_per_lead_time() {
+        let database = setup_test_database().await;
+        let leases = database.execution_lease_repository();
+        let occurrence = chrono::Utc::now();
+        let ten = ExecutionLeaseRepository::pre_alert_key("chime", &occurrence, 10);
+        let one = ExecutionLeaseRepository::pre_alert_key("chime", &occurrence, 1);
+
+        assert!(leases.try_acquire(&ten, "first").await.unwrap());
+        assert!(!leases.try_acquire(&ten, "second").await.unwrap());
+        assert!(leases.try_acquire(&one, "second").await.unwrap());
+        assert_ne!(ten, ExecutionLeaseRepository::schedule_key("chime", &occurrence));
+    }
 }
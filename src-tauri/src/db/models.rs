@@ -1,8 +1,10 @@
-use chrono::Weekday;
+use chrono::{NaiveDate, Weekday};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use thiserror::Error;
 
+use crate::audio::{GeneratedSound, PlaybackOptions};
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum RepeatType {
@@ -11,7 +13,16 @@ pub enum RepeatType {
     Weekly { days: Vec<Weekday> },
     Weekdays,
     Weekends,
-    Custom { interval_minutes: u32 },
+    /// Fires every `interval_minutes` starting at `scheduled_time`, restarting
+    /// each day. `until` ("HH:MM") caps the day's last slot.
+    Custom {
+        interval_minutes: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        until: Option<String>,
+    },
+    /// iCalendar (RFC 5545) recurrence: an `RRULE` value, optionally written as
+    /// content lines together with `DTSTART` and `EXDATE`.
+    Rrule { rule: String },
 }
 
 impl RepeatType {
@@ -27,6 +38,124 @@ impl RepeatType {
     }
 }
 
+/// A lead warning ahead of a schedule's main alarm. Without an audio file the
+/// warning is a notification only.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PreAlert {
+    pub minutes_before: u32,
+    #[serde(default)]
+    pub audio_file_path: Option<String>,
+    /// Falls back to the schedule's volume when unset.
+    #[serde(default)]
+    pub volume: Option<u8>,
+}
+
+/// What happens to an occurrence that falls on a date in the schedule's
+/// holiday calendar. Business days are Monday to Friday, minus holidays.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HolidayRule {
+    #[default]
+    Skip,
+    PreviousBusinessDay,
+    NextBusinessDay,
+}
+
+impl HolidayRule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HolidayRule::Skip => "skip",
+            HolidayRule::PreviousBusinessDay => "previous_business_day",
+            HolidayRule::NextBusinessDay => "next_business_day",
+        }
+    }
+}
+
+/// Which instant fires when a scheduled wall-clock time occurs twice because
+/// clocks fall back.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FallBackPolicy {
+    #[default]
+    First,
+    Second,
+    Both,
+}
+
+/// What happens when a scheduled wall-clock time does not exist because clocks
+/// spring forward.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SpringForwardPolicy {
+    /// Fire at the first wall-clock time after the gap.
+    #[default]
+    ShiftForward,
+    /// Drop that day's occurrence.
+    Skip,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct DstPolicy {
+    #[serde(default)]
+    pub fall_back: FallBackPolicy,
+    #[serde(default)]
+    pub spring_forward: SpringForwardPolicy,
+}
+
+/// How a playlist is played each time its schedule fires.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaylistMode {
+    /// Every item, in order.
+    #[default]
+    Sequential,
+    /// Every item, in a fresh random order.
+    Shuffle,
+    /// One item picked at random.
+    Random,
+    /// One item, advancing to the next on each firing.
+    Rotate,
+}
+
+/// Which file a folder source picks each time its schedule fires.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DirectoryOrder {
+    /// A file picked at random.
+    #[default]
+    Random,
+    /// The most recently modified file.
+    Newest,
+    /// The next file by name, advancing on each firing.
+    Alphabetical,
+}
+
+/// Where a schedule's audio comes from.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AudioSource {
+    /// The schedule's `audio_file_path`.
+    #[default]
+    File,
+    Playlist {
+        items: Vec<String>,
+        #[serde(default)]
+        mode: PlaylistMode,
+    },
+    /// A file picked from a folder when the schedule fires, so new sounds
+    /// can be dropped in without editing the schedule.
+    Directory {
+        path: String,
+        #[serde(default)]
+        order: DirectoryOrder,
+        /// Also look in sub-folders.
+        #[serde(default)]
+        recursive: bool,
+    },
+    /// A sound synthesized when the schedule fires, which needs no file.
+    Generated { sound: GeneratedSound },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Schedule {
     pub id: String,
@@ -39,6 +168,28 @@ pub struct Schedule {
     pub created_at: String,
     pub updated_at: String,
     pub last_run_at: Option<String>,
+    #[serde(default)]
+    pub pre_alerts: Vec<PreAlert>,
+    #[serde(default)]
+    pub holiday_calendar_id: Option<String>,
+    #[serde(default)]
+    pub holiday_rule: HolidayRule,
+    /// How late a missed run may still fire; `None` uses the global setting.
+    #[serde(default)]
+    pub late_tolerance_seconds: Option<u32>,
+    /// Minimum time since the previous run before a late run fires; `None`
+    /// uses the global setting.
+    #[serde(default)]
+    pub min_spacing_seconds: Option<u32>,
+    #[serde(default)]
+    pub dst_policy: DstPolicy,
+    #[serde(default)]
+    pub audio_source: AudioSource,
+    /// Index of the playlist item a rotating playlist plays next.
+    #[serde(default)]
+    pub audio_source_position: u32,
+    #[serde(default)]
+    pub playback: PlaybackOptions,
 }
 
 #[derive(Debug, Clone, FromRow)]
@@ -54,6 +205,15 @@ pub struct ScheduleRow {
     pub created_at: String,
     pub updated_at: String,
     pub last_run_at: Option<String>,
+    pub pre_alerts: String,
+    pub holiday_calendar_id: Option<String>,
+    pub holiday_rule: String,
+    pub late_tolerance_seconds: Option<i64>,
+    pub min_spacing_seconds: Option<i64>,
+    pub dst_policy: String,
+    pub audio_source: String,
+    pub audio_source_position: i64,
+    pub playback: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -65,6 +225,22 @@ pub struct CreateScheduleInput {
     pub repeat_type: RepeatType,
     pub volume: u8,
     pub last_run_at: Option<String>,
+    #[serde(default)]
+    pub pre_alerts: Vec<PreAlert>,
+    #[serde(default)]
+    pub holiday_calendar_id: Option<String>,
+    #[serde(default)]
+    pub holiday_rule: HolidayRule,
+    #[serde(default)]
+    pub late_tolerance_seconds: Option<u32>,
+    #[serde(default)]
+    pub min_spacing_seconds: Option<u32>,
+    #[serde(default)]
+    pub dst_policy: DstPolicy,
+    #[serde(default)]
+    pub audio_source: AudioSource,
+    #[serde(default)]
+    pub playback: PlaybackOptions,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -76,6 +252,152 @@ pub struct UpdateScheduleInput {
     pub repeat_type: Option<RepeatType>,
     pub volume: Option<u8>,
     pub last_run_at: Option<String>,
+    pub pre_alerts: Option<Vec<PreAlert>>,
+    /// An empty string detaches the holiday calendar.
+    pub holiday_calendar_id: Option<String>,
+    pub holiday_rule: Option<HolidayRule>,
+    /// `Some(None)` clears the override and falls back to the global setting.
+    #[serde(default, deserialize_with = "deserialize_override")]
+    pub late_tolerance_seconds: Option<Option<u32>>,
+    #[serde(default, deserialize_with = "deserialize_override")]
+    pub min_spacing_seconds: Option<Option<u32>>,
+    pub dst_policy: Option<DstPolicy>,
+    /// Replacing the source restarts a rotating playlist from its first item.
+    pub audio_source: Option<AudioSource>,
+    pub playback: Option<PlaybackOptions>,
+}
+
+/// Distinguishes an explicit `null` (clear the override) from a missing field
+/// (leave it unchanged).
+fn deserialize_override<'de, D>(deserializer: D) -> Result<Option<Option<u32>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Option::<u32>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HolidayCalendar {
+    pub id: String,
+    pub name: String,
+    pub dates: Vec<NaiveDate>,
+    #[serde(default)]
+    pub recurring: Vec<RecurringHoliday>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct HolidayCalendarRow {
+    pub id: String,
+    pub name: String,
+    pub dates: String,
+    pub recurring: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl TryFrom<HolidayCalendarRow> for HolidayCalendar {
+    type Error = serde_json::Error;
+
+    fn try_from(row: HolidayCalendarRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            name: row.name,
+            dates: serde_json::from_str(&row.dates)?,
+            recurring: serde_json::from_str(&row.recurring)?,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateHolidayCalendarInput {
+    pub name: String,
+    pub dates: Vec<NaiveDate>,
+    #[serde(default)]
+    pub recurring: Vec<RecurringHoliday>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct UpdateHolidayCalendarInput {
+    pub name: Option<String>,
+    pub dates: Option<Vec<NaiveDate>>,
+    pub recurring: Option<Vec<RecurringHoliday>>,
+}
+
+/// A holiday that repeats, such as a yearly `RRULE` in an imported calendar.
+/// It is expanded around each lookup, so it applies however far ahead that is.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RecurringHoliday {
+    /// `DTSTART`, `RRULE` and `EXDATE` content lines, as in
+    /// [`RepeatType::Rrule`].
+    pub rule: String,
+    /// Days each occurrence covers.
+    pub days: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalendarSubscription {
+    pub id: String,
+    pub name: String,
+    pub source_path: String,
+    pub audio_file_path: String,
+    pub lead_minutes: u32,
+    pub volume: u8,
+    pub enabled: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct CalendarSubscriptionRow {
+    pub id: String,
+    pub name: String,
+    pub source_path: String,
+    pub audio_file_path: String,
+    pub lead_minutes: i64,
+    pub volume: i64,
+    pub enabled: i64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<CalendarSubscriptionRow> for CalendarSubscription {
+    fn from(row: CalendarSubscriptionRow) -> Self {
+        Self {
+            id: row.id,
+            name: row.name,
+            source_path: row.source_path,
+            audio_file_path: row.audio_file_path,
+            lead_minutes: row.lead_minutes.max(0) as u32,
+            volume: row.volume as u8,
+            enabled: row.enabled != 0,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateCalendarSubscriptionInput {
+    pub name: String,
+    pub source_path: String,
+    pub audio_file_path: String,
+    pub lead_minutes: u32,
+    pub volume: u8,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct UpdateCalendarSubscriptionInput {
+    pub name: Option<String>,
+    pub source_path: Option<String>,
+    pub audio_file_path: Option<String>,
+    pub lead_minutes: Option<u32>,
+    pub volume: Option<u8>,
+    pub enabled: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -131,6 +453,21 @@ impl From<Vec<Setting>> for SettingsSnapshot {
                 "announcement_sound" => {
                     snapshot.announcement_sound = setting.value
                 }
+                "calendar_feed_path" => {
+                    snapshot.calendar_feed_path = Some(setting.value)
+                }
+                "output_device" => snapshot.output_device = Some(setting.value),
+                "late_tolerance_seconds" => {
+                    if let Ok(value) = setting.value.parse::<u32>() {
+                        snapshot.late_tolerance_seconds = value;
+                    }
+                }
+                "min_spacing_seconds" => {
+                    if let Ok(value) = setting.value.parse::<u32>() {
+                        snapshot.min_spacing_seconds = value;
+                    }
+                }
+                "language" => snapshot.language = setting.value,
                 _ => {}
             }
         }
@@ -149,6 +486,14 @@ pub struct SettingsSnapshot {
     pub default_volume: u8,
     pub announcement_enabled: bool,
     pub announcement_sound: String,
+    pub calendar_feed_path: Option<String>,
+    /// Device schedules play through unless they name one; `None` is the
+    /// system default.
+    pub output_device: Option<String>,
+    pub late_tolerance_seconds: u32,
+    pub min_spacing_seconds: u32,
+    /// Language code schedule descriptions are written in, e.g. "en" or "es".
+    pub language: String,
 }
 
 impl Default for SettingsSnapshot {
@@ -162,6 +507,11 @@ impl Default for SettingsSnapshot {
             default_volume: 80,
             announcement_enabled: true,
             announcement_sound: "spell".into(),
+            calendar_feed_path: None,
+            output_device: None,
+            late_tolerance_seconds: 60,
+            min_spacing_seconds: 60,
+            language: "en".into(),
         }
     }
 }
@@ -181,6 +531,15 @@ pub struct PlaybackHistory {
     pub played_at: String,
     pub status: PlaybackStatus,
     pub error_message: Option<String>,
+    /// The file that played (or failed to), if known.
+    pub audio_file_path: Option<String>,
+    /// The output device the file played through.
+    pub output_device: Option<String>,
+    /// Anything worth knowing about a playback that succeeded, e.g. that it
+    /// fell back to the default device.
+    pub note: Option<String>,
+    /// How long the file actually played, once it has stopped.
+    pub played_ms: Option<i64>,
 }
 
 #[derive(Debug, Clone, FromRow)]
@@ -190,6 +549,10 @@ pub struct PlaybackHistoryRow {
     pub played_at: String,
     pub status: String,
     pub error_message: Option<String>,
+    pub audio_file_path: Option<String>,
+    pub output_device: Option<String>,
+    pub note: Option<String>,
+    pub played_ms: Option<i64>,
 }
 
 #[derive(Debug, Error)]
@@ -203,6 +566,12 @@ impl TryFrom<ScheduleRow> for Schedule {
 
     fn try_from(row: ScheduleRow) -> Result<Self, Self::Error> {
         let repeat_type: RepeatType = serde_json::from_str(&row.repeat_type)?;
+        let pre_alerts: Vec<PreAlert> = serde_json::from_str(&row.pre_alerts)?;
+        let dst_policy: DstPolicy = serde_json::from_str(&row.dst_policy)?;
+        let audio_source: AudioSource = serde_json::from_str(&row.audio_source)?;
+        let playback: PlaybackOptions = serde_json::from_str(&row.playback)?;
+        let holiday_rule: HolidayRule =
+            serde_json::from_value(serde_json::Value::String(row.holiday_rule))?;
 
         Ok(Schedule {
             id: row.id,
@@ -215,6 +584,15 @@ impl TryFrom<ScheduleRow> for Schedule {
             created_at: row.created_at,
             updated_at: row.updated_at,
             last_run_at: row.last_run_at,
+            pre_alerts,
+            holiday_calendar_id: row.holiday_calendar_id,
+            holiday_rule,
+            late_tolerance_seconds: row.late_tolerance_seconds.map(|value| value.max(0) as u32),
+            min_spacing_seconds: row.min_spacing_seconds.map(|value| value.max(0) as u32),
+            dst_policy,
+            audio_source,
+            audio_source_position: row.audio_source_position.max(0) as u32,
+            playback,
         })
     }
 }
@@ -240,6 +618,10 @@ impl TryFrom<PlaybackHistoryRow> for PlaybackHistory {
             played_at: row.played_at,
             status,
             error_message: row.error_message,
+            audio_file_path: row.audio_file_path,
+            output_device: row.output_device,
+            note: row.note,
+            played_ms: row.played_ms,
         })
     }
 }
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use chrono::Local;
 use sqlx::{query, query_as, SqlitePool};
 use uuid::Uuid;
@@ -19,7 +21,41 @@ impl PlaybackHistoryRepository {
         &self,
         schedule_id: &str,
         status: PlaybackStatus,
+        audio_file_path: Option<&str>,
+        error_message: Option<String>,
+    ) -> DbResult<PlaybackHistory> {
+        self.insert(schedule_id, status, audio_file_path, error_message, None, None)
+            .await
+    }
+
+    /// Records a file that played, with the device it played through and an
+    /// optional note such as a device fallback.
+    pub async fn record_played(
+        &self,
+        schedule_id: &str,
+        audio_file_path: &str,
+        output_device: Option<&str>,
+        note: Option<&str>,
+    ) -> DbResult<PlaybackHistory> {
+        self.insert(
+            schedule_id,
+            PlaybackStatus::Success,
+            Some(audio_file_path),
+            None,
+            output_device,
+            note,
+        )
+        .await
+    }
+
+    async fn insert(
+        &self,
+        schedule_id: &str,
+        status: PlaybackStatus,
+        audio_file_path: Option<&str>,
         error_message: Option<String>,
+        output_device: Option<&str>,
+        note: Option<&str>,
     ) -> DbResult<PlaybackHistory> {
         let id = Uuid::new_v4().to_string();
         let status_str = match status {
@@ -35,8 +71,11 @@ impl PlaybackHistoryRepository {
                     schedule_id,
                     played_at,
                     status,
-                    error_message
-                ) VALUES (?, ?, ?, ?, ?)
+                    error_message,
+                    audio_file_path,
+                    output_device,
+                    note
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(&id)
@@ -44,12 +83,26 @@ impl PlaybackHistoryRepository {
         .bind(Local::now().to_rfc3339())
         .bind(status_str)
         .bind(error_message)
+        .bind(audio_file_path)
+        .bind(output_device)
+        .bind(note)
         .execute(&self.pool)
         .await?;
 
         self.get_by_id(&id).await
     }
 
+    /// Records how long the playback behind entry `id` actually lasted.
+    pub async fn set_played_duration(&self, id: &str, played: Duration) -> DbResult<()> {
+        query(r#"UPDATE audio_playback_history SET played_ms = ? WHERE id = ?"#)
+            .bind(played.as_millis() as i64)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn get_by_id(&self, id: &str) -> DbResult<PlaybackHistory> {
         let row = query_as::<_, PlaybackHistoryRow>(
             r#"SELECT * FROM audio_playback_history WHERE id = ?"#,
@@ -27,6 +27,14 @@ impl ScheduleRepository {
             repeat_type,
             volume,
             last_run_at,
+            pre_alerts,
+            holiday_calendar_id,
+            holiday_rule,
+            late_tolerance_seconds,
+            min_spacing_seconds,
+            dst_policy,
+            audio_source,
+            playback,
         } = input;
 
         let repeat_type_json = serde_json::to_string(&repeat_type)?;
@@ -34,6 +42,10 @@ impl ScheduleRepository {
             .repeat_days()
             .map(|days| serde_json::to_string(&days))
             .transpose()?;
+        let pre_alerts_json = serde_json::to_string(&pre_alerts)?;
+        let dst_policy_json = serde_json::to_string(&dst_policy)?;
+        let audio_source_json = serde_json::to_string(&audio_source)?;
+        let playback_json = serde_json::to_string(&playback)?;
 
         query(
             r#"
@@ -47,9 +59,17 @@ impl ScheduleRepository {
                     repeat_days,
                     volume,
                     last_run_at,
+                    pre_alerts,
+                    holiday_calendar_id,
+                    holiday_rule,
+                    late_tolerance_seconds,
+                    min_spacing_seconds,
+                    dst_policy,
+                    audio_source,
+                    playback,
                     created_at,
                     updated_at
-                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(&id)
@@ -61,6 +81,14 @@ impl ScheduleRepository {
         .bind(repeat_days_json)
         .bind(volume as i64)
         .bind(last_run_at)
+        .bind(pre_alerts_json)
+        .bind(holiday_calendar_id)
+        .bind(holiday_rule.as_str())
+        .bind(late_tolerance_seconds.map(i64::from))
+        .bind(min_spacing_seconds.map(i64::from))
+        .bind(dst_policy_json)
+        .bind(audio_source_json)
+        .bind(playback_json)
         .bind(&now)
         .bind(&now)
         .execute(&self.pool)
@@ -123,6 +151,31 @@ impl ScheduleRepository {
         if let Some(volume) = input.volume {
             current.volume = volume;
         }
+        if let Some(pre_alerts) = input.pre_alerts {
+            current.pre_alerts = pre_alerts;
+        }
+        if let Some(calendar_id) = input.holiday_calendar_id {
+            current.holiday_calendar_id = Some(calendar_id).filter(|id| !id.is_empty());
+        }
+        if let Some(rule) = input.holiday_rule {
+            current.holiday_rule = rule;
+        }
+        if let Some(tolerance) = input.late_tolerance_seconds {
+            current.late_tolerance_seconds = tolerance;
+        }
+        if let Some(spacing) = input.min_spacing_seconds {
+            current.min_spacing_seconds = spacing;
+        }
+        if let Some(policy) = input.dst_policy {
+            current.dst_policy = policy;
+        }
+        if let Some(source) = input.audio_source {
+            current.audio_source = source;
+            current.audio_source_position = 0;
+        }
+        if let Some(playback) = input.playback {
+            current.playback = playback;
+        }
 
         let now = Local::now().to_rfc3339();
         let repeat_type_json = serde_json::to_string(&current.repeat_type)?;
@@ -131,6 +184,10 @@ impl ScheduleRepository {
             .repeat_days()
             .map(|days| serde_json::to_string(&days))
             .transpose()?;
+        let pre_alerts_json = serde_json::to_string(&current.pre_alerts)?;
+        let dst_policy_json = serde_json::to_string(&current.dst_policy)?;
+        let audio_source_json = serde_json::to_string(&current.audio_source)?;
+        let playback_json = serde_json::to_string(&current.playback)?;
 
         query(
             r#"
@@ -142,6 +199,15 @@ impl ScheduleRepository {
                     repeat_type = ?,
                     repeat_days = ?,
                     volume = ?,
+                    pre_alerts = ?,
+                    holiday_calendar_id = ?,
+                    holiday_rule = ?,
+                    late_tolerance_seconds = ?,
+                    min_spacing_seconds = ?,
+                    dst_policy = ?,
+                    audio_source = ?,
+                    audio_source_position = ?,
+                    playback = ?,
                     updated_at = ?
                 WHERE id = ?
             "#,
@@ -153,6 +219,15 @@ impl ScheduleRepository {
         .bind(repeat_type_json)
         .bind(repeat_days_json)
         .bind(current.volume as i64)
+        .bind(pre_alerts_json)
+        .bind(&current.holiday_calendar_id)
+        .bind(current.holiday_rule.as_str())
+        .bind(current.late_tolerance_seconds.map(i64::from))
+        .bind(current.min_spacing_seconds.map(i64::from))
+        .bind(dst_policy_json)
+        .bind(audio_source_json)
+        .bind(current.audio_source_position as i64)
+        .bind(playback_json)
         .bind(&now)
         .bind(id)
         .execute(&self.pool)
@@ -161,6 +236,18 @@ impl ScheduleRepository {
         self.get_by_id(id).await
     }
 
+    /// Stores where a rotating playlist continues from, without touching
+    /// `updated_at`.
+    pub async fn set_audio_source_position(&self, id: &str, position: u32) -> DbResult<()> {
+        query(r#"UPDATE schedules SET audio_source_position = ? WHERE id = ?"#)
+            .bind(position as i64)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn delete(&self, id: &str) -> DbResult<()> {
         query(r#"DELETE FROM schedules WHERE id = ?"#)
             .bind(id)
@@ -1,11 +1,12 @@
 pub mod audio;
+pub mod calendar;
 pub mod commands;
 pub mod db;
 pub mod scheduler;
 mod tray;
 
 use db::Database;
-use tauri::Manager;
+use tauri::{Emitter, Manager};
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
@@ -23,13 +24,61 @@ pub struct AppState {
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
+        // Must be registered first so a second launch exits before touching
+        // the database; its arguments are handed to the running instance.
+        .plugin(tauri_plugin_single_instance::init(|app, args, _cwd| {
+            let minimized = args.iter().any(|arg| arg == "--minimized");
+            if let Some(window) = app.get_webview_window("main") {
+                if !minimized {
+                    let _ = window.show();
+                    let _ = window.unminimize();
+                    let _ = window.set_focus();
+                }
+            }
+            let _ = app.emit("single-instance", args);
+        }))
         .setup(|app| {
             let handle = app.handle();
             let database = tauri::async_runtime::block_on(db::init_db(handle))
                 .map_err(|err| -> Box<dyn std::error::Error> { Box::new(err) })?;
 
-            let audio_service = audio::AudioService::new()
-                .map_err(|err| -> Box<dyn std::error::Error> { Box::new(err) })?;
+            let backend = audio::OutputBackend::from_env().unwrap_or_else(|err| {
+                eprintln!("{}; using the default audio device", err);
+                audio::OutputBackend::Device
+            });
+            // Starts degraded without a sound device and keeps retrying.
+            let audio_service = audio::AudioService::with_backend(
+                audio::validator::AudioValidator::default(),
+                backend,
+            )
+            .map_err(|err| -> Box<dyn std::error::Error> { Box::new(err) })?;
+            let output = tauri::async_runtime::block_on(audio_service.output_status());
+            if let Some(error) = &output.error {
+                eprintln!("Starting without audio output: {}", error);
+            }
+            let default_device = tauri::async_runtime::block_on(
+                database
+                    .settings_repository()
+                    .get(audio::output::OUTPUT_DEVICE_SETTING),
+            )
+            .map_err(|err| -> Box<dyn std::error::Error> { Box::new(err) })?;
+            audio_service.set_default_device(default_device.map(|setting| setting.value));
+
+            // Let the frontend know when a sound stops, e.g. to reset a
+            // preview button once the file has played.
+            let mut completions = audio_service.subscribe_completions();
+            let completion_app = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    match completions.recv().await {
+                        Ok(completion) => {
+                            let _ = completion_app.emit("audio-playback-completed", completion);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
 
             let scheduler_engine =
                 scheduler::SchedulerEngine::new_with_app(database.clone(), audio_service.clone(), app.handle().clone());
@@ -85,8 +134,25 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             commands::audio::validate_audio_file,
             commands::audio::play_audio_file,
+            commands::audio::play_generated_sound,
             commands::audio::stop_audio,
+            commands::audio::stop_audio_voice,
+            commands::audio::pause_audio,
+            commands::audio::resume_audio,
+            commands::audio::seek_audio,
+            commands::audio::get_audio_progress,
             commands::audio::get_audio_status,
+            commands::audio::list_output_devices,
+            commands::calendar::export_schedules_ics,
+            commands::calendar::get_calendar_subscriptions,
+            commands::calendar::create_calendar_subscription,
+            commands::calendar::update_calendar_subscription,
+            commands::calendar::delete_calendar_subscription,
+            commands::calendar::get_holiday_calendars,
+            commands::calendar::create_holiday_calendar,
+            commands::calendar::import_holiday_calendar,
+            commands::calendar::update_holiday_calendar,
+            commands::calendar::delete_holiday_calendar,
             commands::dialogs::open_audio_file_dialog,
             commands::scheduler::start_scheduler,
             commands::scheduler::stop_scheduler,
@@ -98,6 +164,8 @@ pub fn run() {
             commands::schedules::update_schedule,
             commands::schedules::delete_schedule,
             commands::schedules::toggle_schedule_enabled,
+            commands::schedules::parse_schedule_phrase,
+            commands::schedules::get_schedule_descriptions,
             commands::settings::get_settings,
             commands::settings::update_settings,
             commands::settings::set_launch_at_login,
@@ -0,0 +1,343 @@
+//! Resolves which files a schedule plays when it fires.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::audio::validator::has_supported_extension;
+use crate::audio::GeneratedSound;
+use crate::db::models::{AudioSource, DirectoryOrder, PlaylistMode, Schedule};
+
+use super::error::SchedulerError;
+
+/// How many folders deep a recursive scan goes.
+const MAX_FOLDER_DEPTH: usize = 16;
+
+/// One thing a firing plays.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlaybackItem {
+    File(String),
+    Generated(GeneratedSound),
+}
+
+/// What the playback history records: the file's path, or a description of
+/// the generated sound.
+impl fmt::Display for PlaybackItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => f.write_str(path),
+            Self::Generated(sound) => write!(f, "{sound}"),
+        }
+    }
+}
+
+/// What to play for one firing, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaybackPlan {
+    pub items: Vec<PlaybackItem>,
+    /// Where a rotating playlist continues from next time.
+    pub next_position: Option<u32>,
+}
+
+/// A playable file found in an audio folder.
+#[derive(Debug, Clone)]
+pub struct FolderFile {
+    pub path: PathBuf,
+    pub modified: Option<SystemTime>,
+}
+
+pub fn plan_playback(schedule: &Schedule) -> Result<PlaybackPlan, SchedulerError> {
+    match &schedule.audio_source {
+        AudioSource::File => Ok(PlaybackPlan {
+            items: vec![PlaybackItem::File(schedule.audio_file_path.clone())],
+            next_position: None,
+        }),
+        AudioSource::Generated { sound } => Ok(PlaybackPlan {
+            items: vec![PlaybackItem::Generated(sound.clone())],
+            next_position: None,
+        }),
+        AudioSource::Playlist { items, mode } => Ok(plan_playlist(
+            items,
+            *mode,
+            schedule.audio_source_position,
+        )),
+        AudioSource::Directory {
+            path,
+            order,
+            recursive,
+        } => {
+            let files = scan_folder(Path::new(path), *recursive)?;
+            Ok(plan_folder(files, *order, schedule.audio_source_position))
+        }
+    }
+}
+
+/// Lists the supported audio files in `path`, sorted by path. Hidden files
+/// are skipped, and a recursive scan visits each folder once, however many
+/// symlinks lead to it, going at most [`MAX_FOLDER_DEPTH`] levels down. An
+/// empty or missing folder is an error.
+pub fn scan_folder(path: &Path, recursive: bool) -> Result<Vec<FolderFile>, SchedulerError> {
+    let display = path.display().to_string();
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => {}
+        Ok(_) => {
+            return Err(SchedulerError::FolderUnreadable {
+                path: display,
+                reason: "not a folder".into(),
+            })
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            return Err(SchedulerError::FolderNotFound(display))
+        }
+        Err(err) => {
+            return Err(SchedulerError::FolderUnreadable {
+                path: display,
+                reason: err.to_string(),
+            })
+        }
+    }
+
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    let depth = if recursive { MAX_FOLDER_DEPTH } else { 0 };
+    collect_folder(path, depth, &mut visited, &mut files).map_err(|err| {
+        SchedulerError::FolderUnreadable {
+            path: display.clone(),
+            reason: err.to_string(),
+        }
+    })?;
+    if files.is_empty() {
+        return Err(SchedulerError::EmptyFolder(display));
+    }
+
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Collects from `dir` and, while `depth` allows, the folders below it.
+/// `visited` holds the canonical paths of folders already scanned, so a
+/// symlink back up the tree does not loop.
+fn collect_folder(
+    dir: &Path,
+    depth: usize,
+    visited: &mut HashSet<PathBuf>,
+    files: &mut Vec<FolderFile>,
+) -> std::io::Result<()> {
+    if !visited.insert(fs::canonicalize(dir)?) {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        let path = entry.path();
+        // Follows symlinks, so linked files and folders are picked up too.
+        let Ok(metadata) = fs::metadata(&path) else {
+            continue;
+        };
+        if metadata.is_dir() {
+            if depth > 0 {
+                collect_folder(&path, depth - 1, visited, files)?;
+            }
+        } else if metadata.is_file() && has_supported_extension(&path) {
+            files.push(FolderFile {
+                path,
+                modified: metadata.modified().ok(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn plan_folder(files: Vec<FolderFile>, order: DirectoryOrder, position: u32) -> PlaybackPlan {
+    if files.is_empty() {
+        return PlaybackPlan {
+            items: Vec::new(),
+            next_position: None,
+        };
+    }
+
+    let (file, next_position) = match order {
+        DirectoryOrder::Random => (&files[fastrand::usize(..files.len())], None),
+        DirectoryOrder::Newest => {
+            let newest = files
+                .iter()
+                .max_by_key(|file| file.modified)
+                .unwrap_or(&files[0]);
+            (newest, None)
+        }
+        DirectoryOrder::Alphabetical => {
+            // Files may have been added or removed since the position was
+            // stored; the rotation carries on from wherever it lands.
+            let index = position as usize % files.len();
+            (&files[index], Some(((index + 1) % files.len()) as u32))
+        }
+    };
+
+    PlaybackPlan {
+        items: vec![PlaybackItem::File(file.path.display().to_string())],
+        next_position,
+    }
+}
+
+fn plan_playlist(items: &[String], mode: PlaylistMode, position: u32) -> PlaybackPlan {
+    if items.is_empty() {
+        return PlaybackPlan {
+            items: Vec::new(),
+            next_position: None,
+        };
+    }
+
+    let files = |items: &[String]| items.iter().cloned().map(PlaybackItem::File).collect();
+    match mode {
+        PlaylistMode::Sequential => PlaybackPlan {
+            items: files(items),
+            next_position: None,
+        },
+        PlaylistMode::Shuffle => {
+            let mut shuffled = items.to_vec();
+            fastrand::shuffle(&mut shuffled);
+            PlaybackPlan {
+                items: files(&shuffled),
+                next_position: None,
+            }
+        }
+        PlaylistMode::Random => {
+            let index = fastrand::usize(..items.len());
+            PlaybackPlan {
+                items: files(&items[index..=index]),
+                next_position: None,
+            }
+        }
+        PlaylistMode::Rotate => {
+            // The playlist may have shrunk since the position was stored.
+            let index = position as usize % items.len();
+            PlaybackPlan {
+                items: files(&items[index..=index]),
+                next_position: Some(((index + 1) % items.len()) as u32),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items() -> Vec<String> {
+        vec!["a.mp3".into(), "b.mp3".into(), "c.mp3".into()]
+    }
+
+    fn files(plan: &PlaybackPlan) -> Vec<String> {
+        plan.items
+            .iter()
+            .map(|item| match item {
+                PlaybackItem::File(path) => path.clone(),
+                PlaybackItem::Generated(sound) => panic!("unexpected {sound}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rotation_advances_and_wraps() {
+        let first = plan_playlist(&items(), PlaylistMode::Rotate, 1);
+        assert_eq!(files(&first), vec!["b.mp3".to_string()]);
+        assert_eq!(first.next_position, Some(2));
+
+        let wrapped = plan_playlist(&items(), PlaylistMode::Rotate, 2);
+        assert_eq!(files(&wrapped), vec!["c.mp3".to_string()]);
+        assert_eq!(wrapped.next_position, Some(0));
+
+        // A stale position past the end still lands on an item.
+        let stale = plan_playlist(&items(), PlaylistMode::Rotate, 7);
+        assert_eq!(files(&stale), vec!["b.mp3".to_string()]);
+    }
+
+    #[test]
+    fn folder_skips_unsupported_files_and_rotates_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.wav", "a.MP3", "notes.txt", ".hidden.mp3"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        fs::create_dir(dir.path().join("more")).unwrap();
+        fs::write(dir.path().join("more").join("c.ogg"), b"").unwrap();
+
+        let names = |files: &[FolderFile]| {
+            files
+                .iter()
+                .map(|file| file.path.strip_prefix(dir.path()).unwrap().to_path_buf())
+                .collect::<Vec<_>>()
+        };
+
+        let flat = scan_folder(dir.path(), false).unwrap();
+        assert_eq!(names(&flat), vec![PathBuf::from("a.MP3"), "b.wav".into()]);
+
+        let nested = scan_folder(dir.path(), true).unwrap();
+        assert_eq!(
+            names(&nested),
+            vec![
+                PathBuf::from("a.MP3"),
+                "b.wav".into(),
+                Path::new("more").join("c.ogg")
+            ]
+        );
+
+        let plan = plan_folder(nested, DirectoryOrder::Alphabetical, 1);
+        assert!(files(&plan)[0].ends_with("b.wav"));
+        assert_eq!(plan.next_position, Some(2));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn folder_symlink_loops_are_scanned_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("nested");
+        fs::create_dir(&nested).unwrap();
+        fs::write(dir.path().join("a.mp3"), b"").unwrap();
+        fs::write(nested.join("b.mp3"), b"").unwrap();
+        std::os::unix::fs::symlink(dir.path(), nested.join("back")).unwrap();
+        std::os::unix::fs::symlink(&nested, dir.path().join("again")).unwrap();
+
+        let files = scan_folder(dir.path(), true).unwrap();
+        let names = files
+            .iter()
+            .map(|file| file.path.file_name().unwrap().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["a.mp3", "b.mp3"]);
+    }
+
+    #[test]
+    fn empty_or_missing_folder_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("readme.txt"), b"").unwrap();
+
+        assert!(matches!(
+            scan_folder(dir.path(), true),
+            Err(SchedulerError::EmptyFolder(_))
+        ));
+        assert!(matches!(
+            scan_folder(&dir.path().join("missing"), false),
+            Err(SchedulerError::FolderNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn whole_playlist_modes_play_every_item() {
+        let sequential = plan_playlist(&items(), PlaylistMode::Sequential, 0);
+        assert_eq!(files(&sequential), items());
+
+        let mut shuffled = files(&plan_playlist(&items(), PlaylistMode::Shuffle, 0));
+        shuffled.sort();
+        assert_eq!(shuffled, items());
+
+        let random = plan_playlist(&items(), PlaylistMode::Random, 0);
+        assert_eq!(files(&random).len(), 1);
+        assert!(items().contains(&files(&random)[0]));
+    }
+}
@@ -0,0 +1,698 @@
+//! Human-readable descriptions of schedules ("Every weekday at 07:30",
+//! "Next: tomorrow 07:30"), shared by notifications, the tray, calendar
+//! exports and the phrase parser so they all word things the same way. The
+//! wording comes from a [`Strings`] table per [`Language`].
+
+use chrono::{DateTime, Datelike, Local, Weekday};
+
+use crate::db::models::{RepeatType, Schedule};
+
+use super::recurrence::{Frequency, RecurrenceRule, RecurrenceSet};
+
+const TIME_FORMAT: &str = "%H:%M";
+
+/// Languages descriptions can be written in, chosen by the `language`
+/// setting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    English,
+    Spanish,
+}
+
+impl Language {
+    /// The language for a code such as "es" or "es-MX"; anything unknown is
+    /// English.
+    pub fn from_code(code: &str) -> Self {
+        let primary = code.split(['-', '_']).next().unwrap_or_default();
+        match primary.to_ascii_lowercase().as_str() {
+            "es" => Language::Spanish,
+            _ => Language::English,
+        }
+    }
+
+    fn strings(self) -> &'static Strings {
+        match self {
+            Language::English => &ENGLISH,
+            Language::Spanish => &SPANISH,
+        }
+    }
+}
+
+/// The words and sentence templates for one language. Templates name their
+/// arguments in braces, e.g. `"Every {n} {units}"`.
+struct Strings {
+    at: &'static str,
+    once: &'static str,
+    every_day: &'static str,
+    every_weekday: &'static str,
+    every_weekend_day: &'static str,
+    no_days: &'static str,
+    every_minute: &'static str,
+    every_hour: &'static str,
+    every_n_hours: &'static str,
+    every_n_minutes: &'static str,
+    interval_until: &'static str,
+    interval_starting: &'static str,
+    custom: &'static str,
+    custom_rule: &'static str,
+    every_unit: &'static str,
+    every_other_unit: &'static str,
+    every_n_units: &'static str,
+    /// Singular and plural of day, week, month and year.
+    units: [(&'static str, &'static str); 4],
+    every_days: &'static str,
+    every_other_week_days: &'static str,
+    every_n_weeks_days: &'static str,
+    of_months: &'static str,
+    yearly_on: &'static str,
+    month_date: &'static str,
+    day_of_month: &'static str,
+    one_time: &'static str,
+    n_times: &'static str,
+    until: &'static str,
+    and: &'static str,
+    last_day: &'static str,
+    nth_last_day: &'static str,
+    /// `first` to `fifth`, then `last`.
+    ordinals: [&'static str; 6],
+    nth_last: &'static str,
+    ordinal_number: fn(i32) -> String,
+    /// A day of the month, e.g. "15th" or "15".
+    month_day_number: fn(i32) -> String,
+    days: [&'static str; 7],
+    short_days: [&'static str; 7],
+    months: [&'static str; 12],
+    short_months: [&'static str; 12],
+    next: &'static str,
+    no_next: &'static str,
+    today: &'static str,
+    tomorrow: &'static str,
+    this_week: &'static str,
+    later: &'static str,
+}
+
+const ENGLISH: Strings = Strings {
+    at: " at {time}",
+    once: "Once",
+    every_day: "Every day",
+    every_weekday: "Every weekday",
+    every_weekend_day: "Every weekend day",
+    no_days: "Weekly, no days selected",
+    every_minute: "Every minute",
+    every_hour: "Every hour",
+    every_n_hours: "Every {n} hours",
+    every_n_minutes: "Every {n} minutes",
+    interval_until: "{every} from {start} until {until}",
+    interval_starting: "{every} starting {start}",
+    custom: "Custom recurrence",
+    custom_rule: "Custom recurrence ({rule})",
+    every_unit: "Every {unit}",
+    every_other_unit: "Every other {unit}",
+    every_n_units: "Every {n} {units}",
+    units: [
+        ("day", "days"),
+        ("week", "weeks"),
+        ("month", "months"),
+        ("year", "years"),
+    ],
+    every_days: "Every {days}",
+    every_other_week_days: "Every other {days}",
+    every_n_weeks_days: "Every {n} weeks on {days}",
+    of_months: "On the {days} of {every}",
+    yearly_on: "{every} on {date}",
+    month_date: "{month} {day}",
+    day_of_month: "the {day} of {month}",
+    one_time: ", 1 time",
+    n_times: ", {n} times",
+    until: ", until {date}",
+    and: "and",
+    last_day: "last day",
+    nth_last_day: "{nth} last day",
+    ordinals: ["first", "second", "third", "fourth", "fifth", "last"],
+    nth_last: "{nth} last",
+    ordinal_number: english_ordinal,
+    month_day_number: english_ordinal,
+    days: [
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+        "Sunday",
+    ],
+    short_days: ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"],
+    months: [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ],
+    short_months: [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ],
+    next: "Next: {when}",
+    no_next: "No upcoming runs",
+    today: "today {time}",
+    tomorrow: "tomorrow {time}",
+    this_week: "{day} {time}",
+    later: "{weekday} {day} {month} {year} {time}",
+};
+
+const SPANISH: Strings = Strings {
+    at: " a las {time}",
+    once: "Una vez",
+    every_day: "Cada día",
+    every_weekday: "Cada día laborable",
+    every_weekend_day: "Cada día del fin de semana",
+    no_days: "Semanal, sin días seleccionados",
+    every_minute: "Cada minuto",
+    every_hour: "Cada hora",
+    every_n_hours: "Cada {n} horas",
+    every_n_minutes: "Cada {n} minutos",
+    interval_until: "{every} desde las {start} hasta las {until}",
+    interval_starting: "{every} a partir de las {start}",
+    custom: "Recurrencia personalizada",
+    custom_rule: "Recurrencia personalizada ({rule})",
+    every_unit: "Cada {unit}",
+    every_other_unit: "Cada dos {units}",
+    every_n_units: "Cada {n} {units}",
+    units: [
+        ("día", "días"),
+        ("semana", "semanas"),
+        ("mes", "meses"),
+        ("año", "años"),
+    ],
+    every_days: "Cada {days}",
+    every_other_week_days: "Cada dos semanas el {days}",
+    every_n_weeks_days: "Cada {n} semanas el {days}",
+    of_months: "El {days} de {every}",
+    yearly_on: "{every}, el {date}",
+    month_date: "{day} de {month}",
+    day_of_month: "{day} de {month}",
+    one_time: ", 1 vez",
+    n_times: ", {n} veces",
+    until: ", hasta el {date}",
+    and: "y",
+    last_day: "último día",
+    nth_last_day: "{nth} día desde el final",
+    ordinals: ["primer", "segundo", "tercer", "cuarto", "quinto", "último"],
+    nth_last: "{nth} desde el final",
+    ordinal_number: spanish_ordinal,
+    month_day_number: cardinal,
+    days: [
+        "lunes",
+        "martes",
+        "miércoles",
+        "jueves",
+        "viernes",
+        "sábado",
+        "domingo",
+    ],
+    short_days: ["lun", "mar", "mié", "jue", "vie", "sáb", "dom"],
+    months: [
+        "enero",
+        "febrero",
+        "marzo",
+        "abril",
+        "mayo",
+        "junio",
+        "julio",
+        "agosto",
+        "septiembre",
+        "octubre",
+        "noviembre",
+        "diciembre",
+    ],
+    short_months: [
+        "ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sept", "oct", "nov", "dic",
+    ],
+    next: "Próxima: {when}",
+    no_next: "Sin próximas ejecuciones",
+    today: "hoy {time}",
+    tomorrow: "mañana {time}",
+    this_week: "{day} {time}",
+    later: "{weekday} {day} {month} {year} {time}",
+};
+
+/// Replaces each `{name}` in `template` with its value.
+fn fill(template: &str, args: &[(&str, &str)]) -> String {
+    args.iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{name}}}"), value)
+        })
+}
+
+impl RepeatType {
+    /// Describes the repeat pattern at `scheduled_time` ("HH:MM") in English,
+    /// e.g. "Every weekday at 07:30" or "Every 45 minutes starting 08:00".
+    pub fn describe(&self, scheduled_time: &str) -> String {
+        self.describe_in(scheduled_time, Language::English)
+    }
+
+    pub fn describe_in(&self, scheduled_time: &str, language: Language) -> String {
+        let strings = language.strings();
+        let at = fill(strings.at, &[("time", scheduled_time)]);
+        match self {
+            RepeatType::Once => format!("{}{at}", strings.once),
+            RepeatType::Daily => format!("{}{at}", strings.every_day),
+            RepeatType::Weekdays => format!("{}{at}", strings.every_weekday),
+            RepeatType::Weekends => format!("{}{at}", strings.every_weekend_day),
+            RepeatType::Weekly { days } => {
+                let mut days = days.clone();
+                days.sort_by_key(|day| day.num_days_from_monday());
+                days.dedup();
+                match days.len() {
+                    0 => strings.no_days.to_string(),
+                    7 => format!("{}{at}", strings.every_day),
+                    _ => format!(
+                        "{}{at}",
+                        fill(strings.every_days, &[("days", &join_days(strings, &days))])
+                    ),
+                }
+            }
+            RepeatType::Custom {
+                interval_minutes,
+                until,
+            } => {
+                let every = every_minutes(strings, *interval_minutes);
+                match until {
+                    Some(until) => fill(
+                        strings.interval_until,
+                        &[
+                            ("every", &every),
+                            ("start", scheduled_time),
+                            ("until", until),
+                        ],
+                    ),
+                    None => fill(
+                        strings.interval_starting,
+                        &[("every", &every), ("start", scheduled_time)],
+                    ),
+                }
+            }
+            RepeatType::Rrule { rule } => match rule.parse::<RecurrenceSet>() {
+                Ok(set) => format!("{}{at}", describe_rule(strings, &set.rule)),
+                Err(_) => format!("{}{at}", strings.custom),
+            },
+        }
+    }
+}
+
+impl Schedule {
+    pub fn describe(&self) -> String {
+        self.repeat_type.describe(&self.scheduled_time)
+    }
+
+    pub fn describe_in(&self, language: Language) -> String {
+        self.repeat_type.describe_in(&self.scheduled_time, language)
+    }
+}
+
+/// "Next: today 18:00", "Next: tomorrow 07:30", "Next: Friday 07:30" within
+/// the coming week, otherwise the date; `next` is the run the scheduler has
+/// lined up, if any.
+pub fn describe_next_run(
+    next: Option<DateTime<Local>>,
+    now: DateTime<Local>,
+    language: Language,
+) -> String {
+    let strings = language.strings();
+    match next {
+        Some(next) => fill(strings.next, &[("when", &relative_day(strings, next, now))]),
+        None => strings.no_next.to_string(),
+    }
+}
+
+fn relative_day(strings: &Strings, when: DateTime<Local>, now: DateTime<Local>) -> String {
+    let time = when.format(TIME_FORMAT).to_string();
+    let days_ahead = (when.date_naive() - now.date_naive()).num_days();
+    match days_ahead {
+        0 => fill(strings.today, &[("time", &time)]),
+        1 => fill(strings.tomorrow, &[("time", &time)]),
+        2..=6 => fill(
+            strings.this_week,
+            &[("day", day_name(strings, when.weekday())), ("time", &time)],
+        ),
+        _ => fill(
+            strings.later,
+            &[
+                (
+                    "weekday",
+                    strings.short_days[when.weekday().num_days_from_monday() as usize],
+                ),
+                ("day", &when.day().to_string()),
+                ("month", strings.short_months[when.month0() as usize]),
+                ("year", &when.year().to_string()),
+                ("time", &time),
+            ],
+        ),
+    }
+}
+
+fn describe_rule(strings: &Strings, rule: &RecurrenceRule) -> String {
+    let interval = rule.interval.max(1);
+    let simple = rule.by_set_pos.is_empty() && rule.by_month.is_empty();
+    let [day, week, month, year] = strings.units;
+
+    let mut text = match rule.frequency {
+        Frequency::Daily if simple && rule.by_day.is_empty() && rule.by_month_day.is_empty() => {
+            every(strings, interval, day)
+        }
+        Frequency::Weekly
+            if simple
+                && rule.by_month_day.is_empty()
+                && rule.by_day.iter().all(|entry| entry.ordinal.is_none()) =>
+        {
+            let mut days = rule
+                .by_day
+                .iter()
+                .map(|entry| entry.weekday)
+                .collect::<Vec<_>>();
+            days.sort_by_key(|day| day.num_days_from_monday());
+            days.dedup();
+            let names = join_days(strings, &days);
+            let n = interval.to_string();
+            match (interval, days.is_empty()) {
+                (_, true) => every(strings, interval, week),
+                (1, false) => fill(strings.every_days, &[("days", &names)]),
+                (2, false) => fill(strings.every_other_week_days, &[("days", &names)]),
+                (_, false) => fill(strings.every_n_weeks_days, &[("n", &n), ("days", &names)]),
+            }
+        }
+        Frequency::Monthly if simple && rule.by_day.is_empty() && !rule.by_month_day.is_empty() => {
+            let days = rule
+                .by_month_day
+                .iter()
+                .map(|day| month_day(strings, *day))
+                .collect::<Vec<_>>();
+            fill(
+                strings.of_months,
+                &[
+                    ("days", &join_words(strings, &days)),
+                    ("every", &every(strings, interval, month).to_lowercase()),
+                ],
+            )
+        }
+        Frequency::Monthly
+            if simple
+                && rule.by_month_day.is_empty()
+                && !rule.by_day.is_empty()
+                && rule.by_day.iter().all(|entry| entry.ordinal.is_some()) =>
+        {
+            let days = rule
+                .by_day
+                .iter()
+                .map(|entry| {
+                    format!(
+                        "{} {}",
+                        ordinal_word(strings, entry.ordinal.unwrap_or(1)),
+                        day_name(strings, entry.weekday)
+                    )
+                })
+                .collect::<Vec<_>>();
+            fill(
+                strings.of_months,
+                &[
+                    ("days", &join_words(strings, &days)),
+                    ("every", &every(strings, interval, month).to_lowercase()),
+                ],
+            )
+        }
+        Frequency::Yearly
+            if rule.by_set_pos.is_empty()
+                && rule.by_day.is_empty()
+                && rule.by_month.len() == 1
+                && rule.by_month_day.len() == 1 =>
+        {
+            let day = rule.by_month_day[0];
+            let month = rule.by_month[0]
+                .checked_sub(1)
+                .and_then(|index| strings.months.get(index as usize))
+                .copied()
+                .unwrap_or_default();
+            let date = if day < 0 {
+                fill(
+                    strings.day_of_month,
+                    &[("month", month), ("day", &month_day(strings, day))],
+                )
+            } else {
+                fill(
+                    strings.month_date,
+                    &[("month", month), ("day", &day.to_string())],
+                )
+            };
+            fill(
+                strings.yearly_on,
+                &[("every", &every(strings, interval, year)), ("date", &date)],
+            )
+        }
+        Frequency::Yearly if simple && rule.by_day.is_empty() && rule.by_month_day.is_empty() => {
+            every(strings, interval, year)
+        }
+        _ => return fill(strings.custom_rule, &[("rule", &rule.to_string())]),
+    };
+
+    match rule.count {
+        Some(1) => text.push_str(strings.one_time),
+        Some(count) => text.push_str(&fill(strings.n_times, &[("n", &count.to_string())])),
+        None => {}
+    }
+    if let Some(until) = &rule.until {
+        let date = until.local_date().format("%Y-%m-%d").to_string();
+        text.push_str(&fill(strings.until, &[("date", &date)]));
+    }
+    text
+}
+
+fn every(strings: &Strings, interval: u32, (unit, units): (&str, &str)) -> String {
+    match interval {
+        1 => fill(strings.every_unit, &[("unit", unit)]),
+        2 => fill(
+            strings.every_other_unit,
+            &[("unit", unit), ("units", units)],
+        ),
+        n => fill(
+            strings.every_n_units,
+            &[("n", &n.to_string()), ("units", units)],
+        ),
+    }
+}
+
+fn every_minutes(strings: &Strings, minutes: u32) -> String {
+    match minutes {
+        1 => strings.every_minute.to_string(),
+        60 => strings.every_hour.to_string(),
+        n if n % 60 == 0 => fill(strings.every_n_hours, &[("n", &(n / 60).to_string())]),
+        n => fill(strings.every_n_minutes, &[("n", &n.to_string())]),
+    }
+}
+
+fn month_day(strings: &Strings, day: i32) -> String {
+    match day {
+        -1 => strings.last_day.to_string(),
+        n if n < 0 => fill(
+            strings.nth_last_day,
+            &[("nth", &(strings.ordinal_number)(-n))],
+        ),
+        n => (strings.month_day_number)(n),
+    }
+}
+
+fn ordinal_word(strings: &Strings, ordinal: i32) -> String {
+    match ordinal {
+        -1 => strings.ordinals[5].to_string(),
+        1..=5 => strings.ordinals[ordinal as usize - 1].to_string(),
+        n if n < 0 => fill(strings.nth_last, &[("nth", &(strings.ordinal_number)(-n))]),
+        n => (strings.ordinal_number)(n),
+    }
+}
+
+fn english_ordinal(n: i32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{n}{suffix}")
+}
+
+fn spanish_ordinal(n: i32) -> String {
+    format!("{n}.º")
+}
+
+fn cardinal(n: i32) -> String {
+    n.to_string()
+}
+
+fn join_days(strings: &Strings, days: &[Weekday]) -> String {
+    let names = days
+        .iter()
+        .map(|day| day_name(strings, *day).to_string())
+        .collect::<Vec<_>>();
+    join_words(strings, &names)
+}
+
+/// "a", "a and b", "a, b and c".
+fn join_words(strings: &Strings, words: &[String]) -> String {
+    match words.split_last() {
+        Some((last, rest)) if !rest.is_empty() => {
+            format!("{} {} {last}", rest.join(", "), strings.and)
+        }
+        _ => words.concat(),
+    }
+}
+
+fn day_name(strings: &Strings, day: Weekday) -> &'static str {
+    strings.days[day.num_days_from_monday() as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn describes_builtin_repeat_types() {
+        assert_eq!(
+            RepeatType::Weekdays.describe("07:30"),
+            "Every weekday at 07:30"
+        );
+        assert_eq!(
+            RepeatType::Weekly {
+                days: vec![Weekday::Fri, Weekday::Mon, Weekday::Wed],
+            }
+            .describe("06:00"),
+            "Every Monday, Wednesday and Friday at 06:00"
+        );
+        assert_eq!(
+            RepeatType::Custom {
+                interval_minutes: 45,
+                until: None,
+            }
+            .describe("08:00"),
+            "Every 45 minutes starting 08:00"
+        );
+        assert_eq!(
+            RepeatType::Custom {
+                interval_minutes: 120,
+                until: Some("17:00".into()),
+            }
+            .describe("09:00"),
+            "Every 2 hours from 09:00 until 17:00"
+        );
+    }
+
+    #[test]
+    fn describes_recurrence_rules() {
+        let describe = |rule: &str| RepeatType::Rrule { rule: rule.into() }.describe("12:00");
+
+        assert_eq!(
+            describe("FREQ=MONTHLY;BYDAY=1MO"),
+            "On the first Monday of every month at 12:00"
+        );
+        assert_eq!(
+            describe("FREQ=MONTHLY;INTERVAL=2;BYMONTHDAY=1,15"),
+            "On the 1st and 15th of every other month at 12:00"
+        );
+        assert_eq!(
+            describe("FREQ=WEEKLY;INTERVAL=2;BYDAY=TU;COUNT=4"),
+            "Every other Tuesday, 4 times at 12:00"
+        );
+        assert_eq!(
+            describe("FREQ=YEARLY;BYMONTH=12;BYMONTHDAY=25"),
+            "Every year on December 25 at 12:00"
+        );
+        assert_eq!(
+            describe("FREQ=YEARLY;BYMONTH=12;BYMONTHDAY=-2"),
+            "Every year on the 2nd last day of December at 12:00"
+        );
+        assert_eq!(
+            describe("FREQ=MONTHLY;BYDAY=MO,TU;BYSETPOS=-1"),
+            "Custom recurrence (FREQ=MONTHLY;BYDAY=MO,TU;BYSETPOS=-1) at 12:00"
+        );
+    }
+
+    #[test]
+    fn describes_next_run_relative_to_now() {
+        let at = |d: u32, h: u32| Local.with_ymd_and_hms(2025, 11, d, h, 30, 0).unwrap();
+        let now = at(12, 9);
+
+        let next = |when| describe_next_run(Some(when), now, Language::English);
+
+        assert_eq!(next(at(12, 18)), "Next: today 18:30");
+        assert_eq!(next(at(13, 7)), "Next: tomorrow 07:30");
+        assert_eq!(next(at(15, 7)), "Next: Saturday 07:30");
+        assert_eq!(next(at(24, 7)), "Next: Mon 24 Nov 2025 07:30");
+        assert_eq!(
+            describe_next_run(None, now, Language::English),
+            "No upcoming runs"
+        );
+    }
+
+    #[test]
+    fn describes_in_spanish() {
+        let describe = |repeat: RepeatType| repeat.describe_in("07:30", Language::Spanish);
+
+        assert_eq!(
+            describe(RepeatType::Weekdays),
+            "Cada día laborable a las 07:30"
+        );
+        assert_eq!(
+            describe(RepeatType::Weekly {
+                days: vec![Weekday::Fri, Weekday::Mon, Weekday::Wed],
+            }),
+            "Cada lunes, miércoles y viernes a las 07:30"
+        );
+        assert_eq!(
+            describe(RepeatType::Custom {
+                interval_minutes: 45,
+                until: None,
+            }),
+            "Cada 45 minutos a partir de las 07:30"
+        );
+        assert_eq!(
+            describe(RepeatType::Rrule {
+                rule: "FREQ=MONTHLY;BYDAY=1MO".into(),
+            }),
+            "El primer lunes de cada mes a las 07:30"
+        );
+        assert_eq!(
+            describe(RepeatType::Rrule {
+                rule: "FREQ=MONTHLY;INTERVAL=2;BYMONTHDAY=1,15;COUNT=4".into(),
+            }),
+            "El 1 y 15 de cada dos meses, 4 veces a las 07:30"
+        );
+        assert_eq!(
+            describe(RepeatType::Rrule {
+                rule: "FREQ=YEARLY;BYMONTH=12;BYMONTHDAY=25".into(),
+            }),
+            "Cada año, el 25 de diciembre a las 07:30"
+        );
+
+        let at = |d: u32| Local.with_ymd_and_hms(2025, 11, d, 7, 30, 0).unwrap();
+        let next = |when| describe_next_run(Some(when), at(12), Language::Spanish);
+        assert_eq!(next(at(13)), "Próxima: mañana 07:30");
+        assert_eq!(next(at(15)), "Próxima: sábado 07:30");
+        assert_eq!(next(at(24)), "Próxima: lun 24 nov 2025 07:30");
+    }
+
+    #[test]
+    fn picks_the_language_from_its_code() {
+        assert_eq!(Language::from_code("es"), Language::Spanish);
+        assert_eq!(Language::from_code("es-MX"), Language::Spanish);
+        assert_eq!(Language::from_code("en_GB"), Language::English);
+        assert_eq!(Language::from_code("xx"), Language::English);
+    }
+}
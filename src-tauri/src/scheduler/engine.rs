@@ -3,19 +3,35 @@ use std::sync::Arc;
 use std::time::Duration as StdDuration;
 
 use async_trait::async_trait;
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Duration, Local, Utc};
 use serde::Serialize;
 use tauri::Manager;
 use tauri_plugin_notification::NotificationExt;
 use tokio::sync::RwLock;
 use tokio_util::sync::CancellationToken;
 
-use crate::audio::AudioService;
-use crate::db::models::{PlaybackStatus, RepeatType, Schedule, UpdateScheduleInput};
+use crate::audio::{
+    AudioService, GeneratedSound, OutputStatus, PlaybackOptions, PlaybackState, PreRoll,
+};
+use crate::calendar::holidays::Holidays;
+use crate::db::models::{
+    AudioSource, CalendarSubscription, PlaybackStatus, PreAlert, RepeatType, Schedule,
+    SettingsSnapshot, UpdateScheduleInput,
+};
+use crate::db::execution_leases::ExecutionLeaseRepository;
 use crate::db::Database;
 
+use super::audio_source::{plan_playback, PlaybackItem};
 use super::error::SchedulerError;
-use super::time_calculator::next_execution_time;
+use super::subscriptions::{run_subscription_task, SubscriptionRuntimeInfo, SubscriptionState};
+use super::time_calculator::{next_occurrence_with_holidays, pre_alert_times, GracePolicy};
+
+/// How long claimed execution leases are kept before being pruned on start.
+const LEASE_RETENTION_DAYS: i64 = 7;
+/// Volume of the announcement played before each schedule.
+const ANNOUNCEMENT_VOLUME: u8 = 80;
+/// Silence between the announcement and the schedule's audio.
+const ANNOUNCEMENT_GAP: StdDuration = StdDuration::from_millis(300);
 
 #[derive(Debug, Clone, Serialize, Default)]
 #[serde(rename_all = "snake_case")]
@@ -54,6 +70,16 @@ pub struct SchedulerStatus {
     pub is_running: bool,
     pub total_schedules: usize,
     pub schedules: Vec<ScheduleRuntimeInfo>,
+    pub subscriptions: Vec<SubscriptionRuntimeInfo>,
+    /// `None` when the scheduler plays through a custom audio controller.
+    pub audio_output: Option<OutputStatus>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UpcomingKind {
+    Alarm,
+    PreAlert { minutes_before: u32 },
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -62,12 +88,65 @@ pub struct UpcomingExecution {
     pub name: String,
     pub scheduled_for: DateTime<Local>,
     pub repeat_type: RepeatType,
+    /// The schedule's repeat pattern in words, e.g. "Every weekday at 07:30".
+    pub description: String,
+    pub kind: UpcomingKind,
+}
+
+/// Where a file ended up playing.
+#[derive(Debug, Clone, Default)]
+pub struct PlayOutcome {
+    /// The voice playing the file, for [`AudioController::wait_until_finished`].
+    pub voice_id: Option<String>,
+    pub output_device: Option<String>,
+    /// How long the pre-roll plays before the file starts, if it was queued.
+    pub preroll: Option<StdDuration>,
+    /// Why the schedule's device was not used, if it was not.
+    pub fallback: Option<String>,
 }
 
 #[async_trait]
 pub trait AudioController: Send + Sync + 'static {
     async fn play(&self, path: &str, volume: u8) -> Result<(), SchedulerError>;
     async fn is_playing(&self) -> bool;
+
+    /// Plays with a schedule's playback options, after `preroll` if given;
+    /// controllers that have no use for them fall back to `play`.
+    async fn play_with_options(
+        &self,
+        path: &str,
+        volume: u8,
+        _options: &PlaybackOptions,
+        _preroll: Option<&PreRoll>,
+    ) -> Result<PlayOutcome, SchedulerError> {
+        self.play(path, volume).await?;
+        Ok(PlayOutcome::default())
+    }
+
+    /// Plays a generated sound with a schedule's playback options; controllers
+    /// that cannot synthesize sounds are handed its description as the path.
+    async fn play_generated(
+        &self,
+        sound: &GeneratedSound,
+        volume: u8,
+        options: &PlaybackOptions,
+        preroll: Option<&PreRoll>,
+    ) -> Result<PlayOutcome, SchedulerError> {
+        self.play_with_options(&sound.to_string(), volume, options, preroll)
+            .await
+    }
+
+    /// Waits for the voice `voice_id` to stop and returns how long it played,
+    /// or `None` if the controller does not track playback.
+    async fn wait_until_finished(&self, _voice_id: &str) -> Option<StdDuration> {
+        None
+    }
+
+    /// Length of the file at `path`, used to wait between playlist items
+    /// when the controller cannot report when playback finishes.
+    async fn duration(&self, _path: &str) -> Option<StdDuration> {
+        None
+    }
 }
 
 #[async_trait]
@@ -80,18 +159,84 @@ impl AudioController for AudioService {
     async fn is_playing(&self) -> bool {
         self.status().await.is_playing
     }
+
+    async fn play_with_options(
+        &self,
+        path: &str,
+        volume: u8,
+        options: &PlaybackOptions,
+        preroll: Option<&PreRoll>,
+    ) -> Result<PlayOutcome, SchedulerError> {
+        let state = self
+            .play_with_preroll(path.to_string(), volume, options.clone(), preroll.cloned())
+            .await?;
+        Ok(play_outcome(self, state, preroll).await)
+    }
+
+    async fn play_generated(
+        &self,
+        sound: &GeneratedSound,
+        volume: u8,
+        options: &PlaybackOptions,
+        preroll: Option<&PreRoll>,
+    ) -> Result<PlayOutcome, SchedulerError> {
+        let state = self
+            .play_generated(sound.clone(), volume, options.clone(), preroll.cloned())
+            .await?;
+        Ok(play_outcome(self, state, preroll).await)
+    }
+
+    async fn wait_until_finished(&self, voice_id: &str) -> Option<StdDuration> {
+        self.wait_for_completion(voice_id)
+            .await
+            .map(|completion| StdDuration::from_millis(completion.played_ms))
+    }
+
+    async fn duration(&self, path: &str) -> Option<StdDuration> {
+        self.metadata(path)
+            .ok()
+            .and_then(|metadata| metadata.duration_ms)
+            .map(StdDuration::from_millis)
+    }
+}
+
+/// What the scheduler needs to know about a voice the service just started.
+async fn play_outcome(
+    audio: &AudioService,
+    state: PlaybackState,
+    preroll: Option<&PreRoll>,
+) -> PlayOutcome {
+    let preroll_length = match preroll {
+        Some(preroll) => AudioController::duration(audio, &preroll.path.to_string_lossy())
+            .await
+            .map(|length| length + preroll.gap),
+        None => None,
+    };
+    state
+        .current
+        .map(|current| PlayOutcome {
+            voice_id: Some(current.id),
+            output_device: Some(current.output_device),
+            preroll: preroll_length,
+            fallback: current.fallback,
+        })
+        .unwrap_or_default()
 }
 
 struct ScheduleData {
     schedule: RwLock<Schedule>,
     state: RwLock<ScheduleExecutionState>,
+    holidays: Holidays,
+    grace: GracePolicy,
 }
 
 impl ScheduleData {
-    fn new(schedule: Schedule) -> Self {
+    fn new(schedule: Schedule, holidays: Holidays, grace: GracePolicy) -> Self {
         Self {
             schedule: RwLock::new(schedule),
             state: RwLock::new(ScheduleExecutionState::default()),
+            holidays,
+            grace,
         }
     }
 
@@ -123,17 +268,27 @@ struct ActiveSchedule {
     handle: tauri::async_runtime::JoinHandle<()>,
 }
 
+struct ActiveSubscription {
+    subscription: CalendarSubscription,
+    state: Arc<RwLock<SubscriptionState>>,
+    cancel_token: CancellationToken,
+    handle: tauri::async_runtime::JoinHandle<()>,
+}
+
 #[derive(Default)]
 struct EngineState {
     running: bool,
     schedules: HashMap<String, ActiveSchedule>,
+    subscriptions: HashMap<String, ActiveSubscription>,
 }
 
 struct SchedulerInner {
     database: Database,
     audio: Arc<dyn AudioController>,
-    audio_service: Option<AudioService>, // Store concrete type for announcement validation
+    audio_service: Option<AudioService>, // Store concrete type for output status
     app_handle: Option<tauri::AppHandle>,
+    /// Identifies this engine when claiming execution leases.
+    instance_id: String,
     state: RwLock<EngineState>,
 }
 
@@ -161,6 +316,7 @@ impl SchedulerEngine {
             audio,
             audio_service,
             app_handle,
+            instance_id: uuid::Uuid::new_v4().to_string(),
             state: RwLock::new(EngineState::default()),
         };
 
@@ -177,20 +333,72 @@ impl SchedulerEngine {
             .get_enabled()
             .await?;
 
+        let holiday_calendars = self
+            .inner
+            .database
+            .holiday_calendar_repository()
+            .get_all()
+            .await?
+            .into_iter()
+            .map(|calendar| {
+                let holidays = Holidays::new(&calendar.dates, &calendar.recurring);
+                (calendar.id, holidays)
+            })
+            .collect::<HashMap<_, _>>();
+
+        let settings: SettingsSnapshot = self
+            .inner
+            .database
+            .settings_repository()
+            .get_all()
+            .await?
+            .into();
+        let default_grace = GracePolicy::from_settings(&settings);
+
+        let lease_cutoff = Utc::now() - Duration::days(LEASE_RETENTION_DAYS);
+        if let Err(err) = self
+            .inner
+            .database
+            .execution_lease_repository()
+            .prune_before(lease_cutoff)
+            .await
+        {
+            eprintln!("Failed to prune execution leases: {}", err);
+        }
+
         let mut active = Vec::new();
 
         for schedule in schedules {
-            let data = Arc::new(ScheduleData::new(schedule.clone()));
+            // Normalized files are measured now, so the first firing after a
+            // restart does not play them unadjusted.
+            if let Some(service) = self.inner.audio_service.as_ref().filter(|_| schedule.playback.normalize) {
+                match &schedule.audio_source {
+                    AudioSource::File => service.measure_in_background(&schedule.audio_file_path),
+                    AudioSource::Playlist { items, .. } => {
+                        items.iter().for_each(|item| service.measure_in_background(item))
+                    }
+                    _ => {}
+                }
+            }
+
+            let holidays = schedule
+                .holiday_calendar_id
+                .as_ref()
+                .and_then(|id| holiday_calendars.get(id))
+                .cloned()
+                .unwrap_or_default();
+            let grace = GracePolicy::for_schedule(&schedule, default_grace);
+            let data = Arc::new(ScheduleData::new(schedule.clone(), holidays, grace));
             let cancel_token = CancellationToken::new();
             let task_data = Arc::clone(&data);
             let task_db = self.inner.database.clone();
             let task_audio = Arc::clone(&self.inner.audio);
             let task_app = self.inner.app_handle.clone();
             let task_cancel = cancel_token.clone();
+            let task_owner = self.inner.instance_id.clone();
 
-            let task_audio_service = self.inner.audio_service.clone();
             let handle = tauri::async_runtime::spawn(async move {
-                run_schedule_task(task_data, task_db, task_audio, task_audio_service, task_app, task_cancel).await;
+                run_schedule_task(task_data, task_db, task_audio, task_app, task_owner, task_cancel).await;
             });
 
             active.push((
@@ -203,6 +411,41 @@ impl SchedulerEngine {
             ));
         }
 
+        let subscriptions = self
+            .inner
+            .database
+            .calendar_subscription_repository()
+            .get_enabled()
+            .await?;
+
+        let mut active_subscriptions = Vec::new();
+
+        for subscription in subscriptions {
+            let state = Arc::new(RwLock::new(SubscriptionState::default()));
+            let cancel_token = CancellationToken::new();
+            let task_subscription = subscription.clone();
+            let task_state = Arc::clone(&state);
+            let task_db = self.inner.database.clone();
+            let task_audio = Arc::clone(&self.inner.audio);
+            let task_app = self.inner.app_handle.clone();
+            let task_cancel = cancel_token.clone();
+            let task_owner = self.inner.instance_id.clone();
+
+            let handle = tauri::async_runtime::spawn(async move {
+                run_subscription_task(task_subscription, task_state, task_db, task_audio, task_app, task_owner, task_cancel).await;
+            });
+
+            active_subscriptions.push((
+                subscription.id.clone(),
+                ActiveSubscription {
+                    subscription,
+                    state,
+                    cancel_token,
+                    handle,
+                },
+            ));
+        }
+
         let mut state = self.inner.state.write().await;
         if state.running {
             return Err(SchedulerError::AlreadyRunning);
@@ -210,19 +453,23 @@ impl SchedulerEngine {
 
         state.running = true;
         state.schedules = active.into_iter().collect();
+        state.subscriptions = active_subscriptions.into_iter().collect();
 
         Ok(())
     }
 
     pub async fn stop(&self) -> Result<(), SchedulerError> {
-        let schedules = {
+        let (schedules, subscriptions) = {
             let mut state = self.inner.state.write().await;
             if !state.running {
                 return Err(SchedulerError::NotRunning);
             }
 
             state.running = false;
-            std::mem::take(&mut state.schedules)
+            (
+                std::mem::take(&mut state.schedules),
+                std::mem::take(&mut state.subscriptions),
+            )
         };
 
         for (_, active) in schedules {
@@ -230,6 +477,11 @@ impl SchedulerEngine {
             let _ = active.handle.await;
         }
 
+        for (_, active) in subscriptions {
+            active.cancel_token.cancel();
+            let _ = active.handle.await;
+        }
+
         Ok(())
     }
 
@@ -245,20 +497,26 @@ impl SchedulerEngine {
         } else {
             let mut state = self.inner.state.write().await;
             state.schedules.clear();
+            state.subscriptions.clear();
         }
 
         Ok(())
     }
 
     pub async fn status(&self) -> SchedulerStatus {
-        let (is_running, handles) = {
+        let (is_running, handles, subscription_handles) = {
             let state = self.inner.state.read().await;
             let handles = state
                 .schedules
                 .values()
                 .map(|active| Arc::clone(&active.data))
                 .collect::<Vec<_>>();
-            (state.running, handles)
+            let subscription_handles = state
+                .subscriptions
+                .values()
+                .map(|active| (active.subscription.clone(), Arc::clone(&active.state)))
+                .collect::<Vec<_>>();
+            (state.running, handles, subscription_handles)
         };
 
         let mut schedules = Vec::with_capacity(handles.len());
@@ -286,10 +544,22 @@ impl SchedulerEngine {
             });
         }
 
+        let mut subscriptions = Vec::with_capacity(subscription_handles.len());
+        for (subscription, state) in subscription_handles {
+            subscriptions.push(state.read().await.runtime_info(&subscription));
+        }
+
+        let audio_output = match &self.inner.audio_service {
+            Some(service) => Some(service.output_status().await),
+            None => None,
+        };
+
         SchedulerStatus {
             is_running,
             total_schedules: schedules.len(),
             schedules,
+            subscriptions,
+            audio_output,
         }
     }
 
@@ -303,11 +573,32 @@ impl SchedulerEngine {
                 .collect::<Vec<_>>()
         };
 
+        let now = Local::now();
         let mut upcoming = Vec::new();
         for data in handles {
             let schedule = data.schedule().await;
             let state = data.state().await;
 
+            let Some(next_run) = state.next_run else {
+                continue;
+            };
+            let description = schedule.describe();
+
+            for (alert_at, alert) in pre_alert_times(&schedule, next_run) {
+                if alert_at > now {
+                    upcoming.push(UpcomingExecution {
+                        schedule_id: schedule.id.clone(),
+                        name: schedule.name.clone(),
+                        scheduled_for: alert_at,
+                        repeat_type: schedule.repeat_type.clone(),
+                        description: description.clone(),
+                        kind: UpcomingKind::PreAlert {
+                            minutes_before: alert.minutes_before,
+                        },
+                    });
+                }
+            }
+
             let Schedule {
                 id,
                 name,
@@ -315,14 +606,14 @@ impl SchedulerEngine {
                 ..
             } = schedule;
 
-            if let Some(next_run) = state.next_run {
-                upcoming.push(UpcomingExecution {
-                    schedule_id: id,
-                    name,
-                    scheduled_for: next_run,
-                    repeat_type,
-                });
-            }
+            upcoming.push(UpcomingExecution {
+                schedule_id: id,
+                name,
+                scheduled_for: next_run,
+                repeat_type,
+                description,
+                kind: UpcomingKind::Alarm,
+            });
         }
 
         upcoming.sort_by_key(|entry| entry.scheduled_for);
@@ -373,7 +664,7 @@ impl SchedulerEngine {
     }
 }
 
-async fn send_notification(
+pub(super) async fn send_notification(
     app_handle: &Option<tauri::AppHandle>,
     database: &Database,
     title: &str,
@@ -399,12 +690,249 @@ async fn send_notification(
     }
 }
 
+/// Sleeps until `target`, returning `false` if the task was cancelled first.
+async fn sleep_until(target: DateTime<Local>, cancel_token: &CancellationToken) -> bool {
+    let wait = (target - Local::now())
+        .to_std()
+        .unwrap_or(StdDuration::from_secs(0));
+
+    tokio::select! {
+        _ = tokio::time::sleep(wait) => !cancel_token.is_cancelled(),
+        _ = cancel_token.cancelled() => false,
+    }
+}
+
+async fn fire_pre_alert(
+    schedule: &Schedule,
+    alert: &PreAlert,
+    audio: &dyn AudioController,
+    database: &Database,
+    app_handle: &Option<tauri::AppHandle>,
+) {
+    if let Some(path) = &alert.audio_file_path {
+        let volume = alert.volume.unwrap_or(schedule.volume);
+        if let Err(err) = audio.play(path, volume).await {
+            eprintln!("Failed to play pre-alert for \"{}\": {}", schedule.name, err);
+        }
+    }
+
+    let unit = if alert.minutes_before == 1 { "minute" } else { "minutes" };
+    send_notification(
+        app_handle,
+        database,
+        "Coming Up",
+        &format!(
+            "\"{}\" plays in {} {}",
+            schedule.name, alert.minutes_before, unit
+        ),
+    )
+    .await;
+}
+
+/// Plays the schedule's audio source, recording each file in the history.
+/// Succeeds when at least one file played.
+async fn play_source(
+    schedule: &Schedule,
+    audio: &Arc<dyn AudioController>,
+    database: &Database,
+    data: &ScheduleData,
+    mut preroll: Option<PreRoll>,
+    cancel_token: &CancellationToken,
+) -> Result<(), SchedulerError> {
+    let playback_repo = database.playback_history_repository();
+    let plan = match plan_playback(schedule) {
+        Ok(plan) => plan,
+        Err(err) => {
+            let _ = playback_repo
+                .record(&schedule.id, PlaybackStatus::Failed, None, Some(err.to_string()))
+                .await;
+            return Err(err);
+        }
+    };
+
+    if let Some(position) = plan.next_position {
+        if let Err(err) = database
+            .schedule_repository()
+            .set_audio_source_position(&schedule.id, position)
+            .await
+        {
+            eprintln!("Failed to save playlist position for \"{}\": {}", schedule.name, err);
+        }
+        data.update_schedule(Schedule {
+            audio_source_position: position,
+            ..schedule.clone()
+        })
+        .await;
+    }
+
+    if plan.items.is_empty() {
+        let err = SchedulerError::EmptyPlaylist;
+        let _ = playback_repo
+            .record(&schedule.id, PlaybackStatus::Failed, None, Some(err.to_string()))
+            .await;
+        return Err(err);
+    }
+
+    let mut played = false;
+    let mut last_error = None;
+    for (index, item) in plan.items.iter().enumerate() {
+        if cancel_token.is_cancelled() {
+            break;
+        }
+
+        // Only the first item is announced.
+        let announcement = preroll.take();
+        let path = item.to_string();
+        let started = match item {
+            PlaybackItem::File(path) => {
+                audio
+                    .play_with_options(path, schedule.volume, &schedule.playback, announcement.as_ref())
+                    .await
+            }
+            PlaybackItem::Generated(sound) => {
+                audio
+                    .play_generated(sound, schedule.volume, &schedule.playback, announcement.as_ref())
+                    .await
+            }
+        };
+        match started {
+            Ok(outcome) => {
+                played = true;
+                if let Some(fallback) = &outcome.fallback {
+                    eprintln!("Schedule \"{}\": {}", schedule.name, fallback);
+                }
+                let entry = playback_repo
+                    .record_played(
+                        &schedule.id,
+                        &path,
+                        outcome.output_device.as_deref(),
+                        outcome.fallback.as_deref(),
+                    )
+                    .await;
+
+                // The history entry gets the played duration once the file
+                // stops, whether or not anything waits for it here.
+                let finished = outcome.voice_id.clone().map(|voice_id| {
+                    let audio = Arc::clone(audio);
+                    let playback_repo = playback_repo.clone();
+                    let entry_id = entry.as_ref().ok().map(|entry| entry.id.clone());
+                    tauri::async_runtime::spawn(async move {
+                        let played = audio.wait_until_finished(&voice_id).await;
+                        if let (Some(played), Some(entry_id)) = (played, entry_id) {
+                            if let Err(err) = playback_repo.set_played_duration(&entry_id, played).await {
+                                eprintln!("Failed to record played duration: {}", err);
+                            }
+                        }
+                    })
+                });
+
+                // Let the item finish before starting the next one.
+                if index + 1 < plan.items.len() {
+                    if let Some(finished) = finished {
+                        tokio::select! {
+                            _ = finished => {}
+                            _ = cancel_token.cancelled() => break,
+                        }
+                    } else if let Some(duration) = item_length(audio, item)
+                        .await
+                        .and_then(|duration| schedule.playback.played_length(duration))
+                        .map(|duration| duration + outcome.preroll.unwrap_or_default())
+                    {
+                        tokio::select! {
+                            _ = tokio::time::sleep(duration) => {}
+                            _ = cancel_token.cancelled() => break,
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                // Without an output nothing can play, so the firing is logged
+                // as skipped rather than blamed on the file.
+                let no_output =
+                    matches!(&err, SchedulerError::Audio(audio) if audio.is_output_unavailable());
+                let status = if no_output {
+                    PlaybackStatus::Skipped
+                } else {
+                    PlaybackStatus::Failed
+                };
+                let _ = playback_repo
+                    .record(&schedule.id, status, Some(&path), Some(err.to_string()))
+                    .await;
+                last_error = Some(err);
+                if no_output {
+                    break;
+                }
+            }
+        }
+    }
+
+    match last_error {
+        Some(err) if !played => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// How long one play of `item` lasts, if it can be told.
+async fn item_length(audio: &Arc<dyn AudioController>, item: &PlaybackItem) -> Option<StdDuration> {
+    match item {
+        PlaybackItem::File(path) => audio.duration(path).await,
+        PlaybackItem::Generated(sound) => Some(sound.length()),
+    }
+}
+
+/// The announcement played before each schedule, if enabled in settings.
+async fn announcement_preroll(
+    database: &Database,
+    app_handle: &Option<tauri::AppHandle>,
+) -> Option<PreRoll> {
+    let handle = app_handle.as_ref()?;
+    let settings: SettingsSnapshot = database.settings_repository().get_all().await.ok()?.into();
+    if !settings.announcement_enabled {
+        return None;
+    }
+
+    let announcement_filename = match settings.announcement_sound.as_str() {
+        "spell" => "light-spell-notifiation.wav",
+        _ => "light-spell-notifiation.wav", // Default to spell
+    };
+
+    // Try production resource path first
+    let path = match handle.path().resolve(announcement_filename, tauri::path::BaseDirectory::Resource) {
+        Ok(resource_path) if resource_path.exists() => Some(resource_path),
+        _ => {
+            // Fallback to dev mode: use path relative to current executable
+            std::env::current_exe().ok().and_then(|exe_path| {
+                // Go up from target/debug/resonatify to target/debug
+                // Then to target, then to src-tauri, then to resources
+                exe_path.parent().map(|dir| {
+                    dir.join("../../resources").join(announcement_filename)
+                })
+            }).and_then(|p| {
+                // Canonicalize to resolve .. in path
+                p.canonicalize().ok()
+            })
+        }
+    };
+
+    match path {
+        Some(path) => Some(PreRoll {
+            path,
+            volume_percent: ANNOUNCEMENT_VOLUME,
+            gap: ANNOUNCEMENT_GAP,
+        }),
+        None => {
+            eprintln!("Could not resolve announcement audio path for: {}", announcement_filename);
+            None
+        }
+    }
+}
+
 async fn run_schedule_task(
     data: Arc<ScheduleData>,
     database: Database,
     audio: Arc<dyn AudioController>,
-    audio_service: Option<AudioService>,
     app_handle: Option<tauri::AppHandle>,
+    owner: String,
     cancel_token: CancellationToken,
 ) {
     let playback_repo = database.playback_history_repository();
@@ -433,8 +961,10 @@ async fn run_schedule_task(
         }
 
         let now = Local::now();
-        match next_execution_time(&schedule, now, state_snapshot.last_run) {
-            Ok(Some(next_run)) => {
+        let holidays = data.holidays.around(now.date_naive());
+        match next_occurrence_with_holidays(&schedule, &holidays, data.grace, now, state_snapshot.last_run) {
+            Ok(Some(occurrence)) => {
+                let next_run = occurrence.fire_at;
                 data.update_state(|state| {
                     state.next_run = Some(next_run);
                     state.status = ScheduleStatus::Waiting;
@@ -442,23 +972,35 @@ async fn run_schedule_task(
                 })
                 .await;
 
-                let wait = match (next_run - now).to_std() {
-                    Ok(duration) => duration,
-                    Err(_) => StdDuration::from_secs(0),
-                };
-
-                tokio::select! {
-                    _ = tokio::time::sleep(wait) => {}
-                    _ = cancel_token.cancelled() => {
-                        data.update_state(|state| {
-                            state.status = ScheduleStatus::Stopped;
-                            state.next_run = None;
-                        }).await;
+                // Lead warnings share the wait for `next_run`; any whose time
+                // has already passed are skipped.
+                let mut cancelled = false;
+                for (alert_at, alert) in pre_alert_times(&schedule, next_run) {
+                    if alert_at <= Local::now() {
+                        continue;
+                    }
+                    if !sleep_until(alert_at, &cancel_token).await {
+                        cancelled = true;
                         break;
                     }
+                    // Claimed like the occurrence itself, so instances sharing
+                    // the database announce it once.
+                    let lease_key = ExecutionLeaseRepository::pre_alert_key(
+                        &schedule.id,
+                        &occurrence.scheduled_for,
+                        alert.minutes_before,
+                    );
+                    match database.execution_lease_repository().try_acquire(&lease_key, &owner).await {
+                        Ok(true) => {}
+                        Ok(false) => continue,
+                        Err(err) => {
+                            eprintln!("Failed to acquire pre-alert lease for \"{}\": {}", schedule.name, err);
+                        }
+                    }
+                    fire_pre_alert(&schedule, alert, &*audio, &database, &app_handle).await;
                 }
 
-                if cancel_token.is_cancelled() {
+                if cancelled || !sleep_until(next_run, &cancel_token).await {
                     data.update_state(|state| {
                         state.status = ScheduleStatus::Stopped;
                         state.next_run = None;
@@ -467,118 +1009,56 @@ async fn run_schedule_task(
                     break;
                 }
 
+                // Another instance sharing this database may already have
+                // claimed the occurrence; if so, treat it as done here.
+                let lease_key = ExecutionLeaseRepository::schedule_key(&schedule.id, &occurrence.scheduled_for);
+                match database.execution_lease_repository().try_acquire(&lease_key, &owner).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        if matches!(schedule.repeat_type, RepeatType::Once) {
+                            data.update_state(|state| {
+                                state.status = ScheduleStatus::Disabled;
+                                state.next_run = None;
+                            })
+                            .await;
+                            break;
+                        }
+                        data.update_state(|state| {
+                            state.last_run = Some(Local::now());
+                            state.status = ScheduleStatus::Idle;
+                            state.next_run = None;
+                        })
+                        .await;
+                        continue;
+                    }
+                    Err(err) => {
+                        eprintln!("Failed to acquire execution lease for \"{}\": {}", schedule.name, err);
+                    }
+                }
+
                 data.update_state(|state| {
                     state.status = ScheduleStatus::Running;
                     state.next_run = None;
                 })
                 .await;
 
-                // Check if announcement is enabled and play announcement first
-                let settings_repo = database.settings_repository();
-                let settings = settings_repo.get_all().await;
-                if let Ok(settings_list) = settings {
-                    let settings_snapshot: crate::db::models::SettingsSnapshot = settings_list.into();
-                    if settings_snapshot.announcement_enabled && app_handle.is_some() {
-                        // Get the announcement sound filename
-                        let announcement_filename = match settings_snapshot.announcement_sound.as_str() {
-                            "spell" => "light-spell-notifiation.wav",
-                            _ => "light-spell-notifiation.wav", // Default to spell
-                        };
-
-                        // Resolve the announcement audio path
-                        let announcement_path = if let Some(ref handle) = app_handle {
-                            // Try production resource path first
-                            match handle.path().resolve(announcement_filename, tauri::path::BaseDirectory::Resource) {
-                                Ok(resource_path) if resource_path.exists() => {
-                                    Some(resource_path)
-                                }
-                                _ => {
-                                    // Fallback to dev mode: use path relative to current executable
-                                    std::env::current_exe().ok().and_then(|exe_path| {
-                                        // Go up from target/debug/resonatify to target/debug
-                                        // Then to target, then to src-tauri, then to resources
-                                        exe_path.parent().map(|dir| {
-                                            dir.join("../../resources").join(announcement_filename)
-                                        })
-                                    }).and_then(|p| {
-                                        // Canonicalize to resolve .. in path
-                                        p.canonicalize().ok()
-                                    })
-                                }
-                            }
-                        } else {
-                            None
-                        };
-
-                        if let Some(path) = announcement_path {
-                            if let Some(path_str) = path.to_str() {
-                                // Get the announcement duration by validating the audio file
-                                let wait_duration = if let Some(ref service) = audio_service {
-                                    // Validate to get metadata including duration
-                                    match service.validate(path_str).await {
-                                        Ok(metadata) => {
-                                            if let Some(duration_ms) = metadata.duration_ms {
-                                                // Use actual duration + 500ms buffer for smooth transition and startup latency
-                                                Some(StdDuration::from_millis(duration_ms + 500))
-                                            } else {
-                                                // Duration unknown, use 3 second default
-                                                eprintln!("Announcement duration unknown, using 3s default");
-                                                Some(StdDuration::from_millis(3000))
-                                            }
-                                        }
-                                        Err(e) => {
-                                            eprintln!("Failed to validate announcement audio: {}, using 3s default", e);
-                                            Some(StdDuration::from_millis(3000))
-                                        }
-                                    }
-                                } else {
-                                    // Fallback if we don't have audio service
-                                    eprintln!("No audio service available for validation, using 3s default");
-                                    Some(StdDuration::from_millis(3000))
-                                };
-
-                                // Play announcement at default volume with short fade-in
-                                let play_result = if let Some(ref service) = audio_service {
-                                    service.play_with_fade(path_str, 80, StdDuration::from_millis(50))
-                                        .await
-                                        .map(|_| ())
-                                        .map_err(SchedulerError::Audio)
-                                } else {
-                                    audio.play(path_str, 80).await
-                                };
-
-                                match play_result {
-                                    Ok(_) => {
-                                        if let Some(duration) = wait_duration {
-                                            // Wait for the announcement to finish based on its actual duration
-                                            eprintln!("Playing announcement, waiting {}ms", duration.as_millis());
-                                            tokio::time::sleep(duration).await;
-                                            eprintln!("Announcement finished, playing scheduled audio");
-                                        }
-                                    }
-                                    Err(e) => {
-                                        // Log error but continue with scheduled audio
-                                        eprintln!("Failed to play announcement: {}", e);
-                                    }
-                                }
-
-                            }
-                        } else {
-                            eprintln!("Could not resolve announcement audio path for: {}", announcement_filename);
-                        }
-                    }
-                }
-
-                let play_result = audio.play(&schedule.audio_file_path, schedule.volume).await;
+                // The announcement is queued ahead of the schedule's audio so
+                // the two play back to back.
+                let preroll = announcement_preroll(&database, &app_handle).await;
+                let play_result = play_source(
+                    &schedule,
+                    &audio,
+                    &database,
+                    &data,
+                    preroll,
+                    &cancel_token,
+                )
+                .await;
 
                 match play_result {
                     Ok(_) => {
                         let executed_at = Local::now();
 
-                        let _ = playback_repo
-                            .record(&schedule.id, PlaybackStatus::Success, None)
-                            .await;
-
                         // Send success notification
                         send_notification(
                             &app_handle,
@@ -595,9 +1075,6 @@ async fn run_schedule_task(
                     }
                     Err(err) => {
                         let message = err.to_string();
-                        let _ = playback_repo
-                            .record(&schedule.id, PlaybackStatus::Failed, Some(message.clone()))
-                            .await;
 
                         // Send failure notification
                         send_notification(
@@ -629,6 +1106,9 @@ async fn run_schedule_task(
                         {
                             Ok(updated) => {
                                 data.update_schedule(updated).await;
+                                if let Err(err) = crate::calendar::refresh_feed(&database).await {
+                                    eprintln!("Failed to refresh calendar feed: {}", err);
+                                }
                                 data.update_state(|state| {
                                     state.status = ScheduleStatus::Disabled;
                                     state.next_run = None;
@@ -660,7 +1140,7 @@ async fn run_schedule_task(
             Err(err) => {
                 let message = err.to_string();
                 let _ = playback_repo
-                    .record(&schedule.id, PlaybackStatus::Skipped, Some(message.clone()))
+                    .record(&schedule.id, PlaybackStatus::Skipped, None, Some(message.clone()))
                     .await;
 
                 data.update_state(|state| {
@@ -678,7 +1158,9 @@ async fn run_schedule_task(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::db::models::{CreateScheduleInput, RepeatType};
+    use crate::db::models::{
+        AudioSource, CreateCalendarSubscriptionInput, CreateScheduleInput, PlaylistMode, RepeatType,
+    };
     use crate::db::{Database, DatabaseError};
     use chrono::Local;
     use std::sync::atomic::{AtomicUsize, Ordering};
@@ -755,6 +1237,14 @@ mod tests {
                 repeat_type: RepeatType::Once,
                 volume: 70,
                 last_run_at: None,
+                pre_alerts: Vec::new(),
+                holiday_calendar_id: None,
+                holiday_rule: Default::default(),
+                late_tolerance_seconds: None,
+                min_spacing_seconds: None,
+                dst_policy: Default::default(),
+                audio_source: Default::default(),
+                playback: Default::default(),
             })
             .await
             .unwrap();
@@ -783,4 +1273,271 @@ mod tests {
         assert!(!history.is_empty());
         assert_eq!(history[0].schedule_id, schedule.id);
     }
+
+    #[tokio::test]
+    async fn engines_sharing_a_database_play_an_occurrence_once() {
+        let database = setup_database().await.unwrap();
+        database
+            .schedule_repository()
+            .create(CreateScheduleInput {
+                name: "Shared".into(),
+                audio_file_path: "/tmp/test.mp3".into(),
+                scheduled_time: current_time_string(),
+                enabled: true,
+                repeat_type: RepeatType::Once,
+                volume: 70,
+                last_run_at: None,
+                pre_alerts: Vec::new(),
+                holiday_calendar_id: None,
+                holiday_rule: Default::default(),
+                late_tolerance_seconds: None,
+                min_spacing_seconds: None,
+                dst_policy: Default::default(),
+                audio_source: Default::default(),
+                playback: Default::default(),
+            })
+            .await
+            .unwrap();
+
+        let first_audio = Arc::new(MockAudioController::new());
+        let second_audio = Arc::new(MockAudioController::new());
+        let first = SchedulerEngine::with_audio_controller(database.clone(), first_audio.clone(), None, None);
+        let second = SchedulerEngine::with_audio_controller(database.clone(), second_audio.clone(), None, None);
+
+        first.start().await.unwrap();
+        second.start().await.unwrap();
+
+        tokio::time::sleep(StdDuration::from_millis(250)).await;
+
+        first.stop().await.unwrap();
+        second.stop().await.unwrap();
+
+        assert_eq!(first_audio.play_count() + second_audio.play_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn rotating_playlist_plays_one_item_and_advances() {
+        let database = setup_database().await.unwrap();
+        let schedule_repo = database.schedule_repository();
+        let schedule = schedule_repo
+            .create(CreateScheduleInput {
+                name: "Rotation".into(),
+                audio_file_path: String::new(),
+                scheduled_time: current_time_string(),
+                enabled: true,
+                repeat_type: RepeatType::Once,
+                volume: 70,
+                last_run_at: None,
+                pre_alerts: Vec::new(),
+                holiday_calendar_id: None,
+                holiday_rule: Default::default(),
+                late_tolerance_seconds: None,
+                min_spacing_seconds: None,
+                dst_policy: Default::default(),
+                audio_source: AudioSource::Playlist {
+                    items: vec!["/tmp/a.mp3".into(), "/tmp/b.mp3".into()],
+                    mode: PlaylistMode::Rotate,
+                },
+                playback: Default::default(),
+            })
+            .await
+            .unwrap();
+
+        let audio = Arc::new(MockAudioController::new());
+        let scheduler = SchedulerEngine::with_audio_controller(database.clone(), audio.clone(), None, None);
+        scheduler.start().await.unwrap();
+        tokio::time::sleep(StdDuration::from_millis(250)).await;
+        scheduler.stop().await.unwrap();
+
+        assert_eq!(audio.play_count(), 1);
+        let updated = schedule_repo.get_by_id(&schedule.id).await.unwrap();
+        assert_eq!(updated.audio_source_position, 1);
+
+        let history = database
+            .playback_history_repository()
+            .list_recent(1)
+            .await
+            .unwrap();
+        assert_eq!(history[0].audio_file_path.as_deref(), Some("/tmp/a.mp3"));
+    }
+
+    #[tokio::test]
+    async fn generated_source_plays_without_a_file() {
+        let database = setup_database().await.unwrap();
+        database
+            .schedule_repository()
+            .create(CreateScheduleInput {
+                name: "Stretch".into(),
+                audio_file_path: String::new(),
+                scheduled_time: current_time_string(),
+                enabled: true,
+                repeat_type: RepeatType::Once,
+                volume: 60,
+                last_run_at: None,
+                pre_alerts: Vec::new(),
+                holiday_calendar_id: None,
+                holiday_rule: Default::default(),
+                late_tolerance_seconds: None,
+                min_spacing_seconds: None,
+                dst_policy: Default::default(),
+                audio_source: AudioSource::Generated {
+                    sound: GeneratedSound::Bell {
+                        frequency_hz: 440.0,
+                        count: 3,
+                        duration_ms: 1500,
+                        spacing_ms: 800,
+                    },
+                },
+                playback: Default::default(),
+            })
+            .await
+            .unwrap();
+
+        let audio = Arc::new(MockAudioController::new());
+        let scheduler = SchedulerEngine::with_audio_controller(database.clone(), audio.clone(), None, None);
+        scheduler.start().await.unwrap();
+        tokio::time::sleep(StdDuration::from_millis(250)).await;
+        scheduler.stop().await.unwrap();
+
+        assert_eq!(audio.play_count(), 1);
+        let history = database
+            .playback_history_repository()
+            .list_recent(1)
+            .await
+            .unwrap();
+        assert_eq!(
+            history[0].audio_file_path.as_deref(),
+            Some("3 bell strikes at 440 Hz")
+        );
+    }
+
+    fn calendar_with_event_in(seconds: i64) -> String {
+        let start = (Local::now() + chrono::Duration::seconds(seconds)).format("%Y%m%dT%H%M%S");
+        format!(
+            "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:meeting\r\nSUMMARY:Meeting\r\nDTSTART:{start}\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n"
+        )
+    }
+
+    async fn subscribe(database: &Database, source: &std::path::Path) {
+        database
+            .calendar_subscription_repository()
+            .create(CreateCalendarSubscriptionInput {
+                name: "Work".into(),
+                source_path: source.display().to_string(),
+                audio_file_path: "/tmp/chime.mp3".into(),
+                lead_minutes: 0,
+                volume: 60,
+                enabled: true,
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn scheduler_plays_calendar_subscription_reminders() {
+        let database = setup_database().await.unwrap();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let source = temp_dir.path().join("work.ics");
+        std::fs::write(&source, calendar_with_event_in(1)).unwrap();
+        subscribe(&database, &source).await;
+
+        let audio = Arc::new(MockAudioController::new());
+        let controller: Arc<dyn AudioController> = audio.clone();
+        let scheduler = SchedulerEngine::with_audio_controller(database.clone(), controller, None, None);
+
+        scheduler.start().await.unwrap();
+        let status = scheduler.status().await;
+        assert_eq!(status.subscriptions.len(), 1);
+
+        tokio::time::sleep(StdDuration::from_millis(2000)).await;
+        scheduler.stop().await.unwrap();
+
+        assert_eq!(audio.play_count(), 1);
+        assert_eq!(*audio.last_volume.lock().await, Some(60));
+    }
+
+    #[tokio::test]
+    async fn scheduler_cancels_reminders_removed_from_calendar() {
+        let database = setup_database().await.unwrap();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let source = temp_dir.path().join("work.ics");
+        std::fs::write(&source, calendar_with_event_in(2)).unwrap();
+        subscribe(&database, &source).await;
+
+        let audio = Arc::new(MockAudioController::new());
+        let controller: Arc<dyn AudioController> = audio.clone();
+        let scheduler = SchedulerEngine::with_audio_controller(database.clone(), controller, None, None);
+
+        scheduler.start().await.unwrap();
+        tokio::time::sleep(StdDuration::from_millis(200)).await;
+        std::fs::write(&source, "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n").unwrap();
+
+        tokio::time::sleep(StdDuration::from_millis(1800)).await;
+        scheduler.stop().await.unwrap();
+
+        assert_eq!(audio.play_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn upcoming_executions_list_pre_alerts_separately() {
+        let database = setup_database().await.unwrap();
+        let scheduled_time = (Local::now() + chrono::Duration::hours(2))
+            .format("%H:%M")
+            .to_string();
+
+        database
+            .schedule_repository()
+            .create(CreateScheduleInput {
+                name: "Wake up".into(),
+                audio_file_path: "/tmp/alarm.mp3".into(),
+                scheduled_time,
+                enabled: true,
+                repeat_type: RepeatType::Daily,
+                volume: 70,
+                last_run_at: None,
+                pre_alerts: vec![
+                    PreAlert {
+                        minutes_before: 1,
+                        audio_file_path: Some("/tmp/chime.mp3".into()),
+                        volume: Some(30),
+                    },
+                    PreAlert {
+                        minutes_before: 10,
+                        audio_file_path: None,
+                        volume: None,
+                    },
+                ],
+                holiday_calendar_id: None,
+                holiday_rule: Default::default(),
+                late_tolerance_seconds: None,
+                min_spacing_seconds: None,
+                dst_policy: Default::default(),
+                audio_source: Default::default(),
+                playback: Default::default(),
+            })
+            .await
+            .unwrap();
+
+        let controller: Arc<dyn AudioController> = Arc::new(MockAudioController::new());
+        let scheduler = SchedulerEngine::with_audio_controller(database, controller, None, None);
+
+        scheduler.start().await.unwrap();
+        tokio::time::sleep(StdDuration::from_millis(100)).await;
+        let upcoming = scheduler.upcoming_executions(10).await;
+        scheduler.stop().await.unwrap();
+
+        let kinds = upcoming.iter().map(|entry| entry.kind.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                UpcomingKind::PreAlert { minutes_before: 10 },
+                UpcomingKind::PreAlert { minutes_before: 1 },
+                UpcomingKind::Alarm,
+            ]
+        );
+        assert_eq!(
+            upcoming[2].scheduled_for - upcoming[0].scheduled_for,
+            chrono::Duration::minutes(10)
+        );
+    }
 }
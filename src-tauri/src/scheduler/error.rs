@@ -13,6 +13,14 @@ pub enum SchedulerError {
     Database(#[from] DatabaseError),
     #[error("audio error: {0}")]
     Audio(#[from] AudioError),
+    #[error("playlist has no items")]
+    EmptyPlaylist,
+    #[error("audio folder not found: {0}")]
+    FolderNotFound(String),
+    #[error("audio folder has no supported audio files: {0}")]
+    EmptyFolder(String),
+    #[error("could not read audio folder {path}: {reason}")]
+    FolderUnreadable { path: String, reason: String },
     #[error("invalid schedule time for {schedule_id}: {reason}")]
     InvalidScheduleTime { schedule_id: String, reason: String },
     #[error("failed to join schedule task: {0}")]
@@ -1,6 +1,12 @@
+pub mod audio_source;
+pub mod describe;
 pub mod engine;
 pub mod error;
+pub mod phrase;
+pub mod recurrence;
+pub mod subscriptions;
 pub mod time_calculator;
 
-pub use engine::{SchedulerEngine, SchedulerStatus, UpcomingExecution};
+pub use engine::{SchedulerEngine, SchedulerStatus, UpcomingExecution, UpcomingKind};
 pub use error::SchedulerError;
+pub use subscriptions::SubscriptionRuntimeInfo;
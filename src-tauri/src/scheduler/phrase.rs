@@ -0,0 +1,546 @@
+//! Plain-English schedule phrases such as "every weekday at 7:30",
+//! "every 2 hours from 9am to 5pm" or "on the first Monday of each month at
+//! noon", turned into the repeat type and time the create dialog would have
+//! produced.
+
+use std::str::FromStr;
+
+use chrono::{NaiveTime, Weekday};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::db::models::{CreateScheduleInput, RepeatType};
+
+use super::recurrence::{Frequency, RecurrenceRule, WeekdayNum};
+
+const TIME_FORMAT: &str = "%H:%M";
+const DEFAULT_VOLUME: u8 = 80;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PhraseError {
+    #[error("the phrase is empty")]
+    Empty,
+    #[error("could not understand '{0}'")]
+    UnknownWord(String),
+    #[error("expected a time after '{0}'")]
+    ExpectedTime(String),
+    #[error("no time of day given (try adding \"at 7:30\")")]
+    MissingTime,
+    #[error("{0}")]
+    Conflict(String),
+}
+
+/// The outcome of parsing a phrase, with a normalized restatement so the user
+/// can confirm what was understood.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParsedPhrase {
+    pub repeat_type: RepeatType,
+    pub scheduled_time: String,
+    pub description: String,
+}
+
+impl ParsedPhrase {
+    fn new(repeat_type: RepeatType, time: NaiveTime) -> Self {
+        let scheduled_time = format_time(time);
+        Self {
+            description: repeat_type.describe(&scheduled_time),
+            repeat_type,
+            scheduled_time,
+        }
+    }
+
+    pub fn into_input(self, name: String, audio_file_path: String) -> CreateScheduleInput {
+        CreateScheduleInput {
+            name,
+            audio_file_path,
+            scheduled_time: self.scheduled_time,
+            enabled: true,
+            repeat_type: self.repeat_type,
+            volume: DEFAULT_VOLUME,
+            last_run_at: None,
+            pre_alerts: Vec::new(),
+            holiday_calendar_id: None,
+            holiday_rule: Default::default(),
+            late_tolerance_seconds: None,
+            min_spacing_seconds: None,
+            dst_policy: Default::default(),
+            audio_source: Default::default(),
+            playback: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Other,
+    Once,
+    Number(u32),
+    /// `first` .. `fifth`, `1st` .. `31st`; `last` is -1.
+    Ordinal(i32),
+    Day(Weekday),
+    Weekdays,
+    Weekends,
+    Unit(Unit),
+}
+
+/// Times picked out of the phrase by the keyword that introduced them.
+#[derive(Debug, Default)]
+struct Times {
+    at: Option<NaiveTime>,
+    from: Option<NaiveTime>,
+    until: Option<NaiveTime>,
+}
+
+pub fn parse_phrase(phrase: &str) -> Result<ParsedPhrase, PhraseError> {
+    let words = normalize(phrase);
+    if words.is_empty() {
+        return Err(PhraseError::Empty);
+    }
+
+    let (tokens, times) = tokenize(&words)?;
+    interpret(&tokens, &times)
+}
+
+fn normalize(phrase: &str) -> Vec<String> {
+    phrase
+        .to_lowercase()
+        .replace("a.m.", "am")
+        .replace("p.m.", "pm")
+        .replace("o'clock", "")
+        .replace([',', ';', '!', '?'], " ")
+        .split_whitespace()
+        .map(|word| word.trim_end_matches('.').to_string())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+fn tokenize(words: &[String]) -> Result<(Vec<Token>, Times), PhraseError> {
+    let mut tokens = Vec::new();
+    let mut times = Times::default();
+    let mut index = 0;
+
+    while index < words.len() {
+        let word = words[index].as_str();
+        index += 1;
+
+        match word {
+            "at" | "from" | "to" | "until" | "till" | "between" => {
+                let time = take_time(words, &mut index)
+                    .ok_or_else(|| PhraseError::ExpectedTime(word.to_string()))?;
+                match word {
+                    "at" => times.at = Some(time),
+                    "from" => times.from = Some(time),
+                    "between" => {
+                        times.from = Some(time);
+                        if words.get(index).map(String::as_str) != Some("and") {
+                            return Err(PhraseError::ExpectedTime("and".into()));
+                        }
+                        index += 1;
+                        times.until = Some(
+                            take_time(words, &mut index)
+                                .ok_or_else(|| PhraseError::ExpectedTime("and".into()))?,
+                        );
+                    }
+                    _ => times.until = Some(time),
+                }
+            }
+            _ if looks_like_time(words, index - 1) => {
+                index -= 1;
+                times.at = take_time(words, &mut index);
+            }
+            _ => {
+                if let Some(token) = classify(word) {
+                    tokens.push(token);
+                } else if !is_filler(word) {
+                    return Err(PhraseError::UnknownWord(word.to_string()));
+                }
+            }
+        }
+    }
+
+    Ok((tokens, times))
+}
+
+/// Whether the word at `index` is unambiguously a time of day even without
+/// a leading "at" ("7:30", "7pm", "7 pm", "noon").
+fn looks_like_time(words: &[String], index: usize) -> bool {
+    let word = words[index].as_str();
+    if matches!(word, "noon" | "midday" | "midnight") {
+        return true;
+    }
+
+    let followed_by_meridiem =
+        matches!(words.get(index + 1).map(String::as_str), Some("am" | "pm"));
+    let bare = word.trim_end_matches("am").trim_end_matches("pm");
+    let numeric = !bare.is_empty()
+        && bare
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == ':' || c == '.');
+
+    numeric && (bare.contains(':') || bare.len() < word.len() || followed_by_meridiem)
+}
+
+/// Reads a time starting at `words[*index]`, advancing past it (and a
+/// separate "am"/"pm" word) on success.
+fn take_time(words: &[String], index: &mut usize) -> Option<NaiveTime> {
+    let word = words.get(*index)?.as_str();
+    let fixed = match word {
+        "noon" | "midday" => NaiveTime::from_hms_opt(12, 0, 0),
+        "midnight" => NaiveTime::from_hms_opt(0, 0, 0),
+        _ => None,
+    };
+    if fixed.is_some() {
+        *index += 1;
+        return fixed;
+    }
+
+    let (clock, meridiem, consumed) = if let Some(clock) = word.strip_suffix("am") {
+        (clock, Some(false), 1)
+    } else if let Some(clock) = word.strip_suffix("pm") {
+        (clock, Some(true), 1)
+    } else {
+        match words.get(*index + 1).map(String::as_str) {
+            Some("am") => (word, Some(false), 2),
+            Some("pm") => (word, Some(true), 2),
+            _ => (word, None, 1),
+        }
+    };
+
+    let (hour, minute) = match clock.split_once([':', '.']) {
+        Some((hour, minute)) if minute.len() == 2 => {
+            (hour.parse::<u32>().ok()?, minute.parse::<u32>().ok()?)
+        }
+        Some(_) => return None,
+        None => (clock.parse::<u32>().ok()?, 0),
+    };
+
+    let hour = match meridiem {
+        Some(pm) if (1..=12).contains(&hour) => hour % 12 + if pm { 12 } else { 0 },
+        Some(_) => return None,
+        None => hour,
+    };
+
+    let time = NaiveTime::from_hms_opt(hour, minute, 0)?;
+    *index += consumed;
+    Some(time)
+}
+
+fn classify(word: &str) -> Option<Token> {
+    let token = match word {
+        "other" | "alternate" => Token::Other,
+        "once" => Token::Once,
+        "daily" | "everyday" | "day" | "days" => Token::Unit(Unit::Day),
+        "hourly" | "hour" | "hours" | "hr" | "hrs" => Token::Unit(Unit::Hour),
+        "minute" | "minutes" | "min" | "mins" => Token::Unit(Unit::Minute),
+        "weekly" | "week" | "weeks" => Token::Unit(Unit::Week),
+        "monthly" | "month" | "months" => Token::Unit(Unit::Month),
+        "weekday" | "weekdays" | "workday" | "workdays" => Token::Weekdays,
+        "weekend" | "weekends" => Token::Weekends,
+        "first" => Token::Ordinal(1),
+        "second" => Token::Ordinal(2),
+        "third" => Token::Ordinal(3),
+        "fourth" => Token::Ordinal(4),
+        "fifth" => Token::Ordinal(5),
+        "last" => Token::Ordinal(-1),
+        "two" => Token::Number(2),
+        "three" => Token::Number(3),
+        "four" => Token::Number(4),
+        "five" => Token::Number(5),
+        "six" => Token::Number(6),
+        "ten" => Token::Number(10),
+        "fifteen" => Token::Number(15),
+        "twenty" => Token::Number(20),
+        "thirty" => Token::Number(30),
+        "tues" => Token::Day(Weekday::Tue),
+        "weds" => Token::Day(Weekday::Wed),
+        "thur" | "thurs" => Token::Day(Weekday::Thu),
+        _ => {
+            if let Ok(number) = word.parse::<u32>() {
+                return Some(Token::Number(number));
+            }
+            if let Some(ordinal) = parse_numeric_ordinal(word) {
+                return Some(Token::Ordinal(ordinal));
+            }
+            return Weekday::from_str(word)
+                .or_else(|_| Weekday::from_str(word.trim_end_matches('s')))
+                .ok()
+                .map(Token::Day);
+        }
+    };
+
+    Some(token)
+}
+
+fn parse_numeric_ordinal(word: &str) -> Option<i32> {
+    let digits = ["st", "nd", "rd", "th"]
+        .iter()
+        .find_map(|suffix| word.strip_suffix(suffix))?;
+    digits
+        .parse::<i32>()
+        .ok()
+        .filter(|day| (1..=31).contains(day))
+}
+
+fn is_filler(word: &str) -> bool {
+    matches!(
+        word,
+        "every"
+            | "each"
+            | "on"
+            | "the"
+            | "of"
+            | "a"
+            | "an"
+            | "and"
+            | "in"
+            | "starting"
+            | "beginning"
+    )
+}
+
+fn interpret(tokens: &[Token], times: &Times) -> Result<ParsedPhrase, PhraseError> {
+    let has = |token: Token| tokens.contains(&token);
+    let days = weekdays(tokens);
+    let ordinals = tokens
+        .iter()
+        .filter_map(|token| match token {
+            Token::Ordinal(ordinal) => Some(*ordinal),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let interval = interval(tokens)?;
+
+    if has(Token::Unit(Unit::Minute)) || has(Token::Unit(Unit::Hour)) {
+        if !days.is_empty()
+            || has(Token::Weekdays)
+            || has(Token::Weekends)
+            || has(Token::Unit(Unit::Month))
+        {
+            return Err(PhraseError::Conflict(
+                "interval schedules run every day; drop the day names".into(),
+            ));
+        }
+
+        let minutes = if has(Token::Unit(Unit::Hour)) {
+            interval * 60
+        } else {
+            interval
+        };
+        let start = times.from.or(times.at).unwrap_or(NaiveTime::MIN);
+        if times.until.is_some_and(|until| until < start) {
+            return Err(PhraseError::Conflict(
+                "the window ends before it starts".into(),
+            ));
+        }
+
+        return Ok(ParsedPhrase::new(
+            RepeatType::Custom {
+                interval_minutes: minutes,
+                until: times.until.map(format_time),
+            },
+            start,
+        ));
+    }
+
+    let time = times.at.or(times.from).ok_or(PhraseError::MissingTime)?;
+    // The rule is read back the way a saved schedule will be, so a phrase
+    // never yields a rule the scheduler rejects.
+    let rrule = |frequency: Frequency, by_day: Vec<WeekdayNum>, by_month_day: Vec<i32>| {
+        let mut rule = RecurrenceRule::new(frequency);
+        rule.interval = interval;
+        rule.by_day = by_day;
+        rule.by_month_day = by_month_day;
+        let rule = rule.to_string();
+        RecurrenceRule::from_str(&rule)
+            .map(|_| RepeatType::Rrule { rule })
+            .map_err(|err| PhraseError::Conflict(err.to_string()))
+    };
+    let by_day = |ordinal: Option<i32>| {
+        days.iter()
+            .map(|weekday| WeekdayNum {
+                ordinal,
+                weekday: *weekday,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let repeat_type = if has(Token::Unit(Unit::Month)) || !ordinals.is_empty() {
+        let ordinal = *ordinals.first().ok_or_else(|| {
+            PhraseError::Conflict("say which day of the month, e.g. \"on the 1st\"".into())
+        })?;
+        if days.is_empty() {
+            rrule(Frequency::Monthly, Vec::new(), vec![ordinal])?
+        } else if (1..=5).contains(&ordinal) || ordinal == -1 {
+            rrule(Frequency::Monthly, by_day(Some(ordinal)), Vec::new())?
+        } else {
+            return Err(PhraseError::Conflict(
+                "a weekday can only be the first to fifth or last of the month".into(),
+            ));
+        }
+    } else if !days.is_empty() {
+        if interval == 1 {
+            RepeatType::Weekly { days: days.clone() }
+        } else {
+            rrule(Frequency::Weekly, by_day(None), Vec::new())?
+        }
+    } else if has(Token::Weekdays) && has(Token::Weekends) {
+        RepeatType::Daily
+    } else if has(Token::Weekdays) {
+        RepeatType::Weekdays
+    } else if has(Token::Weekends) {
+        RepeatType::Weekends
+    } else if has(Token::Unit(Unit::Week)) {
+        rrule(Frequency::Weekly, Vec::new(), Vec::new())?
+    } else if has(Token::Unit(Unit::Day)) && interval > 1 {
+        rrule(Frequency::Daily, Vec::new(), Vec::new())?
+    } else if has(Token::Unit(Unit::Day)) {
+        RepeatType::Daily
+    } else {
+        RepeatType::Once
+    };
+
+    Ok(ParsedPhrase::new(repeat_type, time))
+}
+
+/// "every 3 hours" or "every other week": the count directly before a unit.
+fn interval(tokens: &[Token]) -> Result<u32, PhraseError> {
+    let mut interval = None;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Other => interval = Some(2),
+            Token::Number(number) => {
+                if !matches!(tokens.get(index + 1), Some(Token::Unit(_))) {
+                    return Err(PhraseError::UnknownWord(number.to_string()));
+                }
+                if *number == 0 {
+                    return Err(PhraseError::Conflict(
+                        "the interval must be greater than 0".into(),
+                    ));
+                }
+                interval = Some(*number);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(interval.unwrap_or(1))
+}
+
+fn weekdays(tokens: &[Token]) -> Vec<Weekday> {
+    let mut days = tokens
+        .iter()
+        .filter_map(|token| match token {
+            Token::Day(day) => Some(*day),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    days.sort_by_key(|day| day.num_days_from_monday());
+    days.dedup();
+    days
+}
+
+fn format_time(time: NaiveTime) -> String {
+    time.format(TIME_FORMAT).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_examples_from_the_dialog() {
+        let weekday = parse_phrase("every weekday at 7:30").unwrap();
+        assert_eq!(weekday.repeat_type, RepeatType::Weekdays);
+        assert_eq!(weekday.scheduled_time, "07:30");
+        assert_eq!(weekday.description, "Every weekday at 07:30");
+
+        let hourly = parse_phrase("Every 2 hours from 9am to 5pm").unwrap();
+        assert_eq!(
+            hourly.repeat_type,
+            RepeatType::Custom {
+                interval_minutes: 120,
+                until: Some("17:00".into()),
+            }
+        );
+        assert_eq!(hourly.scheduled_time, "09:00");
+        assert_eq!(hourly.description, "Every 2 hours from 09:00 until 17:00");
+
+        let monthly = parse_phrase("on the first Monday of each month at noon").unwrap();
+        assert_eq!(
+            monthly.repeat_type,
+            RepeatType::Rrule {
+                rule: "FREQ=MONTHLY;BYDAY=1MO".into(),
+            }
+        );
+        assert_eq!(monthly.scheduled_time, "12:00");
+        assert_eq!(
+            monthly.description,
+            "On the first Monday of every month at 12:00"
+        );
+    }
+
+    #[test]
+    fn parses_day_lists_and_intervals() {
+        let weekly = parse_phrase("mondays and thurs at 6:15 pm").unwrap();
+        assert_eq!(
+            weekly.repeat_type,
+            RepeatType::Weekly {
+                days: vec![Weekday::Mon, Weekday::Thu],
+            }
+        );
+        assert_eq!(weekly.scheduled_time, "18:15");
+
+        let fortnightly = parse_phrase("every other friday at 16:00").unwrap();
+        assert_eq!(
+            fortnightly.repeat_type,
+            RepeatType::Rrule {
+                rule: "FREQ=WEEKLY;INTERVAL=2;BYDAY=FR".into(),
+            }
+        );
+
+        let month_end = parse_phrase("last day of every month 9pm").unwrap();
+        assert_eq!(
+            month_end.repeat_type,
+            RepeatType::Rrule {
+                rule: "FREQ=MONTHLY;BYMONTHDAY=-1".into(),
+            }
+        );
+        assert_eq!(month_end.scheduled_time, "21:00");
+
+        let once = parse_phrase("at midnight").unwrap();
+        assert_eq!(once.repeat_type, RepeatType::Once);
+        assert_eq!(once.scheduled_time, "00:00");
+    }
+
+    #[test]
+    fn rejects_unclear_phrases() {
+        assert_eq!(parse_phrase("   "), Err(PhraseError::Empty));
+        assert_eq!(parse_phrase("every weekday"), Err(PhraseError::MissingTime));
+        assert_eq!(
+            parse_phrase("every fortnight at 9"),
+            Err(PhraseError::UnknownWord("fortnight".into()))
+        );
+        assert_eq!(
+            parse_phrase("daily at 25:00"),
+            Err(PhraseError::ExpectedTime("at".into()))
+        );
+        assert!(matches!(
+            parse_phrase("every 2 hours from 5pm to 9am"),
+            Err(PhraseError::Conflict(_))
+        ));
+        assert!(matches!(
+            parse_phrase("every 31st monday at 9"),
+            Err(PhraseError::Conflict(_))
+        ));
+        assert!(parse_phrase("every 5th monday at 9").is_ok());
+    }
+}
@@ -0,0 +1,882 @@
+//! Subset of RFC 5545 recurrence rules used by `RepeatType::Rrule`.
+//!
+//! The time of day always comes from the schedule's `scheduled_time`, so the
+//! rules here only decide on which *dates* a schedule fires. `DTSTART` anchors
+//! `INTERVAL` and `COUNT`; `TZID` parameters are ignored and all values are
+//! treated as local wall time (except `...Z` values, which are UTC).
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{
+    DateTime, Datelike, Duration, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    Utc, Weekday,
+};
+use thiserror::Error;
+
+/// How far past its first period a search for dates goes. Every date
+/// pattern repeats within one 400-year Gregorian cycle, so rules that can
+/// never match (e.g. `BYMONTH=2;BYMONTHDAY=30`) terminate, while sparse ones
+/// (e.g. `BYMONTH=2;BYMONTHDAY=29`) still find their next date.
+const SEARCH_SPAN: Months = Months::new(400 * 12);
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RecurrenceError {
+    #[error("recurrence rule is missing FREQ")]
+    MissingFrequency,
+    #[error("unsupported frequency '{0}' (expected DAILY, WEEKLY, MONTHLY or YEARLY)")]
+    UnsupportedFrequency(String),
+    #[error("unsupported recurrence rule part '{0}'")]
+    UnsupportedPart(String),
+    #[error("unsupported recurrence property '{0}'")]
+    UnsupportedProperty(String),
+    #[error("invalid value '{value}' for {part}")]
+    InvalidValue { part: String, value: String },
+    #[error("{0}")]
+    Conflict(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Frequency {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        }
+    }
+}
+
+/// A `BYDAY` entry such as `MO`, `1MO` or `-1FR`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WeekdayNum {
+    pub ordinal: Option<i32>,
+    pub weekday: Weekday,
+}
+
+/// A DATE or DATE-TIME value as it appears in `DTSTART`, `UNTIL` or `EXDATE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcalDateTime {
+    Date(NaiveDate),
+    Floating(NaiveDateTime),
+    Utc(DateTime<Utc>),
+}
+
+impl IcalDateTime {
+    pub fn local_date(&self) -> NaiveDate {
+        match self {
+            IcalDateTime::Date(date) => *date,
+            IcalDateTime::Floating(value) => value.date(),
+            IcalDateTime::Utc(value) => value.with_timezone(&Local).date_naive(),
+        }
+    }
+
+    fn includes(&self, occurrence: NaiveDateTime) -> bool {
+        match self {
+            IcalDateTime::Date(date) => occurrence.date() <= *date,
+            IcalDateTime::Floating(value) => occurrence <= *value,
+            IcalDateTime::Utc(value) => match Local.from_local_datetime(&occurrence).earliest() {
+                Some(local) => local.with_timezone(&Utc) <= *value,
+                None => occurrence <= value.with_timezone(&Local).naive_local(),
+            },
+        }
+    }
+}
+
+impl FromStr for IcalDateTime {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if let Some(utc) = value.strip_suffix('Z') {
+            return NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+                .map(|naive| IcalDateTime::Utc(naive.and_utc()))
+                .map_err(|err| err.to_string());
+        }
+
+        if value.contains('T') {
+            NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+                .map(IcalDateTime::Floating)
+                .map_err(|err| err.to_string())
+        } else {
+            NaiveDate::parse_from_str(value, "%Y%m%d")
+                .map(IcalDateTime::Date)
+                .map_err(|err| err.to_string())
+        }
+    }
+}
+
+impl fmt::Display for IcalDateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IcalDateTime::Date(date) => write!(f, "{}", date.format("%Y%m%d")),
+            IcalDateTime::Floating(value) => write!(f, "{}", value.format("%Y%m%dT%H%M%S")),
+            IcalDateTime::Utc(value) => write!(f, "{}", value.format("%Y%m%dT%H%M%SZ")),
+        }
+    }
+}
+
+/// A single `RRULE` value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecurrenceRule {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub by_day: Vec<WeekdayNum>,
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+    pub by_set_pos: Vec<i32>,
+    pub count: Option<u32>,
+    pub until: Option<IcalDateTime>,
+    pub week_start: Weekday,
+}
+
+impl RecurrenceRule {
+    pub fn new(frequency: Frequency) -> Self {
+        Self {
+            frequency,
+            interval: 1,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+            by_set_pos: Vec::new(),
+            count: None,
+            until: None,
+            week_start: Weekday::Mon,
+        }
+    }
+
+    fn validate(&self) -> Result<(), RecurrenceError> {
+        if self.count.is_some() && self.until.is_some() {
+            return Err(RecurrenceError::Conflict(
+                "COUNT and UNTIL cannot both be set".into(),
+            ));
+        }
+
+        if matches!(self.frequency, Frequency::Daily | Frequency::Weekly)
+            && self.by_day.iter().any(|day| day.ordinal.is_some())
+        {
+            return Err(RecurrenceError::Conflict(format!(
+                "numbered BYDAY values are not allowed with FREQ={}",
+                self.frequency.as_str()
+            )));
+        }
+
+        if self.frequency == Frequency::Weekly && !self.by_month_day.is_empty() {
+            return Err(RecurrenceError::Conflict(
+                "BYMONTHDAY is not allowed with FREQ=WEEKLY".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// First day of the period containing `anchor`.
+    fn period_start(&self, anchor: NaiveDate) -> NaiveDate {
+        match self.frequency {
+            Frequency::Daily => anchor,
+            Frequency::Weekly => {
+                let offset = (7 + anchor.weekday().num_days_from_monday()
+                    - self.week_start.num_days_from_monday())
+                    % 7;
+                anchor - Duration::days(offset as i64)
+            }
+            Frequency::Monthly => anchor.with_day(1).unwrap_or(anchor),
+            Frequency::Yearly => NaiveDate::from_ymd_opt(anchor.year(), 1, 1).unwrap_or(anchor),
+        }
+    }
+
+    /// Start of the period that contains `date`, keeping to the `INTERVAL`
+    /// counted from the period of `anchor`.
+    fn period_containing(&self, anchor: NaiveDate, date: NaiveDate) -> NaiveDate {
+        let first = self.period_start(anchor);
+        let target = self.period_start(date);
+        if target <= first {
+            return first;
+        }
+
+        let months = |from: NaiveDate, to: NaiveDate| {
+            (to.year() - from.year()) as u32 * 12 + to.month() - from.month()
+        };
+        let interval = self.interval.max(1);
+        let start = match self.frequency {
+            Frequency::Daily => {
+                let days = (target - first).num_days() as u32;
+                first.checked_add_signed(Duration::days((days / interval * interval) as i64))
+            }
+            Frequency::Weekly => {
+                let weeks = (target - first).num_weeks() as u32;
+                first.checked_add_signed(Duration::weeks((weeks / interval * interval) as i64))
+            }
+            Frequency::Monthly => {
+                first.checked_add_months(Months::new(months(first, target) / interval * interval))
+            }
+            Frequency::Yearly => {
+                let years = (target.year() - first.year()) as u32;
+                first.checked_add_months(Months::new(years / interval * interval * 12))
+            }
+        };
+        start.unwrap_or(first)
+    }
+
+    fn next_period(&self, start: NaiveDate) -> Option<NaiveDate> {
+        match self.frequency {
+            Frequency::Daily => start.checked_add_signed(Duration::days(self.interval as i64)),
+            Frequency::Weekly => start.checked_add_signed(Duration::weeks(self.interval as i64)),
+            Frequency::Monthly => start.checked_add_months(Months::new(self.interval)),
+            Frequency::Yearly => start.checked_add_months(Months::new(self.interval * 12)),
+        }
+    }
+
+    /// Candidate dates of the period starting at `start`, in order and with
+    /// `BYSETPOS` applied.
+    fn expand(&self, start: NaiveDate, anchor: NaiveDate) -> Vec<NaiveDate> {
+        let dates = match self.frequency {
+            Frequency::Daily => self.expand_scope(start, start, anchor),
+            Frequency::Weekly => self.expand_scope(start, start + Duration::days(6), anchor),
+            Frequency::Monthly => self.expand_scope(start, last_day_of_month(start), anchor),
+            Frequency::Yearly if !self.by_month.is_empty() => (0..12)
+                .filter_map(|offset| start.checked_add_months(Months::new(offset)))
+                .flat_map(|first| self.expand_scope(first, last_day_of_month(first), anchor))
+                .collect(),
+            Frequency::Yearly => {
+                let end = NaiveDate::from_ymd_opt(start.year(), 12, 31).unwrap_or(start);
+                self.expand_scope(start, end, anchor)
+            }
+        };
+
+        if self.by_set_pos.is_empty() {
+            return dates;
+        }
+
+        let len = dates.len() as i32;
+        let mut selected: Vec<NaiveDate> = self
+            .by_set_pos
+            .iter()
+            .filter_map(|pos| {
+                let index = if *pos > 0 { pos - 1 } else { len + pos };
+                (0..len).contains(&index).then(|| dates[index as usize])
+            })
+            .collect();
+        selected.sort();
+        selected.dedup();
+        selected
+    }
+
+    fn expand_scope(&self, start: NaiveDate, end: NaiveDate, anchor: NaiveDate) -> Vec<NaiveDate> {
+        start
+            .iter_days()
+            .take_while(|date| *date <= end)
+            .filter(|date| self.matches(*date, start, end, anchor))
+            .collect()
+    }
+
+    fn matches(
+        &self,
+        date: NaiveDate,
+        start: NaiveDate,
+        end: NaiveDate,
+        anchor: NaiveDate,
+    ) -> bool {
+        if !self.by_month.is_empty() && !self.by_month.contains(&date.month()) {
+            return false;
+        }
+
+        if !self.by_month_day.is_empty() {
+            let last = last_day_of_month(date).day() as i32;
+            let day = date.day() as i32;
+            if !self
+                .by_month_day
+                .iter()
+                .any(|value| *value == day || *value == day - last - 1)
+            {
+                return false;
+            }
+        }
+
+        if !self.by_day.is_empty() {
+            return self.by_day.iter().any(|entry| {
+                if entry.weekday != date.weekday() {
+                    return false;
+                }
+
+                match entry.ordinal {
+                    None => true,
+                    Some(n) if n > 0 => ((date - start).num_days() / 7 + 1) as i32 == n,
+                    Some(n) => ((end - date).num_days() / 7 + 1) as i32 == -n,
+                }
+            });
+        }
+
+        if !self.by_month_day.is_empty() {
+            return true;
+        }
+
+        match self.frequency {
+            Frequency::Daily => true,
+            Frequency::Weekly => date.weekday() == anchor.weekday(),
+            Frequency::Monthly => date.day() == anchor.day(),
+            Frequency::Yearly => {
+                date.day() == anchor.day()
+                    && (!self.by_month.is_empty() || date.month() == anchor.month())
+            }
+        }
+    }
+}
+
+impl FromStr for RecurrenceRule {
+    type Err = RecurrenceError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let value = value
+            .strip_prefix("RRULE:")
+            .or_else(|| value.strip_prefix("rrule:"))
+            .unwrap_or(value);
+
+        let mut frequency = None;
+        let mut rule = RecurrenceRule::new(Frequency::Daily);
+
+        for part in value.split(';').filter(|part| !part.trim().is_empty()) {
+            let (key, raw) = part
+                .split_once('=')
+                .ok_or_else(|| RecurrenceError::UnsupportedPart(part.to_string()))?;
+            let key = key.trim().to_ascii_uppercase();
+            let raw = raw.trim().to_ascii_uppercase();
+            let invalid = || RecurrenceError::InvalidValue {
+                part: key.clone(),
+                value: raw.clone(),
+            };
+
+            match key.as_str() {
+                "FREQ" => {
+                    frequency = Some(match raw.as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(RecurrenceError::UnsupportedFrequency(raw)),
+                    })
+                }
+                "INTERVAL" => {
+                    rule.interval = raw
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|interval| *interval > 0)
+                        .ok_or_else(invalid)?
+                }
+                "COUNT" => {
+                    rule.count = Some(
+                        raw.parse::<u32>()
+                            .ok()
+                            .filter(|count| *count > 0)
+                            .ok_or_else(invalid)?,
+                    )
+                }
+                "UNTIL" => rule.until = Some(raw.parse().map_err(|_| invalid())?),
+                "WKST" => rule.week_start = parse_weekday(&raw).ok_or_else(invalid)?,
+                "BYDAY" => {
+                    rule.by_day = raw
+                        .split(',')
+                        .map(|item| parse_weekday_num(item).ok_or_else(invalid))
+                        .collect::<Result<_, _>>()?
+                }
+                "BYMONTHDAY" => {
+                    rule.by_month_day =
+                        parse_list(&raw, |v: i32| v != 0 && v.abs() <= 31).ok_or_else(invalid)?
+                }
+                "BYMONTH" => {
+                    rule.by_month =
+                        parse_list(&raw, |v: u32| (1..=12).contains(&v)).ok_or_else(invalid)?
+                }
+                "BYSETPOS" => {
+                    rule.by_set_pos =
+                        parse_list(&raw, |v: i32| v != 0 && v.abs() <= 366).ok_or_else(invalid)?
+                }
+                _ => return Err(RecurrenceError::UnsupportedPart(key)),
+            }
+        }
+
+        rule.frequency = frequency.ok_or(RecurrenceError::MissingFrequency)?;
+        rule.validate()?;
+
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for RecurrenceRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FREQ={}", self.frequency.as_str())?;
+
+        if self.interval > 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={count}")?;
+        }
+        if let Some(until) = &self.until {
+            write!(f, ";UNTIL={until}")?;
+        }
+        if !self.by_month.is_empty() {
+            write!(f, ";BYMONTH={}", join(&self.by_month))?;
+        }
+        if !self.by_month_day.is_empty() {
+            write!(f, ";BYMONTHDAY={}", join(&self.by_month_day))?;
+        }
+        if !self.by_day.is_empty() {
+            let days = self
+                .by_day
+                .iter()
+                .map(|entry| match entry.ordinal {
+                    Some(n) => format!("{n}{}", weekday_code(entry.weekday)),
+                    None => weekday_code(entry.weekday).to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            write!(f, ";BYDAY={days}")?;
+        }
+        if !self.by_set_pos.is_empty() {
+            write!(f, ";BYSETPOS={}", join(&self.by_set_pos))?;
+        }
+        if self.week_start != Weekday::Mon {
+            write!(f, ";WKST={}", weekday_code(self.week_start))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An `RRULE` together with its optional `DTSTART` and `EXDATE` lines.
+///
+/// Accepts either a bare rule (`FREQ=WEEKLY;BYDAY=MO`) or iCalendar content
+/// lines, one property per line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecurrenceSet {
+    pub start: Option<IcalDateTime>,
+    pub rule: RecurrenceRule,
+    pub exdates: Vec<IcalDateTime>,
+}
+
+impl RecurrenceSet {
+    /// Dates on which the rule fires at `time`, starting from the period that
+    /// contains `anchor`. The series begins at `anchor`: earlier dates in that
+    /// period are neither produced nor counted towards `COUNT`, so pass the
+    /// `DTSTART` date for `COUNT` to mean what RFC 5545 says. Excluded dates
+    /// do count, as in RFC 5545.
+    pub fn occurrences(&self, anchor: NaiveDate, time: NaiveTime) -> Occurrences<'_> {
+        Occurrences {
+            set: self,
+            anchor,
+            time,
+            period: Some(self.rule.period_start(anchor)),
+            search_end: search_end(self.rule.period_start(anchor)),
+            pending: VecDeque::new(),
+            produced: 0,
+        }
+    }
+
+    /// Like [`occurrences`](Self::occurrences), but begins with the period
+    /// that contains `from` rather than walking every period since `anchor`;
+    /// dates before `from` in that period may still be produced. A rule with
+    /// `COUNT` has to count everything since `anchor`, so it is walked in
+    /// full.
+    pub fn occurrences_from(
+        &self,
+        anchor: NaiveDate,
+        time: NaiveTime,
+        from: NaiveDate,
+    ) -> Occurrences<'_> {
+        let mut occurrences = self.occurrences(anchor, time);
+        if self.rule.count.is_none() {
+            let period = self.rule.period_containing(anchor, from);
+            occurrences.period = Some(period);
+            occurrences.search_end = search_end(period);
+        }
+        occurrences
+    }
+
+    fn is_excluded(&self, date: NaiveDate) -> bool {
+        self.exdates
+            .iter()
+            .any(|exdate| exdate.local_date() == date)
+    }
+}
+
+impl FromStr for RecurrenceSet {
+    type Err = RecurrenceError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut start = None;
+        let mut rule = None;
+        let mut exdates = Vec::new();
+
+        for line in value.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let Some((name, content)) = line.split_once(':') else {
+                rule = Some(line.parse::<RecurrenceRule>()?);
+                continue;
+            };
+
+            let property = name
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_uppercase();
+            let parse_value = |raw: &str| {
+                raw.parse::<IcalDateTime>()
+                    .map_err(|_| RecurrenceError::InvalidValue {
+                        part: property.clone(),
+                        value: raw.to_string(),
+                    })
+            };
+
+            match property.as_str() {
+                "RRULE" => rule = Some(content.parse::<RecurrenceRule>()?),
+                "DTSTART" => start = Some(parse_value(content)?),
+                "EXDATE" => {
+                    for raw in content.split(',') {
+                        exdates.push(parse_value(raw)?);
+                    }
+                }
+                _ => return Err(RecurrenceError::UnsupportedProperty(property)),
+            }
+        }
+
+        Ok(Self {
+            start,
+            rule: rule.ok_or(RecurrenceError::MissingFrequency)?,
+            exdates,
+        })
+    }
+}
+
+impl fmt::Display for RecurrenceSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(start) = &self.start {
+            writeln!(f, "DTSTART{}:{start}", value_param(start))?;
+        }
+
+        write!(f, "RRULE:{}", self.rule)?;
+
+        for exdate in &self.exdates {
+            write!(f, "\nEXDATE{}:{exdate}", value_param(exdate))?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct Occurrences<'a> {
+    set: &'a RecurrenceSet,
+    anchor: NaiveDate,
+    time: NaiveTime,
+    period: Option<NaiveDate>,
+    /// Periods starting after this are not searched.
+    search_end: NaiveDate,
+    pending: VecDeque<NaiveDate>,
+    produced: u32,
+}
+
+impl Iterator for Occurrences<'_> {
+    type Item = NaiveDate;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rule = &self.set.rule;
+
+        loop {
+            if let Some(date) = self.pending.pop_front() {
+                if date < self.anchor {
+                    continue;
+                }
+
+                if let Some(until) = &rule.until {
+                    if !until.includes(date.and_time(self.time)) {
+                        self.period = None;
+                        self.pending.clear();
+                        return None;
+                    }
+                }
+
+                self.produced += 1;
+                if rule.count.is_some_and(|count| self.produced > count) {
+                    self.period = None;
+                    self.pending.clear();
+                    return None;
+                }
+
+                if self.set.is_excluded(date) {
+                    continue;
+                }
+
+                return Some(date);
+            }
+
+            let start = self.period.filter(|start| *start <= self.search_end)?;
+            self.pending.extend(rule.expand(start, self.anchor));
+            self.period = rule.next_period(start);
+        }
+    }
+}
+
+fn search_end(first_period: NaiveDate) -> NaiveDate {
+    first_period
+        .checked_add_months(SEARCH_SPAN)
+        .unwrap_or(NaiveDate::MAX)
+}
+
+fn last_day_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1)
+        .and_then(|first| first.checked_add_months(Months::new(1)))
+        .and_then(|next| next.pred_opt())
+        .unwrap_or(date)
+}
+
+fn parse_list<T, F>(raw: &str, valid: F) -> Option<Vec<T>>
+where
+    T: FromStr,
+    F: Fn(T) -> bool,
+    T: Copy,
+{
+    raw.split(',')
+        .map(|item| item.trim().parse::<T>().ok().filter(|value| valid(*value)))
+        .collect()
+}
+
+fn join<T: fmt::Display>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn value_param(value: &IcalDateTime) -> &'static str {
+    match value {
+        IcalDateTime::Date(_) => ";VALUE=DATE",
+        _ => "",
+    }
+}
+
+fn parse_weekday_num(raw: &str) -> Option<WeekdayNum> {
+    let raw = raw.trim();
+    if raw.len() < 2 {
+        return None;
+    }
+
+    let (ordinal, code) = raw.split_at(raw.len() - 2);
+    let weekday = parse_weekday(code)?;
+    let ordinal = match ordinal {
+        "" => None,
+        value => Some(
+            value
+                .parse::<i32>()
+                .ok()
+                .filter(|n| *n != 0 && n.abs() <= 53)?,
+        ),
+    };
+
+    Some(WeekdayNum { ordinal, weekday })
+}
+
+fn parse_weekday(code: &str) -> Option<Weekday> {
+    match code {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+pub fn weekday_code(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn first_dates(rule: &str, anchor: NaiveDate, count: usize) -> Vec<NaiveDate> {
+        let set: RecurrenceSet = rule.parse().unwrap();
+        set.occurrences(anchor, NaiveTime::from_hms_opt(9, 0, 0).unwrap())
+            .take(count)
+            .collect()
+    }
+
+    #[test]
+    fn parses_and_formats_rule() {
+        let rule: RecurrenceRule = "FREQ=MONTHLY;INTERVAL=2;BYDAY=1MO,-1FR;COUNT=5"
+            .parse()
+            .unwrap();
+
+        assert_eq!(rule.frequency, Frequency::Monthly);
+        assert_eq!(rule.interval, 2);
+        assert_eq!(rule.by_day.len(), 2);
+        assert_eq!(rule.by_day[1].ordinal, Some(-1));
+        assert_eq!(
+            rule.to_string(),
+            "FREQ=MONTHLY;INTERVAL=2;COUNT=5;BYDAY=1MO,-1FR"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        assert_eq!(
+            "INTERVAL=2".parse::<RecurrenceRule>().unwrap_err(),
+            RecurrenceError::MissingFrequency
+        );
+        assert!(matches!(
+            "FREQ=HOURLY".parse::<RecurrenceRule>().unwrap_err(),
+            RecurrenceError::UnsupportedFrequency(_)
+        ));
+        assert!(matches!(
+            "FREQ=DAILY;COUNT=2;UNTIL=20250101"
+                .parse::<RecurrenceRule>()
+                .unwrap_err(),
+            RecurrenceError::Conflict(_)
+        ));
+        assert!(matches!(
+            "FREQ=WEEKLY;BYDAY=2MO"
+                .parse::<RecurrenceRule>()
+                .unwrap_err(),
+            RecurrenceError::Conflict(_)
+        ));
+    }
+
+    #[test]
+    fn weekly_with_interval_and_byday() {
+        // 2025-11-03 is a Monday.
+        let dates = first_dates("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE", date(2025, 11, 3), 4);
+
+        assert_eq!(
+            dates,
+            vec![
+                date(2025, 11, 3),
+                date(2025, 11, 5),
+                date(2025, 11, 17),
+                date(2025, 11, 19)
+            ]
+        );
+    }
+
+    #[test]
+    fn monthly_first_monday_and_last_weekday() {
+        let first_monday = first_dates("FREQ=MONTHLY;BYDAY=1MO", date(2025, 11, 1), 2);
+        assert_eq!(first_monday, vec![date(2025, 11, 3), date(2025, 12, 1)]);
+
+        let last_weekday = first_dates(
+            "FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1",
+            date(2025, 11, 1),
+            2,
+        );
+        assert_eq!(last_weekday, vec![date(2025, 11, 28), date(2025, 12, 31)]);
+    }
+
+    #[test]
+    fn monthly_bymonthday_skips_short_months() {
+        let dates = first_dates("FREQ=MONTHLY;BYMONTHDAY=31", date(2025, 1, 1), 3);
+        assert_eq!(
+            dates,
+            vec![date(2025, 1, 31), date(2025, 3, 31), date(2025, 5, 31)]
+        );
+    }
+
+    #[test]
+    fn count_until_and_exdate_limit_occurrences() {
+        let counted = first_dates(
+            "DTSTART;VALUE=DATE:20250101\nRRULE:FREQ=DAILY;COUNT=3\nEXDATE;VALUE=DATE:20250102",
+            date(2025, 1, 1),
+            10,
+        );
+        assert_eq!(counted, vec![date(2025, 1, 1), date(2025, 1, 3)]);
+
+        let until = first_dates("FREQ=DAILY;UNTIL=20250103", date(2025, 1, 1), 10);
+        assert_eq!(until.len(), 3);
+    }
+
+    #[test]
+    fn count_starts_at_the_anchor() {
+        // The week's Monday falls before the anchor, so it is not part of the
+        // series and does not use up one of the two occurrences.
+        let dates = first_dates("FREQ=WEEKLY;BYDAY=MO,WE,FR;COUNT=2", date(2025, 1, 1), 10);
+        assert_eq!(dates, vec![date(2025, 1, 1), date(2025, 1, 3)]);
+    }
+
+    #[test]
+    fn starting_late_gives_the_same_dates_as_a_full_walk() {
+        let time = NaiveTime::from_hms_opt(9, 0, 0).unwrap();
+        let anchor = date(2019, 3, 6);
+        let from = date(2025, 6, 18);
+        for rule in [
+            "FREQ=DAILY;INTERVAL=3",
+            "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH",
+            "FREQ=WEEKLY;INTERVAL=3",
+            "FREQ=MONTHLY;INTERVAL=5;BYDAY=-1FR",
+            "FREQ=YEARLY;INTERVAL=2",
+            "FREQ=DAILY;COUNT=5000",
+        ] {
+            let set: RecurrenceSet = rule.parse().unwrap();
+            let walked: Vec<NaiveDate> = set
+                .occurrences(anchor, time)
+                .skip_while(|date| *date < from)
+                .take(5)
+                .collect();
+            let skipped: Vec<NaiveDate> = set
+                .occurrences_from(anchor, time, from)
+                .skip_while(|date| *date < from)
+                .take(5)
+                .collect();
+            assert_eq!(skipped, walked, "{rule}");
+            assert_eq!(walked.len(), 5, "{rule}");
+        }
+    }
+
+    #[test]
+    fn impossible_rule_terminates() {
+        let dates = first_dates("FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=30", date(2025, 1, 1), 1);
+        assert!(dates.is_empty());
+    }
+
+    #[test]
+    fn sparse_rule_keeps_firing() {
+        // More than a thousand empty days lie between leap days.
+        let rule = "FREQ=DAILY;BYMONTH=2;BYMONTHDAY=29";
+        let dates = first_dates(rule, date(2025, 1, 1), 2);
+        assert_eq!(dates, vec![date(2028, 2, 29), date(2032, 2, 29)]);
+
+        // 2100 is not a leap year.
+        let dates = first_dates(rule, date(2097, 1, 1), 1);
+        assert_eq!(dates, vec![date(2104, 2, 29)]);
+    }
+
+    #[test]
+    fn set_round_trips_through_display() {
+        let source = "DTSTART;VALUE=DATE:20250101\nRRULE:FREQ=YEARLY;BYMONTH=12;BYMONTHDAY=25\nEXDATE;VALUE=DATE:20261225";
+        let set: RecurrenceSet = source.parse().unwrap();
+
+        assert_eq!(set.to_string(), source);
+    }
+}
@@ -0,0 +1,182 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+use chrono::{DateTime, Duration, Local};
+use serde::Serialize;
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+
+use crate::calendar::import::{self, Reminder, SourceFingerprint};
+use crate::calendar::CalendarEvent;
+use crate::db::execution_leases::ExecutionLeaseRepository;
+use crate::db::models::CalendarSubscription;
+use crate::db::Database;
+
+use super::engine::{send_notification, AudioController};
+
+/// How often a subscribed calendar is checked for changes while no reminder
+/// is due sooner.
+const POLL_INTERVAL: StdDuration = StdDuration::from_secs(15);
+/// Reminders missed by less than this (e.g. right after startup) still play;
+/// older ones are dropped.
+const LATE_TOLERANCE: Duration = Duration::minutes(1);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SubscriptionRuntimeInfo {
+    pub id: String,
+    pub name: String,
+    pub source_path: String,
+    pub event_count: usize,
+    pub next_reminder: Option<DateTime<Local>>,
+    pub next_event: Option<String>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct SubscriptionState {
+    event_count: usize,
+    next_reminder: Option<DateTime<Local>>,
+    next_event: Option<String>,
+    last_error: Option<String>,
+}
+
+impl SubscriptionState {
+    pub(super) fn runtime_info(
+        &self,
+        subscription: &CalendarSubscription,
+    ) -> SubscriptionRuntimeInfo {
+        SubscriptionRuntimeInfo {
+            id: subscription.id.clone(),
+            name: subscription.name.clone(),
+            source_path: subscription.source_path.clone(),
+            event_count: self.event_count,
+            next_reminder: self.next_reminder,
+            next_event: self.next_event.clone(),
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+pub(super) async fn run_subscription_task(
+    subscription: CalendarSubscription,
+    state: Arc<RwLock<SubscriptionState>>,
+    database: Database,
+    audio: Arc<dyn AudioController>,
+    app_handle: Option<tauri::AppHandle>,
+    owner: String,
+    cancel_token: CancellationToken,
+) {
+    let leases = database.execution_lease_repository();
+    let source = PathBuf::from(&subscription.source_path);
+    let lead = Duration::minutes(subscription.lead_minutes as i64);
+    let mut fingerprint: Option<SourceFingerprint> = None;
+    let mut events: Vec<CalendarEvent> = Vec::new();
+    let mut checked_until = Local::now() - LATE_TOLERANCE;
+
+    while !cancel_token.is_cancelled() {
+        // Re-read the calendar right before deciding what is due, so events
+        // removed from the file never fire.
+        let load_error = match import::source_fingerprint(&source) {
+            Ok(current) if fingerprint.as_ref() == Some(&current) => None,
+            Ok(current) => match import::load_events(&source) {
+                Ok(loaded) => {
+                    events = loaded;
+                    fingerprint = Some(current);
+                    None
+                }
+                Err(err) => Some(err.to_string()),
+            },
+            Err(err) => Some(err.to_string()),
+        };
+
+        if load_error.is_some() {
+            events.clear();
+            fingerprint = None;
+        }
+
+        let now = Local::now();
+        let window_start = checked_until.max(now - LATE_TOLERANCE);
+        let mut play_error = None;
+        for reminder in import::reminders_between(&events, lead, window_start, now) {
+            let key = ExecutionLeaseRepository::reminder_key(
+                &subscription.id,
+                &reminder.uid,
+                &reminder.starts_at,
+            );
+            match leases.try_acquire(&key, &owner).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(err) => eprintln!("Failed to acquire reminder lease: {}", err),
+            }
+            if let Err(message) =
+                fire_reminder(&subscription, &reminder, &database, &*audio, &app_handle).await
+            {
+                play_error = Some(message);
+            }
+        }
+        checked_until = now;
+
+        let next = import::next_reminder(&events, lead, now);
+        {
+            let mut guard = state.write().await;
+            guard.event_count = events.len();
+            guard.next_reminder = next.as_ref().map(|reminder| reminder.fire_at);
+            guard.next_event = next.as_ref().map(|reminder| reminder.summary.clone());
+            if load_error.is_some() || play_error.is_some() {
+                guard.last_error = load_error.or(play_error);
+            } else if fingerprint.is_some() {
+                guard.last_error = None;
+            }
+        }
+
+        let wait = next
+            .and_then(|reminder| (reminder.fire_at - now).to_std().ok())
+            .map_or(POLL_INTERVAL, |wait| wait.min(POLL_INTERVAL));
+
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = cancel_token.cancelled() => break,
+        }
+    }
+
+    let mut guard = state.write().await;
+    guard.next_reminder = None;
+    guard.next_event = None;
+}
+
+async fn fire_reminder(
+    subscription: &CalendarSubscription,
+    reminder: &Reminder,
+    database: &Database,
+    audio: &dyn AudioController,
+    app_handle: &Option<tauri::AppHandle>,
+) -> Result<(), String> {
+    match audio
+        .play(&subscription.audio_file_path, subscription.volume)
+        .await
+    {
+        Ok(()) => {
+            send_notification(
+                app_handle,
+                database,
+                "Upcoming Event",
+                &format!(
+                    "\"{}\" starts at {}",
+                    reminder.summary,
+                    reminder.starts_at.format("%H:%M")
+                ),
+            )
+            .await;
+            Ok(())
+        }
+        Err(err) => {
+            let message = err.to_string();
+            eprintln!(
+                "Failed to play reminder for \"{}\" from {}: {}",
+                reminder.summary, subscription.name, message
+            );
+            Err(message)
+        }
+    }
+}
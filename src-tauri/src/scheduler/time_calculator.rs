@@ -1,20 +1,137 @@
+use std::collections::BTreeSet;
+
 use chrono::{
     DateTime, Datelike, Duration, Local, LocalResult, NaiveDate, NaiveDateTime, NaiveTime,
     TimeZone, Weekday,
 };
 
-use crate::db::models::{RepeatType, Schedule};
+use crate::db::models::{
+    DstPolicy, FallBackPolicy, HolidayRule, PreAlert, RepeatType, Schedule, SettingsSnapshot,
+    SpringForwardPolicy,
+};
 
+use super::recurrence::RecurrenceSet;
 use super::SchedulerError;
 
 const TIME_FORMAT: &str = "%H:%M";
-const GRACE_PERIOD: Duration = Duration::minutes(1);
+/// Furthest a holiday occurrence is moved looking for a business day.
+const MAX_SHIFT_DAYS: i64 = 31;
+/// Occurrences examined before giving up when holidays cover every one.
+const MAX_HOLIDAY_LOOKAHEAD: usize = 1000;
+/// Longer than any real DST gap; bounds the search for the end of one.
+const MAX_GAP_MINUTES: i64 = 24 * 60;
+
+/// How late a missed occurrence may still fire, and how long after the
+/// previous run it has to be before it does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GracePolicy {
+    pub late_tolerance: Duration,
+    pub min_spacing: Duration,
+}
+
+impl Default for GracePolicy {
+    fn default() -> Self {
+        Self {
+            late_tolerance: Duration::minutes(1),
+            min_spacing: Duration::minutes(1),
+        }
+    }
+}
 
+impl GracePolicy {
+    pub fn from_seconds(late_tolerance: u32, min_spacing: u32) -> Self {
+        Self {
+            late_tolerance: Duration::seconds(late_tolerance as i64),
+            min_spacing: Duration::seconds(min_spacing as i64),
+        }
+    }
+
+    /// The app-wide defaults from the settings.
+    pub fn from_settings(settings: &SettingsSnapshot) -> Self {
+        Self::from_seconds(settings.late_tolerance_seconds, settings.min_spacing_seconds)
+    }
+
+    /// The schedule's own overrides, falling back to `defaults`.
+    pub fn for_schedule(schedule: &Schedule, defaults: GracePolicy) -> Self {
+        Self {
+            late_tolerance: schedule
+                .late_tolerance_seconds
+                .map_or(defaults.late_tolerance, |seconds| {
+                    Duration::seconds(seconds as i64)
+                }),
+            min_spacing: schedule
+                .min_spacing_seconds
+                .map_or(defaults.min_spacing, |seconds| Duration::seconds(seconds as i64)),
+        }
+    }
+
+    /// Whether `candidate`, already in the past at `reference`, should still
+    /// fire now. An occurrence covered by `last_run` never fires twice.
+    fn should_fire_late<Tz: TimeZone>(
+        &self,
+        candidate: &DateTime<Tz>,
+        reference: &DateTime<Tz>,
+        last_run: Option<&DateTime<Tz>>,
+    ) -> bool {
+        if reference.clone() - candidate.clone() > self.late_tolerance {
+            return false;
+        }
+
+        match last_run {
+            Some(last) => last < candidate && reference.clone() - last.clone() >= self.min_spacing,
+            None => true,
+        }
+    }
+}
+
+/// A scheduled occurrence and when it actually fires. The two differ only for
+/// a missed occurrence that still fires late, at the reference time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Occurrence<Tz: TimeZone = Local> {
+    pub scheduled_for: DateTime<Tz>,
+    pub fire_at: DateTime<Tz>,
+}
+
+impl<Tz: TimeZone> Occurrence<Tz> {
+    fn on_time(at: DateTime<Tz>) -> Self {
+        Self {
+            scheduled_for: at.clone(),
+            fire_at: at,
+        }
+    }
+
+    fn late(scheduled_for: DateTime<Tz>, fire_at: DateTime<Tz>) -> Self {
+        Self {
+            scheduled_for,
+            fire_at,
+        }
+    }
+}
+
+/// Next run of `schedule` using its own grace overrides on top of
+/// `defaults`, normally [`GracePolicy::from_settings`].
 pub fn next_execution_time(
     schedule: &Schedule,
+    defaults: GracePolicy,
     reference: DateTime<Local>,
     last_run: Option<DateTime<Local>>,
 ) -> Result<Option<DateTime<Local>>, SchedulerError> {
+    let grace = GracePolicy::for_schedule(schedule, defaults);
+    Ok(next_occurrence(schedule, grace, reference, last_run)?.map(|occurrence| occurrence.fire_at))
+}
+
+/// Generic over the time zone so DST handling can be exercised for zones
+/// other than the system's own.
+fn next_occurrence<Tz>(
+    schedule: &Schedule,
+    grace: GracePolicy,
+    reference: DateTime<Tz>,
+    last_run: Option<DateTime<Tz>>,
+) -> Result<Option<Occurrence<Tz>>, SchedulerError>
+where
+    Tz: TimeZone,
+    Tz::Offset: Copy,
+{
     if !schedule.enabled {
         return Ok(None);
     }
@@ -26,32 +143,38 @@ pub fn next_execution_time(
         }
     })?;
 
+    let dst = schedule.dst_policy;
     let next = match &schedule.repeat_type {
         RepeatType::Once | RepeatType::Daily => Some(find_next_matching_day(
             reference,
             time,
+            dst,
+            grace,
             last_run,
-            |_, _| true,
+            |_| true,
         )),
-        RepeatType::Weekdays => Some(find_next_matching_day(reference, time, last_run, |date, _| {
+        RepeatType::Weekdays => Some(find_next_matching_day(reference, time, dst, grace, last_run, |date| {
             matches!(
                 date.weekday(),
                 Weekday::Mon | Weekday::Tue | Weekday::Wed | Weekday::Thu | Weekday::Fri
             )
         })),
-        RepeatType::Weekends => Some(find_next_matching_day(reference, time, last_run, |date, _| {
+        RepeatType::Weekends => Some(find_next_matching_day(reference, time, dst, grace, last_run, |date| {
             matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
         })),
         RepeatType::Weekly { days } => {
             if days.is_empty() {
                 None
             } else {
-                Some(find_next_matching_day(reference, time, last_run, |date, _| {
+                Some(find_next_matching_day(reference, time, dst, grace, last_run, |date| {
                     days.iter().any(|d| *d == date.weekday())
                 }))
             }
         }
-        RepeatType::Custom { interval_minutes } => {
+        RepeatType::Custom {
+            interval_minutes,
+            until,
+        } => {
             if *interval_minutes == 0 {
                 return Err(SchedulerError::InvalidScheduleTime {
                     schedule_id: schedule.id.clone(),
@@ -59,44 +182,198 @@ pub fn next_execution_time(
                 });
             }
 
+            let until = until
+                .as_deref()
+                .map(parse_time)
+                .transpose()
+                .map_err(|reason| SchedulerError::InvalidScheduleTime {
+                    schedule_id: schedule.id.clone(),
+                    reason,
+                })?;
+            if until.is_some_and(|until| until < time) {
+                return Err(SchedulerError::InvalidScheduleTime {
+                    schedule_id: schedule.id.clone(),
+                    reason: "custom interval must end after it starts".into(),
+                });
+            }
+
             Some(find_next_custom_interval(
                 reference,
                 time,
+                until,
                 *interval_minutes as i64,
+                grace,
                 last_run,
             ))
         }
+        RepeatType::Rrule { rule } => {
+            let set = rule.parse::<RecurrenceSet>().map_err(|err| {
+                SchedulerError::InvalidScheduleTime {
+                    schedule_id: schedule.id.clone(),
+                    reason: format!("invalid recurrence rule - {err}"),
+                }
+            })?;
+
+            // Without DTSTART, INTERVAL and COUNT are counted from the day the
+            // schedule was created.
+            let anchor = set
+                .start
+                .map(|start| start.local_date())
+                .or_else(|| {
+                    DateTime::parse_from_rfc3339(&schedule.created_at)
+                        .ok()
+                        .map(|created| created.with_timezone(&reference.timezone()).date_naive())
+                })
+                .unwrap_or_else(|| reference.date_naive());
+
+            find_next_recurrence(&set, anchor, reference, time, dst, grace, last_run)
+        }
     };
 
     Ok(next)
 }
 
+/// Like [`next_execution_time`] with an already resolved [`GracePolicy`], but
+/// occurrences falling on one of `holidays`
+/// are skipped or moved to the previous/next business day according to the
+/// schedule's [`HolidayRule`]. Interval schedules shift the first run of the
+/// holiday only, since the rest of the day's series is dropped.
+pub fn next_occurrence_with_holidays(
+    schedule: &Schedule,
+    holidays: &BTreeSet<NaiveDate>,
+    grace: GracePolicy,
+    reference: DateTime<Local>,
+    last_run: Option<DateTime<Local>>,
+) -> Result<Option<Occurrence>, SchedulerError> {
+    if holidays.is_empty() {
+        return next_occurrence(schedule, grace, reference, last_run);
+    }
+
+    // Moving back can put a later holiday ahead of an earlier occurrence, so
+    // keep looking a little past the best candidate found so far.
+    let window = match schedule.holiday_rule {
+        HolidayRule::PreviousBusinessDay => Duration::days(MAX_SHIFT_DAYS),
+        HolidayRule::Skip | HolidayRule::NextBusinessDay => Duration::zero(),
+    };
+
+    let mut best: Option<Occurrence> = None;
+    let mut search_from = reference;
+    let mut search_last_run = last_run;
+
+    for _ in 0..MAX_HOLIDAY_LOOKAHEAD {
+        let Some(occurrence) = next_occurrence(schedule, grace, search_from, search_last_run)? else {
+            break;
+        };
+        if best
+            .as_ref()
+            .is_some_and(|best| occurrence.fire_at > best.fire_at + window)
+        {
+            break;
+        }
+
+        let date = occurrence.scheduled_for.date_naive();
+        if !holidays.contains(&date) {
+            search_from = occurrence.fire_at + Duration::seconds(1);
+            search_last_run = Some(occurrence.fire_at);
+            keep_earliest(&mut best, occurrence);
+            continue;
+        }
+
+        let shifted = match schedule.holiday_rule {
+            HolidayRule::Skip => None,
+            HolidayRule::PreviousBusinessDay => business_day(date, holidays, -1),
+            HolidayRule::NextBusinessDay => business_day(date, holidays, 1),
+        }
+        .and_then(|day| {
+            let time = occurrence.scheduled_for.naive_local().time();
+            resolve_local(&Local, day, time, schedule.dst_policy)
+                .into_iter()
+                .find(|shifted| *shifted >= reference)
+        });
+
+        if let Some(shifted) = shifted {
+            keep_earliest(&mut best, Occurrence::on_time(shifted));
+        }
+
+        let Some(next_day) = date.succ_opt() else {
+            break;
+        };
+        search_from = earliest_instant(&Local, next_day, NaiveTime::MIN);
+        search_last_run = None;
+    }
+
+    Ok(best)
+}
+
+fn keep_earliest(best: &mut Option<Occurrence>, candidate: Occurrence) {
+    if best
+        .as_ref()
+        .is_none_or(|best| candidate.fire_at < best.fire_at)
+    {
+        *best = Some(candidate);
+    }
+}
+
+/// The closest weekday in direction `step` (+1 or -1) that is not a holiday.
+fn business_day(date: NaiveDate, holidays: &BTreeSet<NaiveDate>, step: i64) -> Option<NaiveDate> {
+    (1..=MAX_SHIFT_DAYS)
+        .map(|offset| date + Duration::days(offset * step))
+        .find(|day| {
+            !matches!(day.weekday(), Weekday::Sat | Weekday::Sun) && !holidays.contains(day)
+        })
+}
+
+/// When each of the schedule's pre-alerts fires ahead of `next_run`, earliest
+/// first. Alerts with no lead time or a duplicate lead time are ignored.
+pub fn pre_alert_times(
+    schedule: &Schedule,
+    next_run: DateTime<Local>,
+) -> Vec<(DateTime<Local>, &PreAlert)> {
+    let mut alerts = schedule
+        .pre_alerts
+        .iter()
+        .filter(|alert| alert.minutes_before > 0)
+        .collect::<Vec<_>>();
+    alerts.sort_by_key(|alert| std::cmp::Reverse(alert.minutes_before));
+    alerts.dedup_by_key(|alert| alert.minutes_before);
+
+    alerts
+        .into_iter()
+        .map(|alert| (next_run - Duration::minutes(alert.minutes_before as i64), alert))
+        .collect()
+}
+
 fn parse_time(value: &str) -> Result<NaiveTime, String> {
     NaiveTime::parse_from_str(value, TIME_FORMAT)
         .map_err(|err| format!("invalid time '{value}' - {err}"))
 }
 
-fn find_next_matching_day<F>(
-    reference: DateTime<Local>,
+fn find_next_matching_day<Tz, F>(
+    reference: DateTime<Tz>,
     time: NaiveTime,
-    last_run: Option<DateTime<Local>>,
+    dst: DstPolicy,
+    grace: GracePolicy,
+    last_run: Option<DateTime<Tz>>,
     predicate: F,
-) -> DateTime<Local>
+) -> Occurrence<Tz>
 where
-    F: Fn(NaiveDate, DateTime<Local>) -> bool,
+    Tz: TimeZone,
+    Tz::Offset: Copy,
+    F: Fn(NaiveDate) -> bool,
 {
+    let zone = reference.timezone();
     let mut date = reference.date_naive();
 
     loop {
-        let candidate = combine(date, time);
-
-        if predicate(date, candidate) {
-            if candidate >= reference {
-                return candidate;
-            }
+        if predicate(date) {
+            for candidate in resolve_local(&zone, date, time, dst) {
+                if candidate >= reference {
+                    return Occurrence::on_time(candidate);
+                }
 
-            if reference - candidate <= GRACE_PERIOD && should_fire_with_grace(reference, last_run) {
-                return reference;
+                if grace.should_fire_late(&candidate, &reference, last_run.as_ref()) {
+                    return Occurrence::late(candidate, reference);
+                }
             }
         }
 
@@ -104,63 +381,125 @@ where
     }
 }
 
-fn find_next_custom_interval(
-    reference: DateTime<Local>,
+/// Interval schedules restart at `time` every day and then step in real
+/// elapsed time, so a DST change shifts the wall-clock times of later runs
+/// rather than stretching or shrinking an interval.
+fn find_next_custom_interval<Tz>(
+    reference: DateTime<Tz>,
     time: NaiveTime,
+    until: Option<NaiveTime>,
     interval_minutes: i64,
-    last_run: Option<DateTime<Local>>,
-) -> DateTime<Local> {
-    let interval_minutes = interval_minutes.max(1);
-    let interval = Duration::minutes(interval_minutes);
-    let mut candidate = combine(reference.date_naive(), time);
+    grace: GracePolicy,
+    last_run: Option<DateTime<Tz>>,
+) -> Occurrence<Tz>
+where
+    Tz: TimeZone,
+    Tz::Offset: Copy,
+{
+    let interval_seconds = Duration::minutes(interval_minutes.max(1)).num_seconds();
+    let start = earliest_instant(&reference.timezone(), reference.date_naive(), time);
 
-    if candidate > reference {
-        return candidate;
+    if start > reference {
+        return Occurrence::on_time(start);
     }
 
-    if reference - candidate <= GRACE_PERIOD && should_fire_with_grace(reference, last_run) {
-        return reference;
+    // The most recent slot of today's series may still be within tolerance.
+    let mut intervals_passed = (reference - start).num_seconds() / interval_seconds;
+    let last_interval = until.map(|until| (until - time).num_seconds() / interval_seconds);
+    if let Some(last_interval) = last_interval {
+        intervals_passed = intervals_passed.min(last_interval);
     }
+    let latest = start + Duration::seconds(interval_seconds * intervals_passed);
 
-    let elapsed = reference - candidate;
-    let interval_seconds = interval.num_seconds().max(1);
-    let intervals_passed = elapsed.num_seconds() / interval_seconds;
-    let advance_seconds = interval_seconds * (intervals_passed + 1);
-    candidate += Duration::seconds(advance_seconds);
+    if grace.should_fire_late(&latest, &reference, last_run.as_ref()) {
+        return Occurrence::late(latest, reference);
+    }
+
+    if last_interval.is_some_and(|last_interval| intervals_passed >= last_interval) {
+        let tomorrow = reference.date_naive() + Duration::days(1);
+        return Occurrence::on_time(earliest_instant(&reference.timezone(), tomorrow, time));
+    }
 
-    candidate
+    Occurrence::on_time(latest + Duration::seconds(interval_seconds))
 }
 
-fn combine(date: NaiveDate, time: NaiveTime) -> DateTime<Local> {
-    let naive = NaiveDateTime::new(date, time);
+fn find_next_recurrence<Tz>(
+    set: &RecurrenceSet,
+    anchor: NaiveDate,
+    reference: DateTime<Tz>,
+    time: NaiveTime,
+    dst: DstPolicy,
+    grace: GracePolicy,
+    last_run: Option<DateTime<Tz>>,
+) -> Option<Occurrence<Tz>>
+where
+    Tz: TimeZone,
+    Tz::Offset: Copy,
+{
+    let zone = reference.timezone();
+    // Start from the earliest date that could still fire late, with a day
+    // to spare for a DST shift across midnight.
+    let from = reference
+        .naive_local()
+        .checked_sub_signed(grace.late_tolerance + Duration::days(1))
+        .map_or(anchor, |earliest| earliest.date());
+    for date in set.occurrences_from(anchor, time, from) {
+        for candidate in resolve_local(&zone, date, time, dst) {
+            if candidate >= reference {
+                return Some(Occurrence::on_time(candidate));
+            }
 
-    match Local.from_local_datetime(&naive) {
-        LocalResult::Single(dt) => dt,
-        LocalResult::Ambiguous(dt, _) => dt,
-        LocalResult::None => {
-            // Handle DST gaps by moving forward minute-by-minute until a valid time exists.
-            let mut attempt = naive + Duration::minutes(1);
-            loop {
-                match Local.from_local_datetime(&attempt) {
-                    LocalResult::Single(dt) => break dt,
-                    LocalResult::Ambiguous(dt, _) => break dt,
-                    LocalResult::None => attempt += Duration::minutes(1),
-                }
+            if grace.should_fire_late(&candidate, &reference, last_run.as_ref()) {
+                return Some(Occurrence::late(candidate, reference));
             }
         }
     }
+
+    None
 }
 
-fn should_fire_with_grace(
-    reference: DateTime<Local>,
-    last_run: Option<DateTime<Local>>,
-) -> bool {
-    match last_run {
-        Some(last) => reference - last >= GRACE_PERIOD,
-        None => true,
+/// The instants at which wall-clock `time` on `date` fires under `policy`:
+/// none when a skipped spring-forward gap swallows it, two when it repeats
+/// during fall-back and both are wanted.
+fn resolve_local<Tz: TimeZone>(
+    zone: &Tz,
+    date: NaiveDate,
+    time: NaiveTime,
+    policy: DstPolicy,
+) -> Vec<DateTime<Tz>> {
+    let naive = NaiveDateTime::new(date, time);
+
+    match zone.from_local_datetime(&naive) {
+        LocalResult::Single(dt) => vec![dt],
+        LocalResult::Ambiguous(first, second) => match policy.fall_back {
+            FallBackPolicy::First => vec![first],
+            FallBackPolicy::Second => vec![second],
+            FallBackPolicy::Both => vec![first, second],
+        },
+        LocalResult::None => match policy.spring_forward {
+            SpringForwardPolicy::ShiftForward => first_after_gap(zone, naive).into_iter().collect(),
+            SpringForwardPolicy::Skip => Vec::new(),
+        },
     }
 }
 
+/// The first instant at or after wall-clock `time` on `date`, whatever the
+/// schedule's DST policy.
+fn earliest_instant<Tz: TimeZone>(zone: &Tz, date: NaiveDate, time: NaiveTime) -> DateTime<Tz> {
+    let naive = NaiveDateTime::new(date, time);
+    zone.from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| first_after_gap(zone, naive))
+        .unwrap_or_else(|| zone.from_utc_datetime(&naive))
+}
+
+fn first_after_gap<Tz: TimeZone>(zone: &Tz, naive: NaiveDateTime) -> Option<DateTime<Tz>> {
+    (1..=MAX_GAP_MINUTES).find_map(|minutes| {
+        zone.from_local_datetime(&(naive + Duration::minutes(minutes)))
+            .earliest()
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -178,6 +517,15 @@ mod tests {
             created_at: "".into(),
             updated_at: "".into(),
             last_run_at: None,
+            pre_alerts: Vec::new(),
+            holiday_calendar_id: None,
+            holiday_rule: Default::default(),
+            late_tolerance_seconds: None,
+            min_spacing_seconds: None,
+            dst_policy: Default::default(),
+            audio_source: Default::default(),
+            audio_source_position: 0,
+            playback: Default::default(),
         }
     }
 
@@ -187,7 +535,9 @@ mod tests {
         let schedule_time = reference.format("%H:%M").to_string();
         let schedule = schedule_with_repeat(RepeatType::Daily, &schedule_time);
 
-        let next = next_execution_time(&schedule, reference, None).unwrap().unwrap();
+        let next = next_execution_time(&schedule, GracePolicy::default(), reference, None)
+            .unwrap()
+            .unwrap();
 
         let diff = next - reference;
         assert!(diff >= Duration::seconds(0));
@@ -202,7 +552,9 @@ mod tests {
             .with_timezone(&Local);
         let schedule = schedule_with_repeat(RepeatType::Weekdays, "09:30");
 
-        let next = next_execution_time(&schedule, reference, None).unwrap().unwrap();
+        let next = next_execution_time(&schedule, GracePolicy::default(), reference, None)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(next.weekday(), Weekday::Mon);
     }
@@ -215,15 +567,43 @@ mod tests {
         let schedule = schedule_with_repeat(
             RepeatType::Custom {
                 interval_minutes: 45,
+                until: None,
             },
             "08:00",
         );
 
-        let next = next_execution_time(&schedule, reference, None).unwrap().unwrap();
+        let next = next_execution_time(&schedule, GracePolicy::default(), reference, None)
+            .unwrap()
+            .unwrap();
 
         assert!(next > reference);
     }
 
+    #[test]
+    fn custom_interval_stops_at_window_end() {
+        let schedule = schedule_with_repeat(
+            RepeatType::Custom {
+                interval_minutes: 120,
+                until: Some("17:00".into()),
+            },
+            "09:00",
+        );
+        let today = NaiveDate::from_ymd_opt(2025, 11, 13).unwrap();
+        let local = |date: NaiveDate, h: u32, m: u32| {
+            Local.from_local_datetime(&date.and_hms_opt(h, m, 0).unwrap()).unwrap()
+        };
+
+        let midday = next_execution_time(&schedule, GracePolicy::default(), local(today, 12, 30), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(midday, local(today, 13, 0));
+
+        let evening = next_execution_time(&schedule, GracePolicy::default(), local(today, 17, 30), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(evening, local(today.succ_opt().unwrap(), 9, 0));
+    }
+
     #[test]
     fn next_execution_custom_interval_short_duration_check() {
         // Start time 10:00
@@ -239,14 +619,25 @@ mod tests {
             audio_file_path: "test.mp3".into(),
             scheduled_time: start_time.into(),
             enabled: true,
-            repeat_type: RepeatType::Custom { interval_minutes: 1 },
+            repeat_type: RepeatType::Custom { interval_minutes: 1, until: None },
             volume: 100,
             created_at: "".into(),
             updated_at: "".into(),
             last_run_at: None,
+            pre_alerts: Vec::new(),
+            holiday_calendar_id: None,
+            holiday_rule: Default::default(),
+            late_tolerance_seconds: None,
+            min_spacing_seconds: None,
+            dst_policy: Default::default(),
+            audio_source: Default::default(),
+            audio_source_position: 0,
+            playback: Default::default(),
         };
 
-        let next = next_execution_time(&schedule, reference, last_run).unwrap().unwrap();
+        let next = next_execution_time(&schedule, GracePolicy::default(), reference, last_run)
+            .unwrap()
+            .unwrap();
         
         // Expected: 10:01:00
         let expected = reference.date_naive().and_hms_opt(10, 1, 0).unwrap().and_local_timezone(Local).unwrap();
@@ -267,11 +658,364 @@ mod tests {
             "09:00"
         );
 
-        let next = next_execution_time(&schedule, reference, None).unwrap().unwrap();
+        let next = next_execution_time(&schedule, GracePolicy::default(), reference, None)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(next.weekday(), Weekday::Wed);
         // Should be next week
         assert!(next > reference);
         assert!((next - reference).num_days() > 2); 
     }
+
+    #[test]
+    fn next_execution_rrule_last_friday_of_month() {
+        let reference = Local.with_ymd_and_hms(2025, 11, 14, 10, 0, 0).unwrap();
+        let schedule = schedule_with_repeat(
+            RepeatType::Rrule {
+                rule: "FREQ=MONTHLY;BYDAY=-1FR".into(),
+            },
+            "09:00",
+        );
+
+        let next = next_execution_time(&schedule, GracePolicy::default(), reference, None)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(next.date_naive(), NaiveDate::from_ymd_opt(2025, 11, 28).unwrap());
+        assert_eq!(next.format("%H:%M").to_string(), "09:00");
+    }
+
+    #[test]
+    fn next_execution_rrule_exhausted_returns_none() {
+        let reference = Local.with_ymd_and_hms(2025, 11, 14, 10, 0, 0).unwrap();
+        let schedule = schedule_with_repeat(
+            RepeatType::Rrule {
+                rule: "DTSTART;VALUE=DATE:20251101\nRRULE:FREQ=DAILY;COUNT=3".into(),
+            },
+            "09:00",
+        );
+
+        let next = next_execution_time(&schedule, GracePolicy::default(), reference, None)
+            .unwrap();
+
+        assert!(next.is_none());
+    }
+
+    #[test]
+    fn next_execution_rrule_invalid_rule_errors() {
+        let schedule = schedule_with_repeat(
+            RepeatType::Rrule {
+                rule: "FREQ=SOMETIMES".into(),
+            },
+            "09:00",
+        );
+
+        let err = next_execution_time(&schedule, GracePolicy::default(), Local::now(), None)
+            .unwrap_err();
+
+        assert!(matches!(err, SchedulerError::InvalidScheduleTime { .. }));
+    }
+
+    #[test]
+    fn pre_alert_times_are_ordered_and_deduplicated() {
+        let mut schedule = schedule_with_repeat(RepeatType::Daily, "07:30");
+        let alert = |minutes_before| PreAlert {
+            minutes_before,
+            audio_file_path: None,
+            volume: None,
+        };
+        schedule.pre_alerts = vec![alert(1), alert(10), alert(0), alert(1)];
+        let next_run = Local.with_ymd_and_hms(2025, 11, 14, 7, 30, 0).unwrap();
+
+        let times = pre_alert_times(&schedule, next_run)
+            .into_iter()
+            .map(|(at, alert)| (at.format("%H:%M").to_string(), alert.minutes_before))
+            .collect::<Vec<_>>();
+
+        assert_eq!(times, vec![("07:20".to_string(), 10), ("07:29".to_string(), 1)]);
+    }
+
+    #[test]
+    fn holiday_rules_skip_or_shift_to_business_days() {
+        // Monday; the Friday schedule lands on a holiday this week.
+        let reference = Local.with_ymd_and_hms(2025, 11, 10, 10, 0, 0).unwrap();
+        let holidays = BTreeSet::from([NaiveDate::from_ymd_opt(2025, 11, 14).unwrap()]);
+        let mut schedule = schedule_with_repeat(
+            RepeatType::Weekly {
+                days: vec![Weekday::Fri],
+            },
+            "09:00",
+        );
+        schedule.holiday_calendar_id = Some("holidays".into());
+
+        let mut next_for = |rule| {
+            schedule.holiday_rule = rule;
+            next_occurrence_with_holidays(&schedule, &holidays, GracePolicy::default(), reference, None)
+                .unwrap()
+                .unwrap()
+                .fire_at
+                .date_naive()
+        };
+
+        assert_eq!(
+            next_for(HolidayRule::Skip),
+            NaiveDate::from_ymd_opt(2025, 11, 21).unwrap()
+        );
+        assert_eq!(
+            next_for(HolidayRule::PreviousBusinessDay),
+            NaiveDate::from_ymd_opt(2025, 11, 13).unwrap()
+        );
+        assert_eq!(
+            next_for(HolidayRule::NextBusinessDay),
+            NaiveDate::from_ymd_opt(2025, 11, 17).unwrap()
+        );
+    }
+
+    #[test]
+    fn weekdays_skip_holidays_without_firing_twice() {
+        // Thursday after the run; Friday is a holiday shifted back onto
+        // Thursday, which has already passed.
+        let reference = Local.with_ymd_and_hms(2025, 11, 13, 9, 0, 30).unwrap();
+        let holidays = BTreeSet::from([NaiveDate::from_ymd_opt(2025, 11, 14).unwrap()]);
+        let mut schedule = schedule_with_repeat(RepeatType::Weekdays, "09:00");
+        schedule.holiday_rule = HolidayRule::PreviousBusinessDay;
+
+        let next = next_occurrence_with_holidays(
+            &schedule,
+            &holidays,
+            GracePolicy::default(),
+            reference,
+            Some(reference),
+        )
+        .unwrap()
+        .unwrap()
+        .fire_at;
+
+        assert_eq!(next.date_naive(), NaiveDate::from_ymd_opt(2025, 11, 17).unwrap());
+    }
+
+    #[test]
+    fn late_tolerance_is_configurable_per_schedule() {
+        // Woke up ten minutes after a 07:00 alarm.
+        let reference = Local.with_ymd_and_hms(2025, 11, 14, 7, 10, 0).unwrap();
+        let mut schedule = schedule_with_repeat(RepeatType::Daily, "07:00");
+
+        let next = next_execution_time(&schedule, GracePolicy::default(), reference, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(next.date_naive(), NaiveDate::from_ymd_opt(2025, 11, 15).unwrap());
+
+        let settings = SettingsSnapshot {
+            late_tolerance_seconds: 15 * 60,
+            ..SettingsSnapshot::default()
+        };
+        let next = next_execution_time(&schedule, GracePolicy::from_settings(&settings), reference, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(next, reference);
+
+        schedule.late_tolerance_seconds = Some(15 * 60);
+        let next = next_execution_time(&schedule, GracePolicy::default(), reference, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(next, reference);
+
+        // A chime with a 30 second tolerance is dropped even if the global
+        // default would allow it.
+        let chime = schedule_with_repeat(RepeatType::Daily, "07:09");
+        let defaults = GracePolicy::from_seconds(120, 60);
+        let grace = GracePolicy::for_schedule(
+            &Schedule {
+                late_tolerance_seconds: Some(30),
+                ..chime.clone()
+            },
+            defaults,
+        );
+        let next = next_occurrence_with_holidays(&chime, &BTreeSet::new(), grace, reference, None)
+            .unwrap()
+            .unwrap();
+        assert!(next.fire_at > reference);
+
+        let grace = GracePolicy::for_schedule(&chime, defaults);
+        let next = next_occurrence_with_holidays(&chime, &BTreeSet::new(), grace, reference, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(next.fire_at, reference);
+        assert_eq!(next.scheduled_for.format("%H:%M").to_string(), "07:09");
+    }
+
+    #[test]
+    fn min_spacing_blocks_late_runs_soon_after_the_previous_one() {
+        let reference = Local.with_ymd_and_hms(2025, 11, 14, 9, 0, 40).unwrap();
+        let last_run = Some(Local.with_ymd_and_hms(2025, 11, 14, 8, 59, 50).unwrap());
+        let mut schedule = schedule_with_repeat(
+            RepeatType::Custom {
+                interval_minutes: 30,
+                until: None,
+            },
+            "08:00",
+        );
+
+        let next = next_execution_time(&schedule, GracePolicy::default(), reference, last_run)
+            .unwrap()
+            .unwrap();
+        assert_eq!(next.format("%H:%M").to_string(), "09:30");
+
+        schedule.min_spacing_seconds = Some(10);
+        let next = next_execution_time(&schedule, GracePolicy::default(), reference, last_run)
+            .unwrap()
+            .unwrap();
+        assert_eq!(next, reference);
+    }
+
+    mod dst {
+        use super::*;
+        use chrono_tz::America::New_York;
+        use chrono_tz::Australia::Sydney;
+        use chrono_tz::Europe::Berlin;
+        use chrono_tz::Tz;
+
+        fn schedule_with_policy(
+            repeat: RepeatType,
+            time: &str,
+            fall_back: FallBackPolicy,
+            spring_forward: SpringForwardPolicy,
+        ) -> Schedule {
+            Schedule {
+                dst_policy: DstPolicy {
+                    fall_back,
+                    spring_forward,
+                },
+                ..schedule_with_repeat(repeat, time)
+            }
+        }
+
+        fn next_in(schedule: &Schedule, reference: DateTime<Tz>) -> DateTime<Tz> {
+            next_occurrence(schedule, GracePolicy::default(), reference, None)
+                .unwrap()
+                .unwrap()
+                .fire_at
+        }
+
+        fn at(zone: Tz, y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Tz> {
+            zone.with_ymd_and_hms(y, m, d, h, min, 0).earliest().unwrap()
+        }
+
+        /// (zone, day clocks spring forward, day clocks fall back, a wall
+        /// time inside both transitions)
+        fn transitions() -> Vec<(Tz, NaiveDate, NaiveDate, &'static str)> {
+            let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+            vec![
+                (New_York, date(2025, 3, 9), date(2025, 11, 2), "01:30"),
+                (Berlin, date(2025, 3, 30), date(2025, 10, 26), "02:30"),
+                (Sydney, date(2025, 10, 5), date(2025, 4, 6), "02:30"),
+            ]
+        }
+
+        #[test]
+        fn spring_forward_shifts_or_skips() {
+            for (zone, spring, _, _) in transitions() {
+                let reference = zone
+                    .from_local_datetime(&spring.pred_opt().unwrap().and_hms_opt(12, 0, 0).unwrap())
+                    .unwrap();
+
+                let shifted = schedule_with_policy(
+                    RepeatType::Daily,
+                    "02:30",
+                    FallBackPolicy::First,
+                    SpringForwardPolicy::ShiftForward,
+                );
+                let next = next_in(&shifted, reference);
+                assert_eq!(next.date_naive(), spring, "{zone}");
+                assert_eq!(next.format("%H:%M").to_string(), "03:00", "{zone}");
+
+                let skipped = Schedule {
+                    dst_policy: DstPolicy {
+                        spring_forward: SpringForwardPolicy::Skip,
+                        ..shifted.dst_policy
+                    },
+                    ..shifted
+                };
+                let next = next_in(&skipped, reference);
+                assert_eq!(next.date_naive(), spring.succ_opt().unwrap(), "{zone}");
+                assert_eq!(next.format("%H:%M").to_string(), "02:30", "{zone}");
+            }
+        }
+
+        #[test]
+        fn fall_back_fires_first_second_or_both() {
+            for (zone, _, fall, time) in transitions() {
+                let reference = zone
+                    .from_local_datetime(&fall.pred_opt().unwrap().and_hms_opt(12, 0, 0).unwrap())
+                    .unwrap();
+                let schedule = |fall_back| {
+                    schedule_with_policy(
+                        RepeatType::Daily,
+                        time,
+                        fall_back,
+                        SpringForwardPolicy::ShiftForward,
+                    )
+                };
+
+                let first = next_in(&schedule(FallBackPolicy::First), reference);
+                let second = next_in(&schedule(FallBackPolicy::Second), reference);
+                assert_eq!(first.date_naive(), fall, "{zone}");
+                assert_eq!(second.naive_local(), first.naive_local(), "{zone}");
+                assert_eq!(second - first, Duration::hours(1), "{zone}");
+
+                // After the first firing only `Both` returns for the repeat.
+                let both = schedule(FallBackPolicy::Both);
+                let after_first = first + Duration::seconds(1);
+                let next = next_occurrence(
+                    &both,
+                    GracePolicy::default(),
+                    after_first,
+                    Some(after_first),
+                )
+                .unwrap()
+                .unwrap()
+                .fire_at;
+                assert_eq!(next, second, "{zone}");
+
+                let next = next_occurrence(
+                    &schedule(FallBackPolicy::First),
+                    GracePolicy::default(),
+                    after_first,
+                    Some(after_first),
+                )
+                .unwrap()
+                .unwrap()
+                .fire_at;
+                assert_eq!(next.date_naive(), fall.succ_opt().unwrap(), "{zone}");
+            }
+        }
+
+        #[test]
+        fn intervals_measure_real_elapsed_time() {
+            let hourly = schedule_with_repeat(
+                RepeatType::Custom {
+                    interval_minutes: 60,
+                    until: None,
+                },
+                "00:00",
+            );
+
+            // Fall back: 01:00 comes round again an hour later.
+            let reference = at(New_York, 2025, 11, 2, 1, 10);
+            let next = next_in(&hourly, reference);
+            assert_eq!(next - reference, Duration::minutes(50));
+            assert_eq!(next.format("%H:%M").to_string(), "01:00");
+
+            // Spring forward: the hour after 01:00 is 03:00 on the wall clock.
+            let reference = at(New_York, 2025, 3, 9, 1, 30);
+            let next = next_in(&hourly, reference);
+            assert_eq!(next - reference, Duration::minutes(30));
+            assert_eq!(next.format("%H:%M").to_string(), "03:00");
+
+            let reference = at(Berlin, 2025, 10, 26, 2, 40);
+            let next = next_in(&hourly, reference);
+            assert_eq!(next - reference, Duration::minutes(20));
+        }
+    }
 }
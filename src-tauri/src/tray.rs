@@ -1,10 +1,18 @@
-use crate::scheduler::SchedulerEngine;
+use crate::db::models::SettingsSnapshot;
+use crate::scheduler::describe::{describe_next_run, Language};
+use crate::AppState;
+use crate::scheduler::{SchedulerEngine, UpcomingKind};
+use chrono::Local;
+use std::time::Duration;
 use tauri::{
     menu::{Menu, MenuItem, PredefinedMenuItem},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
     AppHandle, Manager, Runtime, WebviewWindow,
 };
 
+const TRAY_ID: &str = "main";
+const TOOLTIP_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
 /// Build and configure the system tray
 pub fn create_tray<R: Runtime>(app: &AppHandle<R>) -> tauri::Result<()> {
     // Create menu items
@@ -29,7 +37,7 @@ pub fn create_tray<R: Runtime>(app: &AppHandle<R>) -> tauri::Result<()> {
     )?;
 
     // Create the tray icon
-    let _tray = TrayIconBuilder::new()
+    let _tray = TrayIconBuilder::with_id(TRAY_ID)
         .icon(app.default_window_icon().unwrap().clone())
         .menu(&menu)
         .tooltip("Resonatify - No schedules active")
@@ -101,13 +109,38 @@ pub fn setup_window_close_handler(window: WebviewWindow) {
     });
 }
 
-/// Background task to update tray tooltip with upcoming schedules
-/// Note: Dynamic tooltip updates will be implemented in a future iteration
-pub async fn start_tray_tooltip_updater<R: Runtime>(
-    _app: AppHandle<R>,
-    _scheduler: SchedulerEngine,
-) {
-    // TODO: Implement dynamic tooltip updates
-    // This requires storing a reference to the tray icon or using Tauri's event system
-    // For now, the tooltip is set once during tray creation
+/// Background task to update tray tooltip with the next scheduled alarm and
+/// a warning while there is no audio output
+pub async fn start_tray_tooltip_updater<R: Runtime>(app: AppHandle<R>, scheduler: SchedulerEngine) {
+    loop {
+        let settings = app
+            .state::<AppState>()
+            .database
+            .settings_repository()
+            .get_all()
+            .await
+            .map(SettingsSnapshot::from)
+            .unwrap_or_default();
+        let language = Language::from_code(&settings.language);
+        let upcoming = scheduler.upcoming_executions(usize::MAX).await;
+        let tooltip = match upcoming.iter().find(|entry| entry.kind == UpcomingKind::Alarm) {
+            Some(next) => format!(
+                "Resonatify - {} ({})",
+                describe_next_run(Some(next.scheduled_for), Local::now(), language),
+                next.name
+            ),
+            None => "Resonatify - No schedules active".to_string(),
+        };
+        let output = scheduler.status().await.audio_output;
+        let tooltip = match output.filter(|output| !output.available) {
+            Some(_) => format!("{tooltip}\n⚠ No audio output - alarms will not sound"),
+            None => tooltip,
+        };
+
+        if let Some(tray) = app.tray_by_id(TRAY_ID) {
+            let _ = tray.set_tooltip(Some(tooltip));
+        }
+
+        tokio::time::sleep(TOOLTIP_REFRESH_INTERVAL).await;
+    }
 }
@@ -82,6 +82,14 @@ async fn test_scheduler_flow_daily_repeat() {
             repeat_type: RepeatType::Daily,
             volume: 50,
             last_run_at: None,
+            pre_alerts: Vec::new(),
+            holiday_calendar_id: None,
+            holiday_rule: Default::default(),
+            late_tolerance_seconds: None,
+            min_spacing_seconds: None,
+            dst_policy: Default::default(),
+            audio_source: Default::default(),
+            playback: Default::default(),
         })
         .await
         .expect("Failed to create schedule");